@@ -0,0 +1,97 @@
+//! Throughput of stepping an external solver one call per step versus in batches.
+//!
+//! Plugin is taken from `SOLVER_PLUGIN` or `solvers/cmake-build-debug`, so it should be built first:
+//! `SOLVER_PLUGIN=path/to/librunge-kutta.so cargo bench --bench batch`
+
+use libloading::library_filename;
+use project::ffi::ExternalSolver;
+use project::plugin::{Parameters, Plugin};
+use project::solution::{Solution, StopCondition};
+use project::solver::{EulerSolver, Solver};
+use project::task::{f, CauchyTask};
+use project::Frozen;
+use std::env;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const STEP: f64 = 1e-4;
+const T_MAX: f64 = 100.0;
+const REPEATS: usize = 5;
+
+fn task() -> CauchyTask<f64, f64> {
+    CauchyTask::new(
+        [
+            f(|_, &[x1, _, _]| -0.577 * x1),
+            f(|_, &[x1, x2, _]| 0.577 * x1 - 0.422 * x2),
+            f(|_, &[_, x2, _]| 0.422 * x2),
+        ],
+        0.0,
+        [1.0, 0.0, 0.0],
+    )
+}
+
+/// Old way of computing: one call and one boxed copy per step
+fn per_step<S: Solver<f64, f64>>(solver: &mut Frozen<S>, task: &CauchyTask<f64, f64>) -> usize {
+    S::solve_task(solver.as_mut(), task)
+        .take_while(|it| it.as_ref().map_or(true, |(t, _)| *t <= T_MAX))
+        .map(|it| it.expect("Solver failed"))
+        .collect::<Vec<_>>()
+        .len()
+}
+
+fn batched<S: Solver<f64, f64>>(solver: &mut Frozen<S>, task: &CauchyTask<f64, f64>) -> usize {
+    let stop = StopCondition::Timed { maximum: T_MAX };
+    Solution::compute(solver.as_mut(), task, stop)
+        .expect("Solver failed")
+        .time()
+        .len()
+}
+
+/// Best of [`REPEATS`] runs
+fn measure(mut run: impl FnMut() -> usize) -> (usize, Duration) {
+    (0..REPEATS)
+        .map(|_| {
+            let start = Instant::now();
+            let points = black_box(run());
+            (points, start.elapsed())
+        })
+        .min_by_key(|(_, elapsed)| *elapsed)
+        .unwrap()
+}
+
+fn report<S: Solver<f64, f64>>(name: &str, solver: &mut Frozen<S>) {
+    let task = task();
+    let (points, single) = measure(|| per_step(solver, &task));
+    let (batch_points, batch) = measure(|| batched(solver, &task));
+    assert_eq!(points, batch_points, "Both ways should compute the same points");
+
+    let rate = |elapsed: Duration| points as f64 / elapsed.as_secs_f64() / 1e6;
+    println!(
+        "{name:<12} {points} steps: per step {single:>10.2?} ({:.2} M/s), batched {batch:>10.2?} ({:.2} M/s), x{:.2}",
+        rate(single),
+        rate(batch),
+        single.as_secs_f64() / batch.as_secs_f64()
+    );
+}
+
+fn main() {
+    let path = env::var_os("SOLVER_PLUGIN").map(PathBuf::from).unwrap_or_else(|| {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("solvers/cmake-build-debug")
+            .join(library_filename("runge-kutta"))
+    });
+    let parameters = Parameters {
+        step: STEP,
+        ..Default::default()
+    };
+
+    report("builtin", &mut EulerSolver::new(STEP));
+    match unsafe { Plugin::load(&path) } {
+        Ok(plugin) => {
+            let mut solver = ExternalSolver::build(&plugin, &parameters).expect("Cannot build solver");
+            report(&plugin.metadata().name, &mut solver);
+        }
+        Err(e) => println!("Skipping external solver: {e:#}"),
+    }
+}
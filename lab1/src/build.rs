@@ -0,0 +1,20 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Generates C header of the API from `src/capi.rs` into `OUT_DIR`, from where it is embedded
+/// as `capi::HEADER`. Committed `include/project.h` is updated with `project gen-header`.
+fn main() {
+    println!("cargo::rerun-if-changed=src/capi.rs");
+    println!("cargo::rerun-if-changed=cbindgen.toml");
+
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("cbindgen.toml should be valid");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/capi.rs"))
+        .generate()
+        .expect("C API should be representable in C")
+        .write_to_file(out_dir.join("project.h"));
+}
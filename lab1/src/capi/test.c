@@ -0,0 +1,115 @@
+/* Checks the C API of the crate against closed-form Euler method iterations */
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "project.h"
+
+static int failures = 0;
+
+#define CHECK(condition)                                                     \
+    do {                                                                     \
+        if (!(condition)) {                                                  \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #condition);                                             \
+            ++failures;                                                      \
+        }                                                                    \
+    } while (0)
+
+/* x' = -rate * x */
+static double decay(void *user_data, double time, const double *inputs) {
+    (void) time;
+    return -*(const double *) user_data * inputs[0];
+}
+
+/* x' = y, y' = -x */
+static void oscillator(void *user_data, double time, const double *inputs, double *outputs) {
+    (void) user_data;
+    (void) time;
+    outputs[0] = inputs[1];
+    outputs[1] = -inputs[0];
+}
+
+static void test_components(void) {
+    double rate = 2.0;
+    double initial = 1.0;
+    ProjectComponentFn derivatives[] = {decay};
+    ProjectTask *task = project_task_new(1, derivatives, &rate, 0.0, &initial);
+    ProjectSolver *solver = project_solver_new_euler(0.01);
+    CHECK(task != NULL && solver != NULL);
+    CHECK(project_task_size(task) == 1);
+
+    CHECK(project_solver_prepare(solver, task) == PROJECT_OK);
+    double expected = initial;
+    for (int i = 1; i <= 10; ++i) {
+        double time;
+        double state;
+        CHECK(project_solver_step(solver, task, &time, &state) == PROJECT_OK);
+        expected *= 1.0 - rate * 0.01;
+        CHECK(fabs(time - 0.01 * i) < 1e-12);
+        CHECK(fabs(state - expected) < 1e-12);
+    }
+
+    project_solver_free(solver);
+    project_task_free(task);
+}
+
+static void test_system(void) {
+    double initial[] = {1.0, 0.0};
+    ProjectTask *task = project_task_new_system(2, oscillator, NULL, 0.0, initial);
+    ProjectSolver *solver = project_solver_new_euler(0.001);
+    CHECK(task != NULL && solver != NULL);
+
+    ProjectSolution *solution = project_solve(solver, task, 1.0);
+    CHECK(solution != NULL);
+    size_t points = project_solution_points(solution);
+    /* The last step may overshoot 1.0 because of rounding */
+    CHECK(points == 1000 || points == 1001);
+    CHECK(project_solution_components(solution) == 2);
+
+    const double *time = project_solution_time(solution);
+    const double *x = project_solution_component(solution, 0);
+    const double *y = project_solution_component(solution, 1);
+    CHECK(project_solution_component(solution, 2) == NULL);
+    CHECK(time[0] == 0.0 && x[0] == 1.0 && y[0] == 0.0);
+    CHECK(fabs(x[points - 1] - cos(time[points - 1])) < 1e-2);
+    CHECK(fabs(y[points - 1] + sin(time[points - 1])) < 1e-2);
+
+    project_solution_free(solution);
+    project_solver_free(solver);
+    project_task_free(task);
+}
+
+static void test_errors(void) {
+    CHECK(project_solver_new_euler(-1.0) == NULL);
+    CHECK(project_last_error() != NULL);
+
+    double initial = 1.0;
+    ProjectComponentFn missing[] = {NULL};
+    CHECK(project_task_new(1, missing, NULL, 0.0, &initial) == NULL);
+    CHECK(project_task_new_system(1, NULL, NULL, 0.0, &initial) == NULL);
+
+    double rate = 1.0;
+    ProjectComponentFn derivatives[] = {decay};
+    ProjectTask *task = project_task_new(1, derivatives, &rate, 0.0, &initial);
+    ProjectSolver *solver = project_solver_new_euler(0.1);
+    double time;
+    double state;
+    CHECK(project_solver_step(solver, task, &time, &state) == PROJECT_ERROR);
+    printf("expected error: %s\n", project_last_error());
+
+    project_solver_free(solver);
+    project_task_free(task);
+}
+
+int main(void) {
+    test_components();
+    test_system();
+    test_errors();
+    if (failures != 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return EXIT_FAILURE;
+    }
+    printf("all checks passed\n");
+    return EXIT_SUCCESS;
+}
@@ -0,0 +1,429 @@
+//! Solver plugin written in Rust, which implements the ABI of `solvers/include/solver.h` with
+//! Euler method. Integration tests load it to exercise `ExternalSolver` without a C++ toolchain.
+//!
+//! Besides `step` it accepts option `mode`, which makes it misbehave on purpose:
+//! `fail` fails to prepare, `null` returns no solution from `solver_eval_next_*` and `overflow`
+//! reports more points from `solver_eval_batch_*` than requested. Modes `crash` and `hang` print
+//! a line to stderr on the first step, then abort the process or never return, so they are only
+//! usable in the sandbox.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::mem::{align_of, offset_of, size_of};
+use std::ops::Add;
+use std::ptr;
+use std::sync::OnceLock;
+
+const ABI_VERSION: u32 = 7;
+const SOLVER_OK: i32 = 0;
+const SOLVER_ERROR: i32 = 1;
+
+#[repr(C)]
+struct Function<T, N> {
+    state_pointer: *mut c_void,
+    fn_pointer: extern "C" fn(*const c_void, T, *const N) -> N,
+    destructor: extern "C" fn(*mut c_void),
+}
+
+#[repr(C)]
+struct VectorFunction<T, N> {
+    state_pointer: *mut c_void,
+    fn_pointer: extern "C" fn(*const c_void, T, *const N, *mut N),
+    destructor: extern "C" fn(*mut c_void),
+}
+
+#[repr(C)]
+struct CauchyTask<T, N> {
+    size: usize,
+    derivatives: *const Function<T, N>,
+    system: *const VectorFunction<T, N>,
+    initial_conditions: *const N,
+    initial_time: T,
+}
+
+#[repr(C)]
+struct SolverOption {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+struct SolverParameters {
+    step: f64,
+    absolute_tolerance: f64,
+    relative_tolerance: f64,
+    max_iterations: usize,
+    options: *const SolverOption,
+    options_count: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Interval<T> {
+    start: T,
+    end: T,
+}
+
+#[repr(C)]
+struct SolverMetadata {
+    abi_version: u32,
+    name: *const c_char,
+    order: u32,
+    implicit: bool,
+    suffixes: *const *const c_char,
+    parameters: *const *const c_char,
+}
+
+#[repr(C)]
+struct SolverLayoutEntry {
+    name: *const c_char,
+    value: usize,
+}
+
+/// Static data with pointers to other static data
+struct Shared<T>(T);
+
+unsafe impl<T> Sync for Shared<T> {}
+unsafe impl<T> Send for Shared<T> {}
+
+trait Time: Copy + PartialOrd + Add<Output = Self> {
+    fn from_f64(value: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+}
+
+impl Time for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Time for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+trait Number: Copy {
+    /// `self + h * slope` for a positive `h`
+    fn advance(self, h: f64, slope: Self) -> Self;
+
+    fn is_nan(self) -> bool;
+}
+
+impl Number for f32 {
+    fn advance(self, h: f64, slope: Self) -> Self {
+        self + h as f32 * slope
+    }
+
+    fn is_nan(self) -> bool {
+        f32::is_nan(self)
+    }
+}
+
+impl Number for f64 {
+    fn advance(self, h: f64, slope: Self) -> Self {
+        self + h * slope
+    }
+
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+}
+
+impl<T: Number> Number for Interval<T> {
+    fn advance(self, h: f64, slope: Self) -> Self {
+        Interval {
+            start: self.start.advance(h, slope.start),
+            end: self.end.advance(h, slope.end),
+        }
+    }
+
+    fn is_nan(self) -> bool {
+        self.start.is_nan() || self.end.is_nan()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Normal,
+    Null,
+    Overflow,
+    Crash,
+    Hang,
+}
+
+struct Euler<T, N> {
+    step: T,
+    time: T,
+    state: Vec<N>,
+    slopes: Vec<N>,
+    mode: Mode,
+}
+
+impl<T: Time, N: Number> Euler<T, N> {
+    unsafe fn prepare(&mut self, task: &CauchyTask<T, N>, parameters: &SolverParameters) -> Result<(), String> {
+        self.mode = Mode::Normal;
+        for i in 0..parameters.options_count {
+            let option = &*parameters.options.add(i);
+            if CStr::from_ptr(option.key) != c"mode" {
+                continue;
+            }
+            self.mode = match CStr::from_ptr(option.value).to_bytes() {
+                b"fail" => return Err("failure was requested".to_string()),
+                b"null" => Mode::Null,
+                b"overflow" => Mode::Overflow,
+                b"crash" => Mode::Crash,
+                b"hang" => Mode::Hang,
+                other => return Err(format!("unknown mode `{}`", String::from_utf8_lossy(other))),
+            };
+        }
+        if !(parameters.step > 0.0 && parameters.step.is_finite()) {
+            return Err(format!("step {} is not a positive finite value", parameters.step));
+        }
+        self.step = T::from_f64(parameters.step);
+        self.time = task.initial_time;
+        self.state = slice(task.initial_conditions, task.size).to_vec();
+        Ok(())
+    }
+
+    unsafe fn next(&mut self, task: &CauchyTask<T, N>) -> Result<(T, &[N]), String> {
+        match self.mode {
+            Mode::Crash => {
+                eprintln!("reference plugin crashes on purpose");
+                std::process::abort()
+            }
+            Mode::Hang => {
+                eprintln!("reference plugin hangs on purpose");
+                loop {
+                    std::thread::park()
+                }
+            }
+            _ => {}
+        }
+        self.slopes.clone_from(&self.state);
+        if task.system.is_null() {
+            let derivatives = slice(task.derivatives, task.size);
+            for (slope, f) in self.slopes.iter_mut().zip(derivatives) {
+                *slope = (f.fn_pointer)(f.state_pointer, self.time, self.state.as_ptr());
+            }
+        } else {
+            let f = &*task.system;
+            (f.fn_pointer)(f.state_pointer, self.time, self.state.as_ptr(), self.slopes.as_mut_ptr());
+        }
+
+        let h = self.step.to_f64();
+        for (i, (x, k)) in self.state.iter_mut().zip(&self.slopes).enumerate() {
+            *x = x.advance(h, *k);
+            if x.is_nan() {
+                return Err(format!("component {} is NaN at t = {}", i + 1, self.time.to_f64()));
+            }
+        }
+        self.time = self.time + self.step;
+        Ok((self.time, &self.state))
+    }
+}
+
+unsafe fn slice<'a, T>(values: *const T, size: usize) -> &'a [T] {
+    if size == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(values, size)
+    }
+}
+
+/// Translates result into a status, writing the message of the error into `error`
+unsafe fn status(result: Result<(), String>, error: *mut c_char, capacity: usize) -> i32 {
+    match result {
+        Ok(()) => SOLVER_OK,
+        Err(message) => {
+            if !error.is_null() && capacity > 0 {
+                let length = message.len().min(capacity - 1);
+                ptr::copy_nonoverlapping(message.as_ptr().cast(), error, length);
+                *error.add(length) = 0;
+            }
+            SOLVER_ERROR
+        }
+    }
+}
+
+/// Adds size, alignment and field offsets of `$ty` to `$entries`, named as `SolverLayout` does
+macro_rules! describe {
+    ($entries:expr, $name:literal, $ty:ty { $($field:ident),* }) => {
+        $entries.push(entry(concat!("sizeof(", $name, ")\0"), size_of::<$ty>()));
+        $entries.push(entry(concat!("alignof(", $name, ")\0"), align_of::<$ty>()));
+        $(
+            $entries.push(entry(
+                concat!("offsetof(", $name, ", ", stringify!($field), ")\0"),
+                offset_of!($ty, $field),
+            ));
+        )*
+    };
+}
+
+fn entry(name: &'static str, value: usize) -> SolverLayoutEntry {
+    SolverLayoutEntry { name: name.as_ptr().cast(), value }
+}
+
+fn layout<T, N>() -> Vec<SolverLayoutEntry> {
+    let mut entries = vec![];
+    describe!(entries, "T", T {});
+    describe!(entries, "N", N {});
+    describe!(entries, "Interval<float>", Interval<f32> { start, end });
+    describe!(entries, "Interval<double>", Interval<f64> { start, end });
+    describe!(entries, "Function", Function<T, N> { state_pointer, fn_pointer, destructor });
+    describe!(entries, "VectorFunction", VectorFunction<T, N> { state_pointer, fn_pointer, destructor });
+    describe!(entries, "SolverParameters", SolverParameters {
+        step, absolute_tolerance, relative_tolerance, max_iterations, options, options_count
+    });
+    describe!(entries, "CauchyTask", CauchyTask<T, N> {
+        size, derivatives, system, initial_conditions, initial_time
+    });
+    entries
+}
+
+/// Exports every `solver_*` symbol for `($t, $n)`, name of the module is the suffix
+macro_rules! export {
+    ($($module:ident: $t:ty, $n:ty;)*) => {$(
+        #[allow(non_snake_case)]
+        mod $module {
+            use super::*;
+
+            type Solver = Euler<$t, $n>;
+
+            #[export_name = concat!("solver_layout_", stringify!($module))]
+            extern "C" fn layout(out_count: *mut usize) -> *const SolverLayoutEntry {
+                static LAYOUT: OnceLock<Shared<Vec<SolverLayoutEntry>>> = OnceLock::new();
+                let entries = &LAYOUT.get_or_init(|| Shared(super::layout::<$t, $n>())).0;
+                unsafe { *out_count = entries.len() };
+                entries.as_ptr()
+            }
+
+            #[export_name = concat!("solver_create_", stringify!($module))]
+            extern "C" fn create() -> *mut Solver {
+                Box::into_raw(Box::new(Euler {
+                    step: <$t>::from_f64(0.0),
+                    time: <$t>::from_f64(0.0),
+                    state: vec![],
+                    slopes: vec![],
+                    mode: Mode::Normal,
+                }))
+            }
+
+            #[export_name = concat!("solver_destroy_", stringify!($module))]
+            unsafe extern "C" fn destroy(solver: *mut Solver) {
+                drop(Box::from_raw(solver))
+            }
+
+            #[export_name = concat!("solver_prepare_", stringify!($module))]
+            unsafe extern "C" fn prepare(
+                solver: *mut Solver,
+                task: CauchyTask<$t, $n>,
+                parameters: *const SolverParameters,
+                error: *mut c_char,
+                capacity: usize,
+            ) -> i32 {
+                status((*solver).prepare(&task, &*parameters), error, capacity)
+            }
+
+            #[export_name = concat!("solver_eval_next_", stringify!($module))]
+            unsafe extern "C" fn eval_next(
+                solver: *mut Solver,
+                task: CauchyTask<$t, $n>,
+                out_time: *mut $t,
+                out_solution: *mut *const $n,
+                error: *mut c_char,
+                capacity: usize,
+            ) -> i32 {
+                let solver = &mut *solver;
+                let mode = solver.mode;
+                let result = solver.next(&task).map(|(time, state)| {
+                    *out_time = time;
+                    *out_solution = if mode == Mode::Null { ptr::null() } else { state.as_ptr() };
+                });
+                status(result, error, capacity)
+            }
+
+            #[export_name = concat!("solver_eval_batch_", stringify!($module))]
+            unsafe extern "C" fn eval_batch(
+                solver: *mut Solver,
+                task: CauchyTask<$t, $n>,
+                max_steps: usize,
+                until: $t,
+                out_times: *mut $t,
+                out_states: *mut $n,
+                out_count: *mut usize,
+                error: *mut c_char,
+                capacity: usize,
+            ) -> i32 {
+                let solver = &mut *solver;
+                *out_count = 0;
+                let mut result = Ok(());
+                for k in 0..max_steps {
+                    match solver.next(&task) {
+                        Ok((time, _)) if time > until => break,
+                        Ok((time, state)) => {
+                            *out_times.add(k) = time;
+                            ptr::copy_nonoverlapping(state.as_ptr(), out_states.add(k * task.size), task.size);
+                            *out_count += 1;
+                        }
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                }
+                if solver.mode == Mode::Overflow {
+                    *out_count = max_steps + 1;
+                }
+                status(result, error, capacity)
+            }
+        }
+    )*};
+}
+
+export! {
+    f64_f64: f64, f64;
+    f32_f32: f32, f32;
+    f64_f32: f64, f32;
+    f32_f64: f32, f64;
+    f64_If64: f64, Interval<f64>;
+    f32_If64: f32, Interval<f64>;
+    f64_If32: f64, Interval<f32>;
+    f32_If32: f32, Interval<f32>;
+}
+
+static SUFFIXES: Shared<[*const c_char; 9]> = Shared([
+    c"f64_f64".as_ptr(),
+    c"f32_f32".as_ptr(),
+    c"f64_f32".as_ptr(),
+    c"f32_f64".as_ptr(),
+    c"f64_If64".as_ptr(),
+    c"f32_If64".as_ptr(),
+    c"f64_If32".as_ptr(),
+    c"f32_If32".as_ptr(),
+    ptr::null(),
+]);
+
+static PARAMETERS: Shared<[*const c_char; 3]> = Shared([c"step".as_ptr(), c"mode".as_ptr(), ptr::null()]);
+
+static METADATA: Shared<SolverMetadata> = Shared(SolverMetadata {
+    abi_version: ABI_VERSION,
+    name: c"reference".as_ptr(),
+    order: 1,
+    implicit: false,
+    suffixes: SUFFIXES.0.as_ptr(),
+    parameters: PARAMETERS.0.as_ptr(),
+});
+
+#[no_mangle]
+extern "C" fn solver_metadata() -> *const SolverMetadata {
+    &METADATA.0
+}
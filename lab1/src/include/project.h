@@ -0,0 +1,135 @@
+#ifndef PROJECT_H
+#define PROJECT_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Status of a successful call
+#define PROJECT_OK 0
+
+// Status of a failed call, see [`project_last_error`]
+#define PROJECT_ERROR 1
+
+// Solution computed by [`project_solve`]
+typedef struct ProjectSolution ProjectSolution;
+
+// Builtin solver over `double`, created by `project_solver_new_*`
+typedef struct ProjectSolver ProjectSolver;
+
+// Cauchy task over `double`, created by [`project_task_new`] or [`project_task_new_system`]
+typedef struct ProjectTask ProjectTask;
+
+// Derivative of a single component at `time`, `inputs` has a value for every component
+typedef double (*ProjectComponentFn)(void *user_data, double time, const double *inputs);
+
+// Derivatives of the whole system at `time`, written into `outputs` of the same size as `inputs`
+typedef void (*ProjectSystemFn)(void *user_data, double time, const double *inputs, double *outputs);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Message of the last error on this thread or null if there was none.
+// It stays valid until the next failed call on this thread.
+const char *project_last_error(void);
+
+// Creates a task with a function per component, `derivatives` and `initial_conditions` have `size` elements.
+// `user_data` is passed to every function as is and must outlive the task.
+//
+// # Safety
+// Pointers must be valid for `size` elements.
+struct ProjectTask *project_task_new(size_t size,
+                                     const ProjectComponentFn *derivatives,
+                                     void *user_data,
+                                     double initial_time,
+                                     const double *initial_conditions);
+
+// Creates a task with a single function for the whole system, `initial_conditions` has `size` elements.
+// `user_data` is passed to the function as is and must outlive the task.
+//
+// # Safety
+// `initial_conditions` must be valid for `size` elements.
+struct ProjectTask *project_task_new_system(size_t size,
+                                            ProjectSystemFn system,
+                                            void *user_data,
+                                            double initial_time,
+                                            const double *initial_conditions);
+
+// Amount of components of the task
+//
+// # Safety
+// `task` must be created by `project_task_new*`.
+size_t project_task_size(const struct ProjectTask *task);
+
+// # Safety
+// `task` must be created by `project_task_new*` or be null, no solver may use it afterwards.
+void project_task_free(struct ProjectTask *task);
+
+// Creates builtin Euler method solver with a fixed positive `step`
+struct ProjectSolver *project_solver_new_euler(double step);
+
+// # Safety
+// `solver` must be created by `project_solver_new_*` or be null.
+void project_solver_free(struct ProjectSolver *solver);
+
+// Resets the solver to the initial conditions of `task`, which is required before [`project_solver_step`]
+//
+// # Safety
+// Pointers must be created by the corresponding constructors.
+int32_t project_solver_prepare(struct ProjectSolver *solver,
+                               const struct ProjectTask *task);
+
+// Does a single step, writing the reached time into `out_time` and the state into `out_state`,
+// which must have room for [`project_task_size`] values
+//
+// # Safety
+// Pointers must be created by the corresponding constructors, outputs must be valid for writes.
+int32_t project_solver_step(struct ProjectSolver *solver,
+                            const struct ProjectTask *task,
+                            double *out_time,
+                            double *out_state);
+
+// Solves `task` from its initial conditions until `maximum` time
+//
+// # Safety
+// Pointers must be created by the corresponding constructors.
+struct ProjectSolution *project_solve(struct ProjectSolver *solver,
+                                      const struct ProjectTask *task,
+                                      double maximum);
+
+// Amount of computed points
+//
+// # Safety
+// `solution` must be created by [`project_solve`].
+size_t project_solution_points(const struct ProjectSolution *solution);
+
+// Amount of components of the solution
+//
+// # Safety
+// `solution` must be created by [`project_solve`].
+size_t project_solution_components(const struct ProjectSolution *solution);
+
+// Time of every point, [`project_solution_points`] values owned by the solution
+//
+// # Safety
+// `solution` must be created by [`project_solve`].
+const double *project_solution_time(const struct ProjectSolution *solution);
+
+// Values of `component` at every point, [`project_solution_points`] values owned by the solution.
+// Null if there is no such component.
+//
+// # Safety
+// `solution` must be created by [`project_solve`].
+const double *project_solution_component(const struct ProjectSolution *solution, size_t component);
+
+// # Safety
+// `solution` must be created by [`project_solve`] or be null.
+void project_solution_free(struct ProjectSolution *solution);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PROJECT_H */
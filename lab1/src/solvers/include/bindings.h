@@ -0,0 +1,25 @@
+// Generated by `project gen-bindings` from `bindings` in `src/ffi.rs`, do not edit
+#pragma once
+
+/// Exports all `solver_*` symbols for every supported pair of types, `solver_ty` is a class template
+#define gen_bindings(solver_ty) \
+    gen_binding(solver_ty, double, double, f64_f64) \
+    gen_binding(solver_ty, double, float, f64_f32) \
+    gen_binding(solver_ty, double, Interval<double>, f64_If64) \
+    gen_binding(solver_ty, double, Interval<float>, f64_If32) \
+    gen_binding(solver_ty, float, double, f32_f64) \
+    gen_binding(solver_ty, float, float, f32_f32) \
+    gen_binding(solver_ty, float, Interval<double>, f32_If64) \
+    gen_binding(solver_ty, float, Interval<float>, f32_If32) \
+
+inline constexpr const char *SOLVER_SUFFIXES[] = {
+    "f64_f64",
+    "f64_f32",
+    "f64_If64",
+    "f64_If32",
+    "f32_f64",
+    "f32_f32",
+    "f32_If64",
+    "f32_If32",
+    nullptr,
+};
@@ -1,6 +1,10 @@
 #pragma once
 #include <cstddef>
 
+/// Layout table of the types shared with the host, see `solver.h`
+template<typename T, typename N>
+struct SolverLayout;
+
 template<typename T, typename N>
 class Function {
     void *state_pointer;
@@ -9,17 +13,49 @@ class Function {
 
     void (*destructor)(void *);
 
+    friend struct SolverLayout<T, N>;
+
 public:
     N operator()(T time, const N *inputs) const {
         return fn_pointer(state_pointer, time, inputs);
     }
 };
 
+/// Right-hand side of the whole system, which writes every derivative into `outputs`
+template<typename T, typename N>
+class VectorFunction {
+    void *state_pointer;
+
+    void (*fn_pointer)(const void *, T, const N *, N *);
+
+    void (*destructor)(void *);
+
+    friend struct SolverLayout<T, N>;
+
+public:
+    void operator()(T time, const N *inputs, N *outputs) const {
+        fn_pointer(state_pointer, time, inputs, outputs);
+    }
+};
+
 template<typename T, typename N>
 struct CauchyTask {
     std::size_t size;
+    /// Function per component, null if the task is given by `system`
     const Function<T, N> *derivatives;
+    /// Function for the whole system, null if the task is given by `derivatives`
+    const VectorFunction<T, N> *system;
     const N *initial_conditions;
     T initial_time;
-};
 
+    /// Writes every derivative at (`time`, `inputs`) into `outputs`, regardless of the form of the task
+    void eval(T time, const N *inputs, N *outputs) const {
+        if (system != nullptr) {
+            (*system)(time, inputs, outputs);
+            return;
+        }
+        for (std::size_t i = 0; i < size; ++i) {
+            outputs[i] = derivatives[i](time, inputs);
+        }
+    }
+};
@@ -20,7 +20,7 @@ struct Interval {
     }
 
     Interval operator-(const Interval &other) const {
-        return Interval(start - other.start, end - other.end);
+        return Interval(start - other.end, end - other.start);
     }
 
     Interval operator*(const Interval &other) const {
@@ -29,10 +29,13 @@ struct Interval {
     }
 
     friend Interval operator*(T a, Interval i) {
-        return Interval(a * i.start, a * i.end);
+        return i * a;
     }
 
-    Interval operator*(T other) {
+    Interval operator*(T other) const {
+        if (other < 0) {
+            return Interval(end * other, start * other);
+        }
         return Interval(start * other, end * other);
     }
 
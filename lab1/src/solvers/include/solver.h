@@ -1,34 +1,274 @@
 #pragma once
 
-#include <memory>
+#include <algorithm>
+#include <cstddef>
+#include <cstdint>
+#include <cmath>
+#include <cstring>
+#include <exception>
+#include <iterator>
+#include <stdexcept>
+#include <string>
+#include <type_traits>
 
 #include "ffi.h"
 #include "interval.h"
 
+/// Must be bumped on every incompatible change of the exported symbols
+#define SOLVER_ABI_VERSION 7
+
+struct SolverOption {
+    const char *key;
+    const char *value;
+};
+
+/// Parameters configured by the host, solver uses those it lists in its metadata
+struct SolverParameters {
+    double step;
+    double absolute_tolerance;
+    double relative_tolerance;
+    std::size_t max_iterations;
+    const SolverOption *options;
+    std::size_t options_count;
+
+    /// Value of the solver-specific option or `nullptr` if it is not set
+    [[nodiscard]] const char *option(const char *key) const {
+        for (std::size_t i = 0; i < options_count; ++i) {
+            if (std::strcmp(options[i].key, key) == 0) {
+                return options[i].value;
+            }
+        }
+        return nullptr;
+    }
+
+    /// Step converted to the time type of the solver, which must stay positive and finite
+    template<typename T>
+    [[nodiscard]] T checked_step() const {
+        auto h = static_cast<T>(step);
+        if (!(h > 0) || !std::isfinite(h)) {
+            throw std::invalid_argument("step " + std::to_string(step) + " is not a positive finite value");
+        }
+        return h;
+    }
+};
+
+/// Throws if advancing `time` by `step` does not change it anymore
+template<typename T>
+void check_step(T time, T step) {
+    if (time + step == time) {
+        throw std::underflow_error("step size underflow at t = " + std::to_string(time));
+    }
+}
+
+template<typename T>
+bool is_nan(T value) {
+    return std::isnan(value);
+}
+
+template<typename T>
+bool is_nan(Interval<T> value) {
+    return std::isnan(value.start) || std::isnan(value.end);
+}
+
+/// Checks solution produced by a step, so that NaN does not propagate silently to the host
+template<typename T, typename N>
+void check_solution(const N *solution, std::size_t size, T time) {
+    if (solution == nullptr) {
+        throw std::logic_error("solver returned no solution");
+    }
+    for (std::size_t i = 0; i < size; ++i) {
+        if (is_nan(solution[i])) {
+            throw std::domain_error(
+                "component " + std::to_string(i + 1) + " is NaN at t = " + std::to_string(time));
+        }
+    }
+}
+
 template<typename T, typename N>
 struct Solver {
     virtual ~Solver() = default;
 
-    virtual void prepare_for_task(CauchyTask<T, N> task) = 0;
+    virtual void prepare_for_task(CauchyTask<T, N> task, const SolverParameters &parameters) = 0;
 
     virtual N *next_solution(CauchyTask<T, N> task, T &out_time) = 0;
+
+    /// Does at most `max_steps` steps, writing every point not later than `until` into `out_times` and
+    /// `out_states` (`task.size` values per point). Returns amount of written points, which is less than
+    /// `max_steps` only if `until` was passed.
+    virtual std::size_t next_batch(CauchyTask<T, N> task, std::size_t max_steps, T until,
+                                   T *out_times, N *out_states) {
+        for (std::size_t k = 0; k < max_steps; ++k) {
+            T time;
+            const N *solution = next_solution(task, time);
+            check_solution(solution, task.size, time);
+            if (time > until) {
+                return k;
+            }
+            out_times[k] = time;
+            std::copy(solution, solution + task.size, out_states + k * task.size);
+        }
+        return max_steps;
+    }
+};
+
+/// Description of the plugin, which is checked by the host before binding any other symbol
+struct SolverMetadata {
+    std::uint32_t abi_version;
+    const char *name;
+    std::uint32_t order;
+    bool implicit;
+    /// Null-terminated list of suffixes of exported `solver_*` symbols
+    const char *const *suffixes;
+    /// Null-terminated list of accepted parameter names
+    const char *const *parameters;
 };
 
-/// Defined by header's consumer
+struct SolverLayoutEntry {
+    const char *name;
+    std::size_t value;
+};
+
+#define SOLVER_LAYOUT_OF(ty, name) {"sizeof(" name ")", sizeof(ty)}, {"alignof(" name ")", alignof(ty)}
+#define SOLVER_OFFSET_OF(ty, name, field) {"offsetof(" name ", " #field ")", offsetof(ty, field)}
+
+/// Sizes, alignments and field offsets of every type shared with the host for `(T, N)`. The host compares
+/// them with its own definitions before creating a solver, names of entries must match `src/layout.rs`.
+template<typename T, typename N>
+struct SolverLayout {
+    using Task = CauchyTask<T, N>;
+    using Fn = Function<T, N>;
+    using VectorFn = VectorFunction<T, N>;
+    using IntervalF = Interval<float>;
+    using IntervalD = Interval<double>;
+
+    static_assert(std::is_standard_layout_v<Task> && std::is_trivially_copyable_v<Task>,
+                  "CauchyTask must be passed by value across the ABI");
+    static_assert(std::is_standard_layout_v<Fn> && std::is_standard_layout_v<VectorFn>,
+                  "functions must have C layout");
+    static_assert(std::is_standard_layout_v<N>, "outputs must have C layout");
+    static_assert(std::is_standard_layout_v<SolverParameters>, "parameters must have C layout");
+
+    static const SolverLayoutEntry *entries(std::size_t *out_count) {
+        static const SolverLayoutEntry entries[] = {
+            SOLVER_LAYOUT_OF(T, "T"),
+            SOLVER_LAYOUT_OF(N, "N"),
+            SOLVER_LAYOUT_OF(IntervalF, "Interval<float>"),
+            SOLVER_OFFSET_OF(IntervalF, "Interval<float>", start),
+            SOLVER_OFFSET_OF(IntervalF, "Interval<float>", end),
+            SOLVER_LAYOUT_OF(IntervalD, "Interval<double>"),
+            SOLVER_OFFSET_OF(IntervalD, "Interval<double>", start),
+            SOLVER_OFFSET_OF(IntervalD, "Interval<double>", end),
+            SOLVER_LAYOUT_OF(Fn, "Function"),
+            SOLVER_OFFSET_OF(Fn, "Function", state_pointer),
+            SOLVER_OFFSET_OF(Fn, "Function", fn_pointer),
+            SOLVER_OFFSET_OF(Fn, "Function", destructor),
+            SOLVER_LAYOUT_OF(VectorFn, "VectorFunction"),
+            SOLVER_OFFSET_OF(VectorFn, "VectorFunction", state_pointer),
+            SOLVER_OFFSET_OF(VectorFn, "VectorFunction", fn_pointer),
+            SOLVER_OFFSET_OF(VectorFn, "VectorFunction", destructor),
+            SOLVER_LAYOUT_OF(SolverParameters, "SolverParameters"),
+            SOLVER_OFFSET_OF(SolverParameters, "SolverParameters", step),
+            SOLVER_OFFSET_OF(SolverParameters, "SolverParameters", absolute_tolerance),
+            SOLVER_OFFSET_OF(SolverParameters, "SolverParameters", relative_tolerance),
+            SOLVER_OFFSET_OF(SolverParameters, "SolverParameters", max_iterations),
+            SOLVER_OFFSET_OF(SolverParameters, "SolverParameters", options),
+            SOLVER_OFFSET_OF(SolverParameters, "SolverParameters", options_count),
+            SOLVER_LAYOUT_OF(Task, "CauchyTask"),
+            SOLVER_OFFSET_OF(Task, "CauchyTask", size),
+            SOLVER_OFFSET_OF(Task, "CauchyTask", derivatives),
+            SOLVER_OFFSET_OF(Task, "CauchyTask", system),
+            SOLVER_OFFSET_OF(Task, "CauchyTask", initial_conditions),
+            SOLVER_OFFSET_OF(Task, "CauchyTask", initial_time),
+        };
+        *out_count = std::size(entries);
+        return entries;
+    }
+};
+
+/// Opaque handle of a solver instance, owned by the host between `solver_create_*` and `solver_destroy_*`
 template<typename T, typename N>
-extern std::unique_ptr<Solver<T, N> > GLOBAL_SOLVER;
+using SolverHandle = Solver<T, N> *;
+
+/// Result of every fallible plugin call, details are written into the error buffer provided by the host
+enum SolverStatus : std::int32_t {
+    SOLVER_OK = 0,
+    SOLVER_ERROR = 1,
+};
 
-#define gen_binding(solver_obj, time_ty, out_ty, suffix)                                                      \
-    extern "C" const out_ty* solver_eval_next_##suffix(CauchyTask<time_ty, out_ty> task, time_ty* out_time) { \
-        return solver_obj<time_ty, out_ty>->next_solution(task, *out_time);                                   \
+/// Runs `body`, translating any exception into `SOLVER_ERROR` with its message truncated to `capacity`
+template<typename F>
+std::int32_t guard(char *error, std::size_t capacity, F body) noexcept {
+    const char *message;
+    std::string owned;
+    try {
+        body();
+        return SOLVER_OK;
+    } catch (const std::exception &e) {
+        owned = e.what();
+        message = owned.c_str();
+    } catch (...) {
+        message = "unknown exception";
+    }
+    if (error != nullptr && capacity > 0) {
+        std::strncpy(error, message, capacity - 1);
+        error[capacity - 1] = '\0';
+    }
+    return SOLVER_ERROR;
+}
+
+#define gen_binding(solver_ty, time_ty, out_ty, suffix)                                                       \
+    extern "C" const SolverLayoutEntry *solver_layout_##suffix(std::size_t *out_count) {                     \
+        return SolverLayout<time_ty, out_ty>::entries(out_count);                                             \
+    }                                                                                                         \
+    extern "C" SolverHandle<time_ty, out_ty> solver_create_##suffix() {                                       \
+        try {                                                                                                 \
+            return new solver_ty<time_ty, out_ty>();                                                          \
+        } catch (...) {                                                                                       \
+            return nullptr;                                                                                   \
+        }                                                                                                     \
     }                                                                                                         \
-    extern "C" void solver_prepare_##suffix(CauchyTask<time_ty, out_ty> task) {                               \
-        solver_obj<time_ty, out_ty>->prepare_for_task(task);                                                  \
+    extern "C" void solver_destroy_##suffix(SolverHandle<time_ty, out_ty> solver) {                           \
+        delete solver;                                                                                        \
+    }                                                                                                         \
+    extern "C" std::int32_t solver_eval_next_##suffix(SolverHandle<time_ty, out_ty> solver,                   \
+                                                      CauchyTask<time_ty, out_ty> task, time_ty *out_time,    \
+                                                      const out_ty **out_solution,                            \
+                                                      char *error, std::size_t error_capacity) {              \
+        return guard(error, error_capacity, [&] {                                                             \
+            *out_solution = solver->next_solution(task, *out_time);                                           \
+            check_solution(*out_solution, task.size, *out_time);                                              \
+        });                                                                                                   \
+    }                                                                                                         \
+    extern "C" std::int32_t solver_eval_batch_##suffix(SolverHandle<time_ty, out_ty> solver,                  \
+                                                       CauchyTask<time_ty, out_ty> task,                      \
+                                                       std::size_t max_steps, time_ty until,                  \
+                                                       time_ty *out_times, out_ty *out_states,                \
+                                                       std::size_t *out_count,                                \
+                                                       char *error, std::size_t error_capacity) {             \
+        *out_count = 0;                                                                                       \
+        return guard(error, error_capacity, [&] {                                                             \
+            *out_count = solver->next_batch(task, max_steps, until, out_times, out_states);                   \
+        });                                                                                                   \
+    }                                                                                                         \
+    extern "C" std::int32_t solver_prepare_##suffix(SolverHandle<time_ty, out_ty> solver,                     \
+                                                    CauchyTask<time_ty, out_ty> task,                         \
+                                                    const SolverParameters *parameters,                       \
+                                                    char *error, std::size_t error_capacity) {                \
+        return guard(error, error_capacity, [&] {                                                             \
+            solver->prepare_for_task(task, *parameters);                                                      \
+        });                                                                                                   \
     }
 
-gen_binding(GLOBAL_SOLVER, double, double, f64_f64)
-gen_binding(GLOBAL_SOLVER, float, float, f32_f32)
-gen_binding(GLOBAL_SOLVER, double, Interval<double>, f64_If64)
-gen_binding(GLOBAL_SOLVER, float, Interval<double>, f32_If64)
-gen_binding(GLOBAL_SOLVER, double, Interval<float>, f64_If32)
-gen_binding(GLOBAL_SOLVER, float, Interval<float>, f32_If32)
+// Defines `gen_bindings` and `SOLVER_SUFFIXES` from the pairs of types known to the host
+#include "bindings.h"
+
+/// Exports `solver_metadata` symbol, variadic arguments are names of accepted parameters
+#define gen_metadata(solver_name, solver_order, is_implicit, ...)                     \
+    static constexpr const char *SOLVER_PARAMETERS[] = {__VA_ARGS__ __VA_OPT__(,) nullptr}; \
+    extern "C" const SolverMetadata *solver_metadata() {                              \
+        static constexpr SolverMetadata metadata{                                     \
+            SOLVER_ABI_VERSION, solver_name, solver_order, is_implicit,               \
+            SOLVER_SUFFIXES, SOLVER_PARAMETERS                                        \
+        };                                                                            \
+        return &metadata;                                                             \
+    }
@@ -6,39 +6,37 @@ class AdamsBashforth : public Solver<T, N> {
     T h;
     T current_time[2];
     std::vector<N> last_solution[2];
+    std::vector<N> slopes[2];
 
 public:
-    explicit AdamsBashforth(T step = 0.1) {
-        this->h = step;
-    }
-
-    void prepare_for_task(CauchyTask<T, N> task) override {
+    void prepare_for_task(CauchyTask<T, N> task, const SolverParameters &parameters) override {
+        h = parameters.checked_step<T>();
         auto cond_view = task.initial_conditions;
         current_time[0] = task.initial_time;
         last_solution[0] = {cond_view, cond_view + task.size};
 
         // Do one step with Euler method to populate last_solution[1]
-        auto view = task.derivatives;
+        slopes[0].resize(task.size);
+        slopes[1].resize(task.size);
+        task.eval(current_time[0], last_solution[0].data(), slopes[0].data());
         last_solution[1].resize(task.size);
         for (std::size_t i = 0; i < task.size; ++i) {
-            auto &f = view[i];
-            last_solution[1][i] = last_solution[0][i] + h * f(current_time[0], last_solution[0].data());
+            last_solution[1][i] = last_solution[0][i] + h * slopes[0][i];
         }
         current_time[1] = current_time[0] + h;
     }
 
     N *next_solution(CauchyTask<T, N> task, T &out_time) override {
-        auto view = task.derivatives;
+        check_step(current_time[1], h);
         auto size = task.size;
         std::vector<N> result;
         result.resize(size);
+        task.eval(current_time[1], last_solution[1].data(), slopes[1].data());
+        task.eval(current_time[0], last_solution[0].data(), slopes[0].data());
 
         for (std::size_t i = 0; i < size; i++) {
-            auto &f = view[i];
-
             // y n = y n-1 + h(1.5 f(x n-1, y n-1) - 0.5 f(x n-2, y n-2))
-            result[i] = last_solution[1][i] + h * (3. / 2. * f(current_time[1], last_solution[1].data()) -
-                                                   1. / 2. * f(current_time[0], last_solution[0].data()));
+            result[i] = last_solution[1][i] + h * (3. / 2. * slopes[1][i] - 1. / 2. * slopes[0][i]);
         }
 
         current_time[0] = current_time[1];
@@ -52,5 +50,6 @@ public:
     }
 };
 
-template<typename T, typename N>
-std::unique_ptr<Solver<T, N>> GLOBAL_SOLVER = std::make_unique<AdamsBashforth<T, N>>();
\ No newline at end of file
+gen_bindings(AdamsBashforth)
+
+gen_metadata("adams-bashforth", 2, false, "step")
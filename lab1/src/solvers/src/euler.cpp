@@ -8,35 +8,31 @@ class EulerSolver : public Solver<T, N> {
     T h;
     T current_time;
     std::vector<N> last_solution;
+    std::vector<N> slopes;
 
 public:
-    explicit EulerSolver(T step = 0.1) {
-        this->h = step;
-    }
-
-    void prepare_for_task(CauchyTask<T, N> task) override {
+    void prepare_for_task(CauchyTask<T, N> task, const SolverParameters &parameters) override {
+        h = parameters.checked_step<T>();
         auto view = task.initial_conditions;
         current_time = task.initial_time;
         last_solution = std::vector<N>{view, view + task.size};
     }
 
     N *next_solution(CauchyTask<T, N> task, T &out_time) override {
-        auto view = task.derivatives;
-        auto size = task.size;
-        auto result = new N[size];
+        check_step(current_time, h);
+        slopes.resize(task.size);
+        task.eval(current_time, last_solution.data(), slopes.data());
 
-        for (std::size_t i = 0; i < size; i++) {
-            auto &f = view[i];
-            result[i] = last_solution[i] + h * f(current_time, last_solution.data());
+        for (std::size_t i = 0; i < task.size; i++) {
+            last_solution[i] = last_solution[i] + h * slopes[i];
         }
 
         current_time += h;
         out_time = current_time;
-        std::copy(std::make_move_iterator(result), std::make_move_iterator(result + size), last_solution.begin());
-        delete[] result;
         return last_solution.data();
     }
 };
 
-template<typename T, typename N>
-std::unique_ptr<Solver<T, N>> GLOBAL_SOLVER = std::make_unique<EulerSolver<T, N>>();
\ No newline at end of file
+gen_bindings(EulerSolver)
+
+gen_metadata("euler", 1, false, "step")
@@ -8,56 +8,53 @@ using namespace std::ranges;
 
 template<typename T, typename N>
 class RungeKuttaSolver final : public Solver<T, N> {
-    T h; // TODO: pass it from Rust somehow
+    T h;
     T current_time;
     std::vector<N> last_solution;
-    std::vector<N> buffer;
+    std::vector<N> k1, k2, k3, k4, stage;
 
 public:
-    explicit RungeKuttaSolver(T step = 0.1) {
-        this->h = step;
-    }
-
-    void prepare_for_task(CauchyTask<T, N> task) override {
+    void prepare_for_task(CauchyTask<T, N> task, const SolverParameters &parameters) override {
+        h = parameters.checked_step<T>();
         auto view = task.initial_conditions;
         last_solution = std::vector<N>{view, view + task.size};
         current_time = task.initial_time;
     }
 
     N *next_solution(CauchyTask<T, N> task, T &out_time) override {
-        auto view = task.derivatives;
+        check_step(current_time, h);
         auto size = task.size;
-        auto temp = new N[size * 5];
-        auto result = temp, k1 = &temp[size], k2 = &temp[size * 2], k3 = &temp[size * 3], k4 = &temp[size * 4];
+        for (auto *buffer: {&k1, &k2, &k3, &k4, &stage}) {
+            buffer->resize(size);
+        }
+        auto y = last_solution.data();
 
+        task.eval(current_time, y, k1.data());
         for (std::size_t i = 0; i < size; i++) {
-            k1[i] = view[i](current_time, last_solution.data());
-            result[i] = last_solution[i] + k1[i] * h / 2.;
+            stage[i] = y[i] + k1[i] * h / 2.;
         }
 
+        task.eval(current_time + h / 2., stage.data(), k2.data());
         for (std::size_t i = 0; i < size; i++) {
-            k2[i] = view[i](current_time + h / 2., temp);
-            result[i] = last_solution[i] + k2[i] * h / 2.;
+            stage[i] = y[i] + k2[i] * h / 2.;
         }
 
+        task.eval(current_time + h / 2., stage.data(), k3.data());
         for (std::size_t i = 0; i < size; i++) {
-            k3[i] = view[i](current_time + h / 2., temp);
-            result[i] = last_solution[i] + k3[i] * h;
+            stage[i] = y[i] + k3[i] * h;
         }
 
+        task.eval(current_time + h, stage.data(), k4.data());
         for (std::size_t i = 0; i < size; i++) {
-            k4[i] = view[i](current_time + h, temp);
-            result[i] = last_solution[i] + h / 6. * (k1[i] + 2. * k2[i] + 2. * k3[i] + k4[i]);
+            y[i] = y[i] + h / 6. * (k1[i] + 2. * k2[i] + 2. * k3[i] + k4[i]);
         }
 
         current_time += h;
         out_time = current_time;
-        std::copy(std::make_move_iterator(temp), std::make_move_iterator(temp + size), last_solution.begin());
-
-        delete[] temp;
         return last_solution.data();
     }
 };
 
-template<typename T, typename N>
-std::unique_ptr<Solver<T, N>> GLOBAL_SOLVER = std::make_unique<RungeKuttaSolver<T, N>>();
+gen_bindings(RungeKuttaSolver)
+
+gen_metadata("runge-kutta", 4, false, "step")
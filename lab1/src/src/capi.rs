@@ -0,0 +1,322 @@
+//! C API of the crate, which is exported from the `cdylib` build.
+//! Its header [`HEADER`] is generated from this module by `build.rs`, the copy in
+//! `include/project.h` is written by `project gen-header`.
+//!
+//! Fallible functions return [`PROJECT_OK`] or [`PROJECT_ERROR`] (or null instead of a pointer),
+//! the message of the last error on the calling thread is available via [`project_last_error`].
+
+use crate::solution::{Solution, StopCondition};
+use crate::solver::{EulerSolver, Solver};
+use crate::task::{panic_message, CauchyTask, Derivatives, Function, VectorFunction};
+use crate::Frozen;
+use anyhow::{anyhow, bail, Error};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+/// Contents of `include/project.h`
+pub const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/project.h"));
+
+/// Status of a successful call
+pub const PROJECT_OK: i32 = 0;
+/// Status of a failed call, see [`project_last_error`]
+pub const PROJECT_ERROR: i32 = 1;
+
+/// Derivative of a single component at `time`, `inputs` has a value for every component
+pub type ProjectComponentFn =
+    Option<extern "C" fn(user_data: *mut c_void, time: f64, inputs: *const f64) -> f64>;
+
+/// Derivatives of the whole system at `time`, written into `outputs` of the same size as `inputs`
+pub type ProjectSystemFn = Option<
+    extern "C" fn(user_data: *mut c_void, time: f64, inputs: *const f64, outputs: *mut f64),
+>;
+
+/// Cauchy task over `double`, created by [`project_task_new`] or [`project_task_new_system`]
+pub struct ProjectTask(CauchyTask<f64, f64>);
+
+/// Builtin solver over `double`, created by `project_solver_new_*`
+pub struct ProjectSolver {
+    solver: Frozen<EulerSolver<f64, f64>>,
+    /// Task which the solver was prepared for, stepping is allowed only with it
+    prepared: *const ProjectTask,
+}
+
+/// Solution computed by [`project_solve`]
+pub struct ProjectSolution(Solution<f64, f64>);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(error: Error) {
+    let message = format!("{error:#}").replace('\0', " ");
+    LAST_ERROR.with(|it| *it.borrow_mut() = CString::new(message).ok());
+}
+
+/// Runs `body`, turning error or panic into the last error
+fn call<R>(body: impl FnOnce() -> Result<R, Error>) -> Option<R> {
+    let result = catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        Err(anyhow!("Panicked: {}", panic_message(&*payload)))
+    });
+    result.map_err(set_error).ok()
+}
+
+fn status(body: impl FnOnce() -> Result<(), Error>) -> i32 {
+    match call(body) {
+        Some(()) => PROJECT_OK,
+        None => PROJECT_ERROR,
+    }
+}
+
+fn boxed<R>(body: impl FnOnce() -> Result<R, Error>) -> *mut R {
+    call(body).map_or(ptr::null_mut(), |it| Box::into_raw(Box::new(it)))
+}
+
+/// Reads `size` values from `values`, which must not be null unless `size` is zero
+unsafe fn read<'a, T>(values: *const T, size: usize, name: &str) -> Result<&'a [T], Error> {
+    if size == 0 {
+        return Ok(&[]);
+    }
+    if values.is_null() {
+        bail!("`{name}` is null");
+    }
+    Ok(slice::from_raw_parts(values, size))
+}
+
+unsafe fn get<'a, T>(value: *const T, name: &str) -> Result<&'a T, Error> {
+    value.as_ref().ok_or_else(|| anyhow!("`{name}` is null"))
+}
+
+unsafe fn get_mut<'a, T>(value: *mut T, name: &str) -> Result<&'a mut T, Error> {
+    value.as_mut().ok_or_else(|| anyhow!("`{name}` is null"))
+}
+
+/// Message of the last error on this thread or null if there was none.
+/// It stays valid until the next failed call on this thread.
+#[no_mangle]
+pub extern "C" fn project_last_error() -> *const c_char {
+    LAST_ERROR.with(|it| it.borrow().as_ref().map_or(ptr::null(), |it| it.as_ptr()))
+}
+
+/// Creates a task with a function per component, `derivatives` and `initial_conditions` have `size` elements.
+/// `user_data` is passed to every function as is and must outlive the task.
+///
+/// # Safety
+/// Pointers must be valid for `size` elements.
+#[no_mangle]
+pub unsafe extern "C" fn project_task_new(
+    size: usize,
+    derivatives: *const ProjectComponentFn,
+    user_data: *mut c_void,
+    initial_time: f64,
+    initial_conditions: *const f64,
+) -> *mut ProjectTask {
+    boxed(|| {
+        let functions = read(derivatives, size, "derivatives")?
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let f = f.ok_or_else(|| anyhow!("Derivative of component {} is null", i + 1))?;
+                Ok(Function::from_slice(size, move |t, xs: &[f64]| f(user_data, t, xs.as_ptr())))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let initial_conditions = read(initial_conditions, size, "initial_conditions")?;
+        Ok(ProjectTask(CauchyTask::from_parts(
+            functions,
+            initial_time,
+            initial_conditions.to_vec(),
+        )))
+    })
+}
+
+/// Creates a task with a single function for the whole system, `initial_conditions` has `size` elements.
+/// `user_data` is passed to the function as is and must outlive the task.
+///
+/// # Safety
+/// `initial_conditions` must be valid for `size` elements.
+#[no_mangle]
+pub unsafe extern "C" fn project_task_new_system(
+    size: usize,
+    system: ProjectSystemFn,
+    user_data: *mut c_void,
+    initial_time: f64,
+    initial_conditions: *const f64,
+) -> *mut ProjectTask {
+    boxed(|| {
+        let f = system.ok_or_else(|| anyhow!("`system` is null"))?;
+        let initial_conditions = read(initial_conditions, size, "initial_conditions")?;
+        Ok(ProjectTask(CauchyTask {
+            size,
+            derivatives: Derivatives::System(VectorFunction::from_slice(
+                size,
+                move |t, inputs: &[f64], outputs: &mut [f64]| {
+                    f(user_data, t, inputs.as_ptr(), outputs.as_mut_ptr())
+                },
+            )),
+            initial_conditions: Box::from(initial_conditions),
+            initial_time,
+        }))
+    })
+}
+
+/// Amount of components of the task
+///
+/// # Safety
+/// `task` must be created by `project_task_new*`.
+#[no_mangle]
+pub unsafe extern "C" fn project_task_size(task: *const ProjectTask) -> usize {
+    task.as_ref().map_or(0, |it| it.0.size)
+}
+
+/// # Safety
+/// `task` must be created by `project_task_new*` or be null, no solver may use it afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn project_task_free(task: *mut ProjectTask) {
+    if !task.is_null() {
+        drop(Box::from_raw(task));
+    }
+}
+
+/// Creates builtin Euler method solver with a fixed positive `step`
+#[no_mangle]
+pub extern "C" fn project_solver_new_euler(step: f64) -> *mut ProjectSolver {
+    boxed(|| {
+        if !(step > 0.0 && step.is_finite()) {
+            bail!("Step should be positive and finite, but it is {step}");
+        }
+        Ok(ProjectSolver {
+            solver: EulerSolver::new(step),
+            prepared: ptr::null(),
+        })
+    })
+}
+
+/// # Safety
+/// `solver` must be created by `project_solver_new_*` or be null.
+#[no_mangle]
+pub unsafe extern "C" fn project_solver_free(solver: *mut ProjectSolver) {
+    if !solver.is_null() {
+        drop(Box::from_raw(solver));
+    }
+}
+
+/// Resets the solver to the initial conditions of `task`, which is required before [`project_solver_step`]
+///
+/// # Safety
+/// Pointers must be created by the corresponding constructors.
+#[no_mangle]
+pub unsafe extern "C" fn project_solver_prepare(
+    solver: *mut ProjectSolver,
+    task: *const ProjectTask,
+) -> i32 {
+    status(|| {
+        let solver = get_mut(solver, "solver")?;
+        EulerSolver::prepare(solver.solver.as_mut(), &get(task, "task")?.0)?;
+        solver.prepared = task;
+        Ok(())
+    })
+}
+
+/// Does a single step, writing the reached time into `out_time` and the state into `out_state`,
+/// which must have room for [`project_task_size`] values
+///
+/// # Safety
+/// Pointers must be created by the corresponding constructors, outputs must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn project_solver_step(
+    solver: *mut ProjectSolver,
+    task: *const ProjectTask,
+    out_time: *mut f64,
+    out_state: *mut f64,
+) -> i32 {
+    status(|| {
+        let solver = get_mut(solver, "solver")?;
+        if solver.prepared != task {
+            bail!("Solver should be prepared for the task with `project_solver_prepare` first");
+        }
+        let out_time = get_mut(out_time, "out_time")?;
+        let task = &get(task, "task")?.0;
+        if out_state.is_null() && task.size != 0 {
+            bail!("`out_state` is null");
+        }
+        let (time, state) = solver.solver.0.next_solution(task)?;
+        *out_time = time;
+        if !state.is_empty() {
+            ptr::copy_nonoverlapping(state.as_ptr(), out_state, state.len());
+        }
+        Ok(())
+    })
+}
+
+/// Solves `task` from its initial conditions until `maximum` time
+///
+/// # Safety
+/// Pointers must be created by the corresponding constructors.
+#[no_mangle]
+pub unsafe extern "C" fn project_solve(
+    solver: *mut ProjectSolver,
+    task: *const ProjectTask,
+    maximum: f64,
+) -> *mut ProjectSolution {
+    boxed(|| {
+        let solver = get_mut(solver, "solver")?;
+        let task = get(task, "task")?;
+        let solution = Solution::compute(solver.solver.as_mut(), &task.0, StopCondition::Timed { maximum })?;
+        solver.prepared = task;
+        Ok(ProjectSolution(solution))
+    })
+}
+
+/// Amount of computed points
+///
+/// # Safety
+/// `solution` must be created by [`project_solve`].
+#[no_mangle]
+pub unsafe extern "C" fn project_solution_points(solution: *const ProjectSolution) -> usize {
+    solution.as_ref().map_or(0, |it| it.0.time().len())
+}
+
+/// Amount of components of the solution
+///
+/// # Safety
+/// `solution` must be created by [`project_solve`].
+#[no_mangle]
+pub unsafe extern "C" fn project_solution_components(solution: *const ProjectSolution) -> usize {
+    solution.as_ref().map_or(0, |it| it.0.components())
+}
+
+/// Time of every point, [`project_solution_points`] values owned by the solution
+///
+/// # Safety
+/// `solution` must be created by [`project_solve`].
+#[no_mangle]
+pub unsafe extern "C" fn project_solution_time(solution: *const ProjectSolution) -> *const f64 {
+    solution.as_ref().map_or(ptr::null(), |it| it.0.time().as_ptr())
+}
+
+/// Values of `component` at every point, [`project_solution_points`] values owned by the solution.
+/// Null if there is no such component.
+///
+/// # Safety
+/// `solution` must be created by [`project_solve`].
+#[no_mangle]
+pub unsafe extern "C" fn project_solution_component(
+    solution: *const ProjectSolution,
+    component: usize,
+) -> *const f64 {
+    match solution.as_ref() {
+        Some(it) if component < it.0.components() => it.0[component].as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+/// # Safety
+/// `solution` must be created by [`project_solve`] or be null.
+#[no_mangle]
+pub unsafe extern "C" fn project_solution_free(solution: *mut ProjectSolution) {
+    if !solution.is_null() {
+        drop(Box::from_raw(solution));
+    }
+}
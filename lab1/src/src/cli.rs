@@ -0,0 +1,80 @@
+use crate::config::Override;
+use clap::{Parser, Subcommand};
+use project::sandbox;
+use std::path::PathBuf;
+
+/// Solves systems of ODEs given in the config with the builtin solver or solver plugins
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Config file, `config.toml` is used if it exists in the current directory
+    #[arg(long, short, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Replaces a config value, e.g. `general.t_max=5`, may be repeated
+    #[arg(long = "set", short = 's', global = true, value_name = "KEY=VALUE")]
+    pub overrides: Vec<Override>,
+    /// Without a command every experiment is solved and plotted, then every configured analysis is run
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Solves every experiment and writes its solution into a csv file
+    Solve,
+    /// Solves every experiment and draws its nominal and interval solutions
+    Plot,
+    /// Solves an experiment with several solvers and compares them with the one of the highest order
+    Compare {
+        /// Names of solvers, ones from the `[compare]` section by default
+        solvers: Vec<String>,
+        /// Name of the experiment, the one from the `[compare]` section or the first one by default
+        #[arg(long)]
+        experiment: Option<String>,
+    },
+    /// Runs an experiment for every combination of values from the `[sweep]` section
+    Sweep,
+    /// Halves the step of a solver several times and estimates its order of accuracy
+    Converge {
+        /// Name of the solver, the one of the experiment by default
+        #[arg(long)]
+        solver: Option<String>,
+        /// Name of the experiment, the first one by default
+        #[arg(long)]
+        experiment: Option<String>,
+        /// How many times the step is halved
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(2..=20))]
+        levels: u32,
+    },
+    /// Lists discovered solvers together with supported types and parameters
+    ListSolvers,
+    /// Checks the config and the solvers it refers to without solving anything
+    ValidateConfig,
+    /// Solves an experiment again whenever a plugin library changes
+    Watch {
+        /// Name of the experiment, the first one by default
+        #[arg(long)]
+        experiment: Option<String>,
+    },
+    /// Runs an experiment again with the config and values recorded in its manifest and checks
+    /// that the same files are written
+    Rerun {
+        manifest: PathBuf,
+        /// Directory for the new outputs, `rerun` next to the manifest by default
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Writes C++ bindings of solvers for every pair of types known to the host
+    GenBindings {
+        #[arg(default_value = "solvers/include/bindings.h")]
+        path: PathBuf,
+    },
+    /// Writes C header of the library API
+    GenHeader {
+        #[arg(default_value = "include/project.h")]
+        path: PathBuf,
+    },
+    /// Helper process of a sandboxed solver, see `general.sandbox`
+    #[command(name = sandbox::HOST_COMMAND, hide = true)]
+    SandboxHost { plugin: PathBuf, suffix: String },
+}
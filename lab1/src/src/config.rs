@@ -1,13 +1,284 @@
+use std::collections::BTreeMap;
+use std::fs;
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::diagnostics::{self, Problem, Source};
+use anyhow::{anyhow, bail, Context, Error};
+use project::interval::Interval;
+use project::plugin::{Parameters, BUILTIN};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Config {
     #[serde(default)]
     pub general: Runtime,
     #[serde(default)]
-    pub plotting: Plot
+    pub plotting: Plot,
+    /// Parameters of every solver by its name, e.g. `[solvers.runge-kutta]`
+    #[serde(default)]
+    pub solvers: BTreeMap<String, toml::Table>,
+    pub sensitivity: Option<Sensitivity>,
+    pub fitting: Option<Fitting>,
+    pub sivia: Option<Sivia>,
+    pub global_sensitivity: Option<GlobalSensitivity>,
+    pub stochastic: Option<Stochastic>,
+    pub compare: Option<Compare>,
+    /// Tasks which are solved one after another, see [`Experiment::reaction`] for the default
+    #[serde(default, rename = "experiment")]
+    pub experiments: Vec<Experiment>,
+    pub sweep: Option<Sweep>,
+    /// Where the config has been read from, which problems are reported against
+    #[serde(skip)]
+    pub source: Source,
+}
+
+/// Config file which is used if no other is given and it exists
+pub const DEFAULT_PATH: &str = "config.toml";
+
+/// Replacement of a single config value, written as `general.t_max=5`
+#[derive(Debug, Clone)]
+pub struct Override {
+    path: Vec<String>,
+    value: toml::Value,
+}
+
+impl FromStr for Override {
+    type Err = String;
+
+    /// Value is parsed as TOML, anything which is not valid TOML is taken as a string,
+    /// so that `general.solver=builtin` needs no quotes
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected `key=value`, got `{s}`"))?;
+        let value = value.trim();
+        let value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+            .ok()
+            .and_then(|mut it| it.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        Self::new(key.trim(), value)
+    }
+}
+
+impl Override {
+    /// Replacement of the value at dotted `key`
+    pub fn new(key: &str, value: toml::Value) -> Result<Self, String> {
+        let path = key.split('.').map(str::to_string).collect::<Vec<_>>();
+        if path.iter().any(String::is_empty) {
+            return Err(format!("Invalid key `{key}`"));
+        }
+        Ok(Self { path, value })
+    }
+
+    pub fn apply(&self, table: &mut toml::Table) -> Result<(), Error> {
+        let (last, parents) = self.path.split_last().unwrap();
+        let mut current = table;
+        for (i, key) in parents.iter().enumerate() {
+            let entry = current
+                .entry(key.clone())
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            let Some(next) = entry.as_table_mut() else {
+                bail!("`{}` is not a table", self.path[..=i].join("."));
+            };
+            current = next;
+        }
+        current.insert(last.clone(), self.value.clone());
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Reads config from `path` or from [`DEFAULT_PATH`] if there is such a file, then applies
+    /// `overrides` in their order. Fails only if the config cannot be deserialized, in which case
+    /// unknown keys are reported too, other problems are left for [`Self::problems`]
+    pub fn load(path: Option<&Path>, overrides: &[Override]) -> Result<Self, Error> {
+        let path = match path {
+            Some(path) => Some(path),
+            None => Some(Path::new(DEFAULT_PATH)).filter(|it| it.exists()),
+        };
+        let text = path
+            .map(|path| fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display())))
+            .transpose()?
+            .unwrap_or_default();
+        Self::parse(path, text, overrides)
+    }
+
+    /// Config from `text` of the file at `path`, which problems are located in, see [`Self::load`].
+    /// Unknown keys are reported together with a deserialization error, but [`Self::problems`]
+    /// need the deserialized config, so they only show up once such an error is fixed.
+    pub fn parse(path: Option<&Path>, text: String, overrides: &[Override]) -> Result<Self, Error> {
+        let mut source = Source::new(
+            path.map(Path::to_path_buf),
+            text,
+            overrides.iter().map(|it| it.path.clone()).collect(),
+        );
+        let mut table = toml::from_str::<toml::Table>(source.text())
+            .map_err(|e| anyhow!("Could not parse config\n{}", source.render_error(&e)))?;
+
+        for it in overrides {
+            it.apply(&mut table)
+                .with_context(|| format!("Could not set `{}`", it.path.join(".")))?;
+        }
+        let mut unknown_keys = diagnostics::unknown_keys(&table);
+        source.sort(&mut unknown_keys);
+        source.unknown_keys = unknown_keys;
+        // Spans are known only for the text of the file, so it is deserialized when nothing is
+        // replaced
+        let config = match overrides {
+            [] => toml::from_str::<Self>(source.text()).map_err(|e| source.render_error(&e)),
+            _ => toml::Value::Table(table).try_into::<Self>().map_err(|e| {
+                // The same error without overrides is in the file, so it can be located
+                match toml::from_str::<Self>(source.text()) {
+                    Err(it) if it.message() == e.message() => source.render_error(&it),
+                    _ => e.message().to_string(),
+                }
+            }),
+        };
+        match config {
+            Ok(config) => Ok(Self { source, ..config }),
+            Err(e) => {
+                let unknown = source.unknown_keys.iter().map(|it| source.render(it));
+                bail!("Invalid config\n{}", unknown.chain([e]).collect::<Vec<_>>().join("\n"))
+            }
+        }
+    }
+
+    /// Values which are deserialized but make no sense, such as a negative `t_max` or an empty
+    /// viewport
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        let general_valid = self.general.t_max > 0.0 && self.general.t_max.is_finite();
+        let mut require = |valid: bool, keys: &[&str], message: String| {
+            if !valid {
+                problems.push(Problem::new(keys, message));
+            }
+        };
+        require(
+            general_valid,
+            &["general", "t_max"],
+            format!("`general.t_max` should be positive, got {}", self.general.t_max),
+        );
+        require(
+            self.general.sandbox_timeout > 0.0 && self.general.sandbox_timeout.is_finite(),
+            &["general", "sandbox_timeout"],
+            format!("`general.sandbox_timeout` should be positive and finite, got {}", self.general.sandbox_timeout),
+        );
+        for (name, range) in [("x", &self.plotting.viewport.x), ("y", &self.plotting.viewport.y)] {
+            require(
+                is_range(range),
+                &["plotting", "viewport", name],
+                format!("Viewport range {name} = {range:?} is empty or inverted"),
+            );
+        }
+        let (width, height) = self.plotting.plot_size;
+        require(
+            width > 0 && height > 0,
+            &["plotting", "plot_size"],
+            format!("`plotting.plot_size` should not be zero, got {width}x{height}"),
+        );
+
+        if let Some(fitting) = &self.fitting {
+            for (i, &weight) in fitting.weights.iter().enumerate() {
+                require(
+                    weight >= 0.0 && weight.is_finite(),
+                    &["fitting", "weights", &i.to_string()],
+                    format!("Weight {weight} of `fitting.weights` should be finite and not negative"),
+                );
+            }
+        }
+        if let Some(sivia) = &self.sivia {
+            require(
+                sivia.error >= 0.0,
+                &["sivia", "error"],
+                format!("`sivia.error` should not be negative, got {}", sivia.error),
+            );
+            require(
+                sivia.epsilon > 0.0,
+                &["sivia", "epsilon"],
+                format!("`sivia.epsilon` should be positive, got {}", sivia.epsilon),
+            );
+            for (i, range) in sivia.domain.iter().enumerate() {
+                require(
+                    is_range(range),
+                    &["sivia", "domain", &i.to_string()],
+                    format!("Range {range:?} of `sivia.domain` is empty or inverted"),
+                );
+            }
+        }
+        if let Some(global_sensitivity) = &self.global_sensitivity {
+            require(
+                (1..=3).contains(&global_sensitivity.component),
+                &["global_sensitivity", "component"],
+                format!(
+                    "`global_sensitivity.component` should be in 1..=3, got {}",
+                    global_sensitivity.component
+                ),
+            );
+            require(
+                global_sensitivity.samples > 0,
+                &["global_sensitivity", "samples"],
+                "`global_sensitivity.samples` should be positive".to_string(),
+            );
+            for (i, range) in global_sensitivity.ranges.iter().enumerate() {
+                require(
+                    is_range(range),
+                    &["global_sensitivity", "ranges", &i.to_string()],
+                    format!("Range {range:?} of `global_sensitivity.ranges` is empty or inverted"),
+                );
+            }
+            require(
+                global_sensitivity.levels >= 2,
+                &["global_sensitivity", "levels"],
+                format!("`global_sensitivity.levels` should be at least 2, got {}", global_sensitivity.levels),
+            );
+        }
+        if let Some(stochastic) = &self.stochastic {
+            require(
+                stochastic.step > 0.0,
+                &["stochastic", "step"],
+                format!("`stochastic.step` should be positive, got {}", stochastic.step),
+            );
+            require(
+                stochastic.paths > 0,
+                &["stochastic", "paths"],
+                "`stochastic.paths` should be positive".to_string(),
+            );
+            let (lower, upper) = stochastic.quantiles;
+            require(
+                (0.0..=1.0).contains(&lower) && (0.0..=1.0).contains(&upper) && lower < upper,
+                &["stochastic", "quantiles"],
+                format!("`stochastic.quantiles` should be increasing within [0, 1], got ({lower}, {upper})"),
+            );
+        }
+        for (i, it) in self.experiments.iter().enumerate() {
+            for problem in it.problems(self.general.t_max) {
+                let keys = ["experiment".to_string(), i.to_string()].into_iter().chain(problem.keys);
+                problems.push(Problem {
+                    keys: keys.collect(),
+                    message: problem.message,
+                });
+            }
+        }
+        problems
+    }
+
+    /// Parameters from `[solvers.<solver>]` section, which keys should be `accepted` by the solver
+    pub fn solver_parameters(
+        &self,
+        solver: &str,
+        accepted: &[impl AsRef<str>],
+    ) -> Result<Parameters, Error> {
+        let values = self.solvers.get(solver).into_iter().flatten().map(|(key, value)| {
+            let value = match value {
+                toml::Value::String(it) => it.clone(),
+                other => other.to_string(),
+            };
+            (key.clone(), value)
+        });
+        Parameters::parse(values, accepted)
+            .with_context(|| format!("Invalid section [solvers.{solver}]"))
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -16,9 +287,30 @@ pub struct Runtime {
     pub t_max: f64,
     #[serde(default = "def_output_dir")]
     pub output_dir: PathBuf,
+    /// Used for discovery of plugins if [`Self::plugin_dirs`] is empty
     #[serde(default = "def_lib_dir")]
     pub lib_dir: PathBuf,
+    /// Directories which are scanned for solver plugins
+    #[serde(default)]
+    pub plugin_dirs: Vec<PathBuf>,
+    /// Name of the solver from its metadata or `builtin`
     pub solver: String,
+    /// Run plugin solvers in a helper process, so that their crashes and hangs become errors
+    #[serde(default)]
+    pub sandbox: bool,
+    /// Seconds the sandboxed solver may take to answer a single request
+    #[serde(default = "def_sandbox_timeout")]
+    pub sandbox_timeout: f64,
+}
+
+impl Runtime {
+    pub fn plugin_dirs(&self) -> &[PathBuf] {
+        if self.plugin_dirs.is_empty() {
+            std::slice::from_ref(&self.lib_dir)
+        } else {
+            &self.plugin_dirs
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Copy, Clone)]
@@ -29,6 +321,15 @@ pub enum Output {
     Svg
 }
 
+impl Output {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Output::Png => "png",
+            Output::Svg => "svg",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Viewport {
     #[serde(default = "def_viewport")]
@@ -47,6 +348,445 @@ pub struct Plot {
     pub output_type: Output
 }
 
+/// Number which is either known exactly or only within an interval, written as `0.577`,
+/// `"0.577 ± 0.001"`, `[0.576, 0.578]` or `{ nominal = 0.577, bounds = [0.576, 0.579] }`
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(try_from = "RawUncertain", into = "RawUncertain")]
+pub struct Uncertain {
+    /// Value which is used where a single number is needed, e.g. the center of `c ± r`
+    pub nominal: f64,
+    pub bounds: Option<Interval<f64>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawUncertain {
+    Exact(f64),
+    Bounds([f64; 2]),
+    Text(String),
+    /// Nominal which is not the midpoint of the bounds, e.g. after `c ± r` is rounded
+    Explicit { nominal: f64, bounds: [f64; 2] },
+}
+
+impl Uncertain {
+    pub fn interval(self) -> Interval<f64> {
+        self.bounds.unwrap_or(Interval::from(self.nominal))
+    }
+}
+
+impl From<f64> for Uncertain {
+    fn from(value: f64) -> Self {
+        Self {
+            nominal: value,
+            bounds: None,
+        }
+    }
+}
+
+impl TryFrom<RawUncertain> for Uncertain {
+    type Error = String;
+
+    fn try_from(value: RawUncertain) -> Result<Self, Self::Error> {
+        let (start, end, nominal) = match value {
+            RawUncertain::Exact(value) => return Ok(value.into()),
+            RawUncertain::Bounds([start, end]) => (start, end, (start + end) / 2.0),
+            RawUncertain::Explicit {
+                nominal,
+                bounds: [start, end],
+            } => {
+                if !(start..=end).contains(&nominal) {
+                    return Err(format!("Nominal {nominal} is outside of the bounds [{start}, {end}]"));
+                }
+                (start, end, nominal)
+            }
+            RawUncertain::Text(text) => {
+                let (center, radius) = text
+                    .split_once('±')
+                    .or_else(|| text.split_once("+-"))
+                    .ok_or_else(|| format!("Expected `<center> ± <radius>`, got `{text}`"))?;
+                let parse = |it: &str| {
+                    it.trim().parse::<f64>().map_err(|e| format!("Invalid number `{}` in `{text}`: {e}", it.trim()))
+                };
+                let (center, radius) = (parse(center)?, parse(radius)?);
+                if radius < 0.0 {
+                    return Err(format!("Radius of `{text}` should not be negative"));
+                }
+                (center - radius, center + radius, center)
+            }
+        };
+        if start.is_nan() || end.is_nan() || start > end {
+            return Err(format!("Interval [{start}, {end}] is empty"));
+        }
+        Ok(Self {
+            nominal,
+            bounds: Some(Interval::new(start, end)),
+        })
+    }
+}
+
+impl From<Uncertain> for RawUncertain {
+    fn from(value: Uncertain) -> Self {
+        match value.bounds {
+            Some(bounds) if value.nominal == (bounds.start() + bounds.end()) / 2.0 => {
+                RawUncertain::Bounds([bounds.start(), bounds.end()])
+            }
+            Some(bounds) => RawUncertain::Explicit {
+                nominal: value.nominal,
+                bounds: [bounds.start(), bounds.end()],
+            },
+            None => RawUncertain::Exact(value.nominal),
+        }
+    }
+}
+
+/// Task from the config which is solved by the configured or its own solver
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Experiment {
+    /// Used in messages and as the default name of output files
+    pub name: String,
+    /// Name of a builtin model, which gives equations and default initial conditions
+    pub model: Option<String>,
+    /// Equations such as `x1' = -k1 * x1`, if there is no [`Self::model`]
+    #[serde(default)]
+    pub equations: Vec<String>,
+    /// Value of every parameter of the equations by its name
+    #[serde(default)]
+    pub parameters: BTreeMap<String, Uncertain>,
+    /// Initial value of every variable by its name, which overrides the one of the model
+    #[serde(default)]
+    pub initial: BTreeMap<String, Uncertain>,
+    #[serde(default)]
+    pub initial_time: f64,
+    /// Name of the solver, `general.solver` by default
+    pub solver: Option<String>,
+    /// Step of the solver, the one from its `[solvers.<name>]` section by default
+    pub step: Option<f64>,
+    /// Time to solve until, `general.t_max` by default
+    pub t_max: Option<f64>,
+    #[serde(default)]
+    pub outputs: Outputs,
+}
+
+/// Files written for an experiment into `general.output_dir`, which names are derived from the
+/// experiment name by default
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Outputs {
+    /// Csv with the nominal solution and bounds of the interval one
+    pub csv: Option<PathBuf>,
+    pub plot: Option<PathBuf>,
+    /// Record of the run, see `rerun`
+    pub manifest: Option<PathBuf>,
+}
+
+impl Experiment {
+    /// Experiment which is run if the config has none: the reaction with an uncertain `k1`
+    pub fn reaction() -> Self {
+        Self {
+            name: "reaction".to_string(),
+            model: Some("reaction".to_string()),
+            equations: vec![],
+            parameters: BTreeMap::from([
+                (
+                    "k1".to_string(),
+                    Uncertain {
+                        nominal: 0.577,
+                        bounds: Some(Interval::new(0.576, 0.578)),
+                    },
+                ),
+                ("k2".to_string(), 0.422.into()),
+            ]),
+            initial: BTreeMap::new(),
+            initial_time: 0.0,
+            solver: None,
+            step: None,
+            t_max: None,
+            outputs: Outputs {
+                csv: Some("data.csv".into()),
+                plot: Some("plot.svg".into()),
+                manifest: Some("manifest.toml".into()),
+            },
+        }
+    }
+
+    pub fn csv(&self) -> PathBuf {
+        self.outputs.csv.clone().unwrap_or_else(|| format!("{}.csv", self.name).into())
+    }
+
+    pub fn plot(&self) -> PathBuf {
+        self.outputs.plot.clone().unwrap_or_else(|| format!("{}.svg", self.name).into())
+    }
+
+    pub fn manifest(&self) -> PathBuf {
+        self.outputs.manifest.clone().unwrap_or_else(|| format!("{}.manifest.toml", self.name).into())
+    }
+
+    /// Values which make no sense, with keys within the experiment. `general_t_max` is used if
+    /// the experiment has no `t_max`, it is not reported if invalid itself.
+    pub fn problems(&self, general_t_max: f64) -> Vec<Problem> {
+        let mut problems = vec![];
+        let mut require = |valid: bool, keys: &[&str], message: String| {
+            if !valid {
+                problems.push(Problem::new(keys, message));
+            }
+        };
+        let t_max = self.t_max.unwrap_or(general_t_max);
+        let inherits_invalid = self.t_max.is_none() && !(general_t_max > 0.0 && general_t_max.is_finite());
+        require(
+            t_max > self.initial_time && t_max.is_finite() || inherits_invalid,
+            &[if self.t_max.is_some() { "t_max" } else { "initial_time" }],
+            format!("Experiment `{}` should end after its start {}, got {t_max}", self.name, self.initial_time),
+        );
+        require(
+            self.initial_time.is_finite(),
+            &["initial_time"],
+            format!("Experiment `{}` should start at a finite time, got {}", self.name, self.initial_time),
+        );
+        if let Some(step) = self.step {
+            require(
+                step > 0.0 && step.is_finite(),
+                &["step"],
+                format!("Step of experiment `{}` should be positive, got {step}", self.name),
+            );
+        }
+        for (table, values) in [("parameters", &self.parameters), ("initial", &self.initial)] {
+            for (name, value) in values {
+                let finite = value.nominal.is_finite()
+                    && value.bounds.is_none_or(|it| it.start().is_finite() && it.end().is_finite());
+                require(
+                    finite,
+                    &[table, name],
+                    format!("`{table}.{name}` of experiment `{}` should be finite", self.name),
+                );
+            }
+        }
+        problems
+    }
+}
+
+/// Runs of an experiment for every combination of values, each into its own subdirectory of
+/// `general.output_dir`
+#[derive(Serialize, Deserialize)]
+pub struct Sweep {
+    /// Name of the swept experiment, the first one by default
+    pub experiment: Option<String>,
+    /// Values of every key within the experiment, e.g. `parameters.k1`, `initial.x1` or `step`
+    pub values: BTreeMap<String, Values>,
+    #[serde(default)]
+    pub combine: Combine,
+    /// Zero means available parallelism
+    #[serde(default = "def_threads")]
+    pub threads: usize,
+    /// Quantities of every variable written to the summary
+    #[serde(default = "def_summary")]
+    pub summary: Vec<Quantity>,
+    /// Threshold of a variable by its name, time when it is first reached is written to the summary
+    #[serde(default)]
+    pub events: BTreeMap<String, f64>,
+}
+
+/// Values of a swept key: `[1, 2, 5]`, `{ linspace = [start, end, count] }` or
+/// `{ logspace = [start, end, count] }` with decimal exponents
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Values {
+    List(Vec<f64>),
+    Linspace { linspace: (f64, f64, usize) },
+    Logspace { logspace: (f64, f64, usize) },
+}
+
+impl Values {
+    pub fn expand(&self) -> Vec<f64> {
+        let space = |(start, end, count): (f64, f64, usize)| {
+            (0..count).map(move |i| match count {
+                1 => start,
+                _ => start + (end - start) * i as f64 / (count - 1) as f64,
+            })
+        };
+        match self {
+            Values::List(it) => it.clone(),
+            Values::Linspace { linspace } => space(*linspace).collect(),
+            Values::Logspace { logspace } => space(*logspace).map(|it| 10f64.powf(it)).collect(),
+        }
+    }
+}
+
+/// How values of several keys are combined into runs
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Combine {
+    /// Every combination of values
+    #[default]
+    Product,
+    /// Values with the same index, so every key should have the same amount of them
+    Zip,
+}
+
+/// Side-by-side comparison of several solvers on the same task
+#[derive(Serialize, Deserialize)]
+pub struct Compare {
+    /// Names of solvers, each configured by its own `[solvers.<name>]` section
+    pub solvers: Vec<String>,
+    /// Name of the solved experiment, the first one by default
+    pub experiment: Option<String>,
+}
+
+/// Forward sensitivity analysis of the reaction rate constants
+#[derive(Serialize, Deserialize)]
+pub struct Sensitivity {
+    #[serde(default = "def_rates")]
+    pub parameters: [f64; 2],
+}
+
+/// Fitting of the reaction rate constants to measured data
+#[derive(Serialize, Deserialize)]
+pub struct Fitting {
+    /// Csv file with measurements, see [`project::fitting::Observations::from_csv`]
+    pub data: PathBuf,
+    #[serde(default = "def_rates")]
+    pub initial: [f64; 2],
+    /// Weight of every component in the least squares
+    #[serde(default)]
+    pub weights: Vec<f64>,
+    #[serde(default)]
+    pub method: FitMethod,
+    #[serde(default = "def_max_iterations")]
+    pub max_iterations: usize,
+}
+
+#[derive(Serialize, Deserialize, Default, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitMethod {
+    #[default]
+    LevenbergMarquardt,
+    NelderMead,
+}
+
+/// Approximate set inversion of the reaction rate constants against measured data, see
+/// [`project::sivia`]
+#[derive(Serialize, Deserialize)]
+pub struct Sivia {
+    /// Csv file with measurements, see [`project::fitting::Observations::from_csv`]
+    pub data: PathBuf,
+    /// Absolute error bound of every measurement
+    pub error: f64,
+    /// Initial box of the rate constants
+    pub domain: [Range<f64>; 2],
+    #[serde(default = "def_epsilon")]
+    pub epsilon: f64,
+}
+
+/// Global sensitivity of a scalar output to the reaction rate constants
+#[derive(Serialize, Deserialize)]
+pub struct GlobalSensitivity {
+    #[serde(default)]
+    pub method: GlobalMethod,
+    /// Ranges of the rate constants to sample from
+    pub ranges: [Range<f64>; 2],
+    #[serde(default)]
+    pub quantity: Quantity,
+    /// One-based index of the component, which quantity is derived from
+    pub component: usize,
+    #[serde(default = "def_samples")]
+    pub samples: usize,
+    #[serde(default)]
+    pub seed: u64,
+    /// Zero means available parallelism
+    #[serde(default)]
+    pub threads: usize,
+    /// Amount of grid levels for Morris screening
+    #[serde(default = "def_levels")]
+    pub levels: usize,
+}
+
+#[derive(Serialize, Deserialize, Default, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum GlobalMethod {
+    #[default]
+    Sobol,
+    Morris,
+}
+
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quantity {
+    /// Value at the end of the simulation
+    #[default]
+    Final,
+    /// Maximum value
+    Peak,
+    /// Time when maximum value is reached
+    PeakTime,
+}
+
+/// Ensemble of the reaction with multiplicative noise `sigma_i * x_i dW_i`
+#[derive(Serialize, Deserialize)]
+pub struct Stochastic {
+    #[serde(default)]
+    pub scheme: StochasticScheme,
+    /// Noise intensity `sigma_i` of every component
+    pub noise: [f64; 3],
+    #[serde(default = "def_rates")]
+    pub parameters: [f64; 2],
+    #[serde(default = "def_step")]
+    pub step: f64,
+    #[serde(default = "def_paths")]
+    pub paths: usize,
+    #[serde(default)]
+    pub seed: u64,
+    /// Lower and upper quantiles of the band drawn around the mean
+    #[serde(default = "def_quantiles")]
+    pub quantiles: (f64, f64),
+}
+
+#[derive(Serialize, Deserialize, Default, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum StochasticScheme {
+    #[default]
+    EulerMaruyama,
+    Milstein,
+    RungeKutta,
+}
+
+fn def_threads() -> usize {
+    1
+}
+
+fn def_summary() -> Vec<Quantity> {
+    vec![Quantity::Final, Quantity::Peak, Quantity::PeakTime]
+}
+
+fn def_step() -> f64 {
+    0.01
+}
+
+fn def_paths() -> usize {
+    100
+}
+
+fn def_quantiles() -> (f64, f64) {
+    (0.05, 0.95)
+}
+
+fn def_samples() -> usize {
+    1024
+}
+
+fn def_levels() -> usize {
+    4
+}
+
+fn def_epsilon() -> f64 {
+    1e-3
+}
+
+fn def_max_iterations() -> usize {
+    100
+}
+
+fn def_rates() -> [f64; 2] {
+    [0.577, 0.422]
+}
+
 fn def_plot_size() -> (u32, u32) {
     (640, 480)
 }
@@ -63,10 +803,19 @@ fn def_output_dir() -> PathBuf {
     PathBuf::from("./out/")
 }
 
+fn def_sandbox_timeout() -> f64 {
+    10.0
+}
+
 fn def_t_max() -> f64 {
     1.0
 }
 
+/// Finite range with its start before its end
+fn is_range(range: &Range<f64>) -> bool {
+    range.start.is_finite() && range.end.is_finite() && range.start < range.end
+}
+
 impl Default for Viewport {
     fn default() -> Self {
         Self {
@@ -92,16 +841,109 @@ impl Default for Runtime {
             t_max: def_t_max(),
             output_dir: def_output_dir(),
             lib_dir: def_lib_dir(),
-            solver: "builtin".to_string(),
+            plugin_dirs: vec![],
+            solver: BUILTIN.to_string(),
+            sandbox: false,
+            sandbox_timeout: def_sandbox_timeout(),
         }
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            general: Default::default(),
-            plotting: Default::default(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> Result<Uncertain, String> {
+        #[derive(Deserialize)]
+        struct Value {
+            value: Uncertain,
         }
+        toml::from_str::<Value>(&format!("value = {value}"))
+            .map(|it| it.value)
+            .map_err(|e| e.message().to_string())
+    }
+
+    #[test]
+    fn uncertain_values() {
+        assert_eq!(parse("0.577"), Ok(Uncertain::from(0.577)));
+        assert_eq!(parse("2"), Ok(Uncertain::from(2.0)));
+        let expected = Uncertain {
+            nominal: 0.5,
+            bounds: Some(Interval::new(0.25, 0.75)),
+        };
+        assert_eq!(parse("\"0.5 ± 0.25\""), Ok(expected));
+        assert_eq!(parse("\"0.5 +- 0.25\""), Ok(expected));
+        assert_eq!(parse("[0.25, 0.75]"), Ok(expected));
+        assert_eq!(
+            parse("[1, 1]"),
+            Ok(Uncertain {
+                nominal: 1.0,
+                bounds: Some(Interval::from(1.0)),
+            })
+        );
+    }
+
+    #[test]
+    fn uncertain_round_trip() {
+        #[derive(Serialize, Deserialize)]
+        struct Value {
+            value: Uncertain,
+        }
+        for text in ["0.577", "\"0.1 ± 0.7\"", "[0.25, 0.75]", "{ nominal = 0.3, bounds = [0.25, 0.75] }"] {
+            let value = parse(text).unwrap();
+            let written = toml::to_string(&Value { value }).unwrap();
+            assert_eq!(toml::from_str::<Value>(&written).unwrap().value, value, "{text} is written as {written}");
+        }
+        assert_eq!(
+            parse("{ nominal = 1, bounds = [0.25, 0.75] }"),
+            Err("Nominal 1 is outside of the bounds [0.25, 0.75]".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_uncertain_values() {
+        assert_eq!(parse("\"1 ± -0.1\""), Err("Radius of `1 ± -0.1` should not be negative".to_string()));
+        assert_eq!(parse("[2, 1]"), Err("Interval [2, 1] is empty".to_string()));
+        assert_eq!(parse("\"1 ± x\"").unwrap_err(), "Invalid number `x` in `1 ± x`: invalid float literal");
+        assert_eq!(parse("\"1\""), Err("Expected `<center> ± <radius>`, got `1`".to_string()));
+    }
+
+    #[test]
+    fn experiment_problems() {
+        let mut experiment = Experiment::reaction();
+        assert!(experiment.problems(10.0).is_empty());
+        // Invalid inherited `t_max` is reported by `general` itself
+        assert!(experiment.problems(-1.0).is_empty());
+
+        experiment.step = Some(0.0);
+        experiment.t_max = Some(-1.0);
+        experiment.parameters.insert("k2".to_string(), f64::INFINITY.into());
+        let keys = experiment.problems(10.0).into_iter().map(|it| it.keys.join(".")).collect::<Vec<_>>();
+        assert_eq!(keys, ["t_max", "step", "parameters.k2"]);
+    }
+
+    #[test]
+    fn config_problems() {
+        let text = r#"
+            [general]
+            solver = "euler"
+            t_max = 10
+            sandbox_timeout = inf
+
+            [fitting]
+            data = "data.csv"
+            weights = [1, -1, nan]
+
+            [global_sensitivity]
+            ranges = [{ start = 0, end = 1 }, { start = 0, end = 1 }]
+            component = 1
+            levels = 1
+        "#;
+        let config = Config::parse(None, text.to_string(), &[]).unwrap();
+        let keys = config.problems().into_iter().map(|it| it.keys.join(".")).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            ["general.sandbox_timeout", "fitting.weights.1", "fitting.weights.2", "global_sensitivity.levels"]
+        );
     }
 }
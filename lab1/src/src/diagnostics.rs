@@ -0,0 +1,280 @@
+use crate::config::{
+    Compare, Config, Experiment, Fitting, GlobalSensitivity, Outputs, Plot, Runtime, Sensitivity, Sivia,
+    Stochastic, Sweep, Viewport,
+};
+use project::plugin::suggest;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Problem of the config value at `keys`, where items of arrays are given by their index,
+/// e.g. `["experiment", "0", "t_max"]`
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub keys: Vec<String>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(keys: &[&str], message: impl Into<String>) -> Self {
+        Self {
+            keys: keys.iter().map(|it| it.to_string()).collect(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Text of the config file and keys set on the command line, which problems are located by
+#[derive(Default)]
+pub struct Source {
+    path: Option<PathBuf>,
+    text: String,
+    overridden: Vec<Vec<String>>,
+    /// Keys which no part of the config has, they are reported together with other problems
+    pub unknown_keys: Vec<Problem>,
+}
+
+impl Source {
+    pub fn new(path: Option<PathBuf>, text: String, overridden: Vec<Vec<String>>) -> Self {
+        Self {
+            path,
+            text,
+            overridden,
+            unknown_keys: vec![],
+        }
+    }
+
+    /// `file:line:column` of a byte offset in the file
+    fn position(&self, offset: usize) -> Option<String> {
+        let path = self.path.as_ref()?;
+        let before = &self.text[..offset.min(self.text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+        Some(format!("{}:{line}:{column}", path.display()))
+    }
+
+    /// Span of the deepest of `keys` which is written in the file
+    fn span(&self, keys: &[String]) -> Option<Range<usize>> {
+        let document = toml_edit::ImDocument::parse(self.text.as_str()).ok()?;
+        let mut item = document.as_item();
+        let mut span = None;
+        for key in keys {
+            let found = match key.parse::<usize>() {
+                Ok(index) if item.is_array() || item.is_array_of_tables() => item.get(index).map(|it| (it.span(), it)),
+                _ => item
+                    .as_table_like()
+                    .and_then(|it| it.get_key_value(key))
+                    .map(|(key, it)| (key.span().or_else(|| it.span()), it)),
+            };
+            let Some((found, next)) = found else { break };
+            span = found.or(span);
+            item = next;
+        }
+        span
+    }
+
+    /// Where the value at `keys` comes from: the option which has set it or its position in the file
+    fn locate(&self, keys: &[String]) -> Option<String> {
+        match self.overridden.iter().find(|it| keys.starts_with(it)) {
+            Some(path) => Some(format!("--set {}", path.join("."))),
+            None => self.position(self.span(keys)?.start),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Orders problems by their position in the file, ones which are not in the file keep their
+    /// order after the others
+    pub fn sort(&self, problems: &mut [Problem]) {
+        problems.sort_by_cached_key(|it| {
+            let overridden = self.overridden.iter().any(|path| it.keys.starts_with(path));
+            match self.span(&it.keys).filter(|_| !overridden) {
+                Some(span) => (0, span.start),
+                None => (1, 0),
+            }
+        });
+    }
+
+    pub fn render(&self, problem: &Problem) -> String {
+        match self.locate(&problem.keys) {
+            Some(location) => format!("{location}: {}", problem.message),
+            None => problem.message.clone(),
+        }
+    }
+
+    /// Error of the TOML parser at its span in the file
+    pub fn render_error(&self, error: &toml::de::Error) -> String {
+        let message = error.message().trim_end();
+        match error.span().and_then(|it| self.position(it.start)) {
+            Some(location) => format!("{location}: {message}"),
+            None => message.to_string(),
+        }
+    }
+}
+
+/// Keys of tables which no part of the config has, each with the closest known key if it looks
+/// like a misspelling
+pub fn unknown_keys(table: &toml::Table) -> Vec<Problem> {
+    let mut problems = vec![];
+    visit(&mut vec![], &toml::Value::Table(table.clone()), &mut problems);
+    problems
+}
+
+fn visit(keys: &mut Vec<String>, value: &toml::Value, problems: &mut Vec<Problem>) {
+    let children: Vec<(String, &toml::Value)> = match value {
+        toml::Value::Table(table) => {
+            let known = known_keys(&keys.iter().map(String::as_str).collect::<Vec<_>>());
+            table
+                .iter()
+                .filter(|(key, _)| match known {
+                    Some(known) if !known.contains(&key.as_str()) => {
+                        problems.push(unknown_key(keys, key, known));
+                        false
+                    }
+                    _ => true,
+                })
+                .map(|(key, value)| (key.clone(), value))
+                .collect()
+        }
+        toml::Value::Array(array) => array.iter().enumerate().map(|(i, it)| (i.to_string(), it)).collect(),
+        _ => return,
+    };
+    for (key, value) in children {
+        keys.push(key);
+        visit(keys, value, problems);
+        keys.pop();
+    }
+}
+
+fn unknown_key(keys: &[String], key: &str, known: &[&str]) -> Problem {
+    let place = match keys {
+        [] => "at the top level".to_string(),
+        keys => format!("in `{}`", keys.join(".")),
+    };
+    let hint = match suggest(key, known.iter().copied()) {
+        Some(similar) => format!("did you mean `{similar}`?"),
+        None => format!("known are: {}", known.join(", ")),
+    };
+    let mut keys = keys.iter().map(String::as_str).collect::<Vec<_>>();
+    keys.push(key);
+    Problem::new(&keys, format!("Unknown key `{key}` {place}, {hint}"))
+}
+
+/// Fields of the table at `keys`, this follows the nesting of [`Config`] and its parts,
+/// tables with arbitrary keys such as `[solvers]` are not checked, as well as ranges, which
+/// deserialization of already rejects unknown keys
+fn known_keys(keys: &[&str]) -> Option<&'static [&'static str]> {
+    Some(match keys {
+        [] => fields::<Config>(),
+        ["general"] => fields::<Runtime>(),
+        ["plotting"] => fields::<Plot>(),
+        ["plotting", "viewport"] => fields::<Viewport>(),
+        ["experiment", _] => fields::<Experiment>(),
+        ["experiment", _, "outputs"] => fields::<Outputs>(),
+        ["sweep"] => fields::<Sweep>(),
+        ["compare"] => fields::<Compare>(),
+        ["sensitivity"] => fields::<Sensitivity>(),
+        ["fitting"] => fields::<Fitting>(),
+        ["sivia"] => fields::<Sivia>(),
+        ["global_sensitivity"] => fields::<GlobalSensitivity>(),
+        ["stochastic"] => fields::<Stochastic>(),
+        _ => return None,
+    })
+}
+
+/// Names of the fields of a struct, which its derived [`Deserialize`] gives to the deserializer
+fn fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    match T::deserialize(FieldsProbe) {
+        Err(Fields(Some(fields))) => fields,
+        _ => &[],
+    }
+}
+
+/// Deserializer which only asks for the fields of a struct and fails
+struct FieldsProbe;
+
+#[derive(Debug)]
+struct Fields(Option<&'static [&'static str]>);
+
+impl Display for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Only fields of a struct can be probed")
+    }
+}
+
+impl std::error::Error for Fields {}
+
+impl de::Error for Fields {
+    fn custom<T: Display>(_: T) -> Self {
+        Self(None)
+    }
+}
+
+impl<'de> Deserializer<'de> for FieldsProbe {
+    type Error = Fields;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Fields> {
+        Err(Fields(None))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Fields> {
+        Err(Fields(Some(fields)))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn rendered(text: &str) -> Vec<String> {
+        let config = Config::parse(Some(Path::new("config.toml")), text.to_string(), &[]).unwrap();
+        config.source.unknown_keys.iter().map(|it| config.source.render(it)).collect()
+    }
+
+    #[test]
+    fn unknown_keys_are_located() {
+        let text = "[general]\nsolver = \"builtin\"\nsandbox_timout = 5\n\n\
+                    [[experiment]]\nname = \"base\"\n  stpe = 0.1\n";
+        assert_eq!(
+            rendered(text),
+            [
+                "config.toml:3:1: Unknown key `sandbox_timout` in `general`, did you mean `sandbox_timeout`?",
+                "config.toml:7:3: Unknown key `stpe` in `experiment.0`, did you mean `step`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_without_similar_ones_list_known_keys() {
+        let problem = unknown_key(&[], "zzz", &["general", "sweep"]);
+        assert_eq!(problem.keys, ["zzz"]);
+        assert_eq!(problem.message, "Unknown key `zzz` at the top level, known are: general, sweep");
+        assert!(rendered("[solvers.euler]\nanything = 1\n").is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_accompany_deserialization_errors() {
+        let text = "[general]\nt_mx = 10\nsolver = 1\n";
+        let Err(error) = Config::parse(Some(Path::new("config.toml")), text.to_string(), &[]) else {
+            panic!("Config should be invalid");
+        };
+        let lines = error.to_string().lines().map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(lines[0], "Invalid config");
+        assert_eq!(lines[1], "config.toml:2:1: Unknown key `t_mx` in `general`, did you mean `t_max`?");
+        assert!(lines[2].starts_with("config.toml:3:10: "), "{error}");
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use crate::task::Sentinel;
+
+/// Dual number `a + b * e`, where `e^2 = 0`.
+/// Evaluating a function on `x + e` yields `f(x) + f'(x) * e`, which is used for
+/// forward-mode automatic differentiation.
+#[derive(Debug, Default, Hash, Clone, Copy, PartialOrd, PartialEq)]
+#[repr(C)]
+pub struct Dual<T>(T, T);
+
+impl<T> Dual<T> {
+    pub fn new(value: T, derivative: T) -> Self {
+        Self(value, derivative)
+    }
+
+    pub fn into_inner(self) -> (T, T) {
+        (self.0, self.1)
+    }
+    pub fn value(self) -> T { self.0 }
+    pub fn derivative(self) -> T { self.1 }
+}
+
+impl<T: Add> Add for Dual<T> {
+    type Output = Dual<T::Output>;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self::Output {
+        Dual(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl<T: Sub> Sub for Dual<T> {
+    type Output = Dual<T::Output>;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self::Output {
+        Dual(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl<T> Mul for Dual<T>
+where
+    T: Copy + Mul<Output = T> + Add<Output = T>,
+{
+    type Output = Dual<T>;
+
+    #[inline]
+    fn mul(self, rhs: Self) -> Self::Output {
+        Dual(self.0 * rhs.0, self.0 * rhs.1 + self.1 * rhs.0)
+    }
+}
+
+impl Mul<Dual<f64>> for f64 {
+    type Output = Dual<f64>;
+
+    fn mul(self, rhs: Dual<f64>) -> Self::Output {
+        Dual(rhs.0 * self, rhs.1 * self)
+    }
+}
+
+impl<T> Div for Dual<T>
+where
+    T: Copy + Mul<Output = T> + Sub<Output = T> + Div<Output = T>,
+{
+    type Output = Dual<T>;
+
+    #[inline]
+    fn div(self, rhs: Self) -> Self::Output {
+        Dual(
+            self.0 / rhs.0,
+            (self.1 * rhs.0 - self.0 * rhs.1) / (rhs.0 * rhs.0),
+        )
+    }
+}
+
+impl<T: Neg> Neg for Dual<T> {
+    type Output = Dual<T::Output>;
+
+    fn neg(self) -> Self::Output {
+        Dual(-self.0, -self.1)
+    }
+}
+
+impl<T: Sentinel> Sentinel for Dual<T> {
+    fn sentinel() -> Self {
+        Dual(T::sentinel(), T::sentinel())
+    }
+}
+
+impl From<f64> for Dual<f64> {
+    fn from(value: f64) -> Self {
+        Self(value, 0.0)
+    }
+}
+
+impl<T: Display> Display for Dual<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(precision) = f.precision() {
+            write!(f, "{:.*} + {:.*}e", precision, self.0, precision, self.1)
+        } else {
+            write!(f, "{} + {}e", self.0, self.1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differentiates_rational_function() {
+        // f(x) = (x^2 + 3) / (x - 1), f'(x) = (x^2 - 2x - 3) / (x - 1)^2
+        let x = Dual::new(2.0, 1.0);
+        let y = (x * x + Dual::from(3.0)) / (x - Dual::from(1.0));
+        assert_eq!(y.into_inner(), (7.0, -3.0));
+    }
+
+    #[test]
+    fn scales_and_negates() {
+        let x = Dual::new(1.5, 2.0);
+        assert_eq!((3.0 * x).into_inner(), (4.5, 6.0));
+        assert_eq!((-x).into_inner(), (-1.5, -2.0));
+    }
+}
@@ -0,0 +1,327 @@
+use crate::config::{Experiment, Uncertain};
+use crate::manifest::Manifest;
+use crate::plot::Plotter;
+use crate::{build_line, build_line_interval, solver_parameters, solver_with, CONFIG, PLUGINS};
+use anyhow::{bail, Context, Error};
+use plotters::prelude::{Color, BLACK, BLUE, CYAN, GREEN, MAGENTA, RED, YELLOW};
+use project::expression::Equations;
+use project::ffi::{CanSolve, ExternalSolver};
+use project::interval::Interval;
+use project::model::Number;
+use project::plugin::{suggest, Parameters, Registry};
+use project::sandbox::Wire;
+use project::solution::{Solution, StopCondition};
+use project::solver::Solver;
+use project::task::CauchyTask;
+use project::Frozen;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::Write;
+use std::ops::Mul;
+use std::path::Path;
+use std::time::Instant;
+
+/// Model which experiments can refer to by name instead of giving equations
+struct Builtin {
+    name: &'static str,
+    equations: &'static [&'static str],
+    /// Initial conditions which differ from zero
+    initial: &'static [(&'static str, f64)],
+}
+
+const MODELS: &[Builtin] = &[
+    Builtin {
+        name: "reaction",
+        equations: &["x1' = -k1 * x1", "x2' = k1 * x1 - k2 * x2", "x3' = k2 * x2"],
+        initial: &[("x1", 1.0)],
+    },
+    Builtin {
+        name: "lotka-volterra",
+        equations: &["x' = a * x - b * x * y", "y' = d * x * y - c * y"],
+        initial: &[("x", 1.0), ("y", 1.0)],
+    },
+    Builtin {
+        name: "oscillator",
+        equations: &["x' = v", "v' = -omega^2 * x"],
+        initial: &[("x", 1.0)],
+    },
+];
+
+/// Experiments from the config or the default one if there are none
+pub fn configured() -> Cow<'static, [Experiment]> {
+    if CONFIG.experiments.is_empty() {
+        Cow::Owned(vec![Experiment::reaction()])
+    } else {
+        Cow::Borrowed(&CONFIG.experiments)
+    }
+}
+
+/// Configured experiment called `name` or the first one
+pub fn select(name: Option<&str>) -> Result<Experiment, Error> {
+    let experiments = configured();
+    let Some(name) = name else {
+        return Ok(experiments[0].clone());
+    };
+    match experiments.iter().find(|it| it.name == name) {
+        Some(experiment) => Ok(experiment.clone()),
+        None => match suggest(name, experiments.iter().map(|it| it.name.as_str())) {
+            Some(similar) => bail!("Unknown experiment `{name}`, did you mean `{similar}`?"),
+            None => bail!(
+                "Unknown experiment `{name}`, configured are: {}",
+                experiments.iter().map(|it| it.name.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+        },
+    }
+}
+
+/// Experiment with parsed equations and values in their order
+pub struct Prepared<'a> {
+    pub experiment: &'a Experiment,
+    /// Equations of the experiment or of its model
+    pub sources: Vec<String>,
+    pub equations: Equations,
+    pub parameters: Vec<Uncertain>,
+    pub initial: Vec<Uncertain>,
+}
+
+impl<'a> Prepared<'a> {
+    pub fn new(experiment: &'a Experiment) -> Result<Self, Error> {
+        let (sources, defaults) = match &experiment.model {
+            Some(_) if !experiment.equations.is_empty() => {
+                bail!("Either `model` or `equations` should be given, not both")
+            }
+            Some(name) => {
+                let Some(model) = MODELS.iter().find(|it| it.name == name) else {
+                    match suggest(name, MODELS.iter().map(|it| it.name)) {
+                        Some(similar) => bail!("Unknown model `{name}`, did you mean `{similar}`?"),
+                        None => bail!(
+                            "Unknown model `{name}`, available are: {}",
+                            MODELS.iter().map(|it| it.name).collect::<Vec<_>>().join(", ")
+                        ),
+                    }
+                };
+                (model.equations.iter().map(|it| it.to_string()).collect(), model.initial)
+            }
+            None if experiment.equations.is_empty() => bail!("Either `model` or `equations` should be given"),
+            None => (experiment.equations.clone(), &[][..]),
+        };
+
+        let equations = Equations::parse(&sources, &experiment.parameters.keys().collect::<Vec<_>>())?;
+        if let Some(name) = experiment.initial.keys().find(|it| !equations.variables().contains(it)) {
+            bail!(
+                "Initial value is given for unknown variable `{name}`, variables are: {}",
+                equations.variables().join(", ")
+            );
+        }
+        let initial = equations
+            .variables()
+            .iter()
+            .map(|name| {
+                let default = defaults.iter().find(|(it, _)| it == name).map_or(0.0, |(_, value)| *value);
+                experiment.initial.get(name).copied().unwrap_or(default.into())
+            })
+            .collect();
+        Ok(Self {
+            experiment,
+            parameters: experiment.parameters.values().copied().collect(),
+            sources,
+            equations,
+            initial,
+        })
+    }
+
+    pub fn solver(&self) -> &'a str {
+        self.experiment.solver.as_deref().unwrap_or(&CONFIG.general.solver)
+    }
+
+    /// Whether any parameter or initial condition is an interval
+    pub fn is_uncertain(&self) -> bool {
+        self.parameters.iter().chain(&self.initial).any(|it| it.bounds.is_some())
+    }
+
+    pub fn t_max(&self) -> f64 {
+        self.experiment.t_max.unwrap_or(CONFIG.general.t_max)
+    }
+
+    pub fn stop(&self) -> StopCondition<f64> {
+        StopCondition::Timed { maximum: self.t_max() }
+    }
+
+    /// Parameters from the section of the solver with the step of the experiment
+    pub fn solver_parameters(&self) -> Result<Parameters, Error> {
+        self.parameters_of(&PLUGINS, self.solver())
+    }
+
+    /// Parameters from the section of solver `name` from `registry` with the step of the experiment
+    pub fn parameters_of(&self, registry: &Registry, name: &str) -> Result<Parameters, Error> {
+        let mut parameters = solver_parameters(registry, name)?;
+        if let Some(step) = self.experiment.step {
+            if !(step > 0.0 && step.is_finite()) {
+                bail!("Step of experiment `{}` should be positive, got {step}", self.experiment.name);
+            }
+            parameters.step = step;
+        }
+        Ok(parameters)
+    }
+
+    /// Task with nominal values of everything
+    pub fn task(&self) -> CauchyTask<f64, f64> {
+        let nominal = |values: &[Uncertain]| values.iter().map(|it| it.nominal).collect::<Vec<_>>();
+        self.equations.build(
+            &nominal(&self.parameters),
+            self.experiment.initial_time,
+            nominal(&self.initial),
+        )
+    }
+
+    /// Solution with nominal values of everything
+    pub fn solve(&self) -> Result<Solution<f64, f64>, Error> {
+        self.compute(self.task())
+    }
+
+    /// Solution with nominal values of everything, which is computed by `solver` instead of the
+    /// solver of the experiment
+    pub fn solve_with<S: Solver<f64, f64>>(&self, solver: Frozen<&mut S>) -> Result<Solution<f64, f64>, Error> {
+        Solution::compute(solver, &self.task(), self.stop())
+    }
+
+    /// Solution with intervals, where exact values are degenerate intervals
+    pub fn solve_interval(&self) -> Result<Solution<f64, Interval<f64>>, Error> {
+        let interval = |values: &[Uncertain]| values.iter().map(|it| it.interval()).collect::<Vec<_>>();
+        self.compute(self.equations.build(
+            &interval(&self.parameters),
+            self.experiment.initial_time,
+            interval(&self.initial),
+        ))
+    }
+
+    fn compute<N>(&self, task: CauchyTask<f64, N>) -> Result<Solution<f64, N>, Error>
+    where
+        for<'b> ExternalSolver<'b, f64, N>: CanSolve<f64, N>,
+        N: Number + Wire,
+        f64: Mul<N, Output = N>,
+    {
+        let solver = solver_with(&PLUGINS, self.solver(), &self.solver_parameters()?);
+        Solution::compute(solver?.as_mut(), &task, self.stop())
+    }
+}
+
+/// Solves the experiment and writes its csv and plot into `dir` if they are requested together
+/// with the manifest of the run, returns the nominal solution
+pub fn run(experiment: &Experiment, dir: &Path, csv: bool, plot: bool) -> Result<Solution<f64, f64>, Error> {
+    let prepared = Prepared::new(experiment)?;
+    let started = Instant::now();
+    let solution = prepared.solve()?;
+    let nominal = started.elapsed();
+    let started = Instant::now();
+    let interval = prepared.is_uncertain().then(|| prepared.solve_interval()).transpose()?;
+    let elapsed = (nominal, interval.as_ref().map(|_| started.elapsed()));
+    if interval.as_ref().is_some_and(|it| it.time() != solution.time()) {
+        bail!("Interval solution has different points than the nominal one");
+    }
+
+    let variables = prepared.equations.variables();
+    let last = variables
+        .iter()
+        .enumerate()
+        .map(|(i, name)| format!("{name} = {}", solution[i].last().copied().unwrap_or(f64::NAN)));
+    println!(
+        "Experiment `{}`: {} points with `{}`, final values: {}",
+        experiment.name,
+        solution.time().len(),
+        prepared.solver(),
+        last.collect::<Vec<_>>().join(", ")
+    );
+
+    let mut outputs = vec![];
+    if csv {
+        let path = dir.join(experiment.csv());
+        create_parent(&path)?;
+        write_csv(variables, &solution, interval.as_ref(), &path)
+            .with_context(|| format!("Could not write {}", path.display()))?;
+        outputs.push(path);
+    }
+    if plot {
+        let path = dir.join(experiment.plot());
+        create_parent(&path)?;
+        draw(variables, &solution, interval.as_ref(), &path)?;
+        outputs.push(path.with_extension(CONFIG.plotting.output_type.extension()));
+    }
+    if !outputs.is_empty() {
+        let path = dir.join(experiment.manifest());
+        create_parent(&path)?;
+        Manifest::new(&prepared, &solution, elapsed, dir, &outputs)?.write(&path)?;
+    }
+    Ok(solution)
+}
+
+/// Outputs may be in subdirectories of the run directory, which do not exist in a new one
+fn create_parent(path: &Path) -> Result<(), Error> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).with_context(|| format!("Could not create {}", parent.display())),
+        None => Ok(()),
+    }
+}
+
+fn write_csv(
+    variables: &[String],
+    solution: &Solution<f64, f64>,
+    interval: Option<&Solution<f64, Interval<f64>>>,
+    path: &Path,
+) -> Result<(), Error> {
+    let mut csv = File::create(path)?;
+    write!(csv, "t")?;
+    for name in variables {
+        write!(csv, ", {name}")?;
+    }
+    if interval.is_some() {
+        for name in variables {
+            write!(csv, ", {name}_start, {name}_end")?;
+        }
+    }
+    writeln!(csv)?;
+
+    for (idx, t) in solution.time().iter().enumerate() {
+        write!(csv, "{t}")?;
+        for i in 0..variables.len() {
+            write!(csv, ", {}", solution[i][idx])?;
+        }
+        if let Some(interval) = interval {
+            for i in 0..variables.len() {
+                write!(csv, ", {}, {}", interval[i][idx].start(), interval[i][idx].end())?;
+            }
+        }
+        writeln!(csv)?;
+    }
+    Ok(())
+}
+
+fn draw(
+    variables: &[String],
+    solution: &Solution<f64, f64>,
+    interval: Option<&Solution<f64, Interval<f64>>>,
+    path: &Path,
+) -> Result<(), Error> {
+    let colors = [RED, GREEN, BLUE, MAGENTA, CYAN, BLACK, YELLOW];
+    let ts = solution.time();
+    let bounds = interval.into_iter().flat_map(|interval| {
+        variables.iter().enumerate().flat_map(move |(i, name)| {
+            build_line_interval(ts, &interval[i], &colors[i % colors.len()], name.as_str(), false)
+        })
+    });
+    let nominal = variables
+        .iter()
+        .enumerate()
+        .flat_map(|(i, name)| build_line(ts, &solution[i], colors[i % colors.len()].stroke_width(2), name.as_str()));
+
+    Plotter::new(
+        path,
+        CONFIG.plotting.plot_size,
+        (
+            CONFIG.plotting.viewport.x.clone(),
+            CONFIG.plotting.viewport.y.clone(),
+        ),
+        bounds.chain(nominal),
+    )
+    .draw(CONFIG.plotting.output_type)
+}
@@ -0,0 +1,357 @@
+use crate::model::Number;
+use crate::task::{CauchyTask, Function};
+use anyhow::{anyhow, bail, Context, Error};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// Arithmetic expression with `+`, `-`, `*`, `/`, natural powers `^` and parentheses, which
+/// can be evaluated with any [`Number`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression(Node);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Constant(f64),
+    /// Index of the variable in the list given to [`Expression::parse`]
+    Variable(usize),
+    Negate(Box<Node>),
+    Add(Box<Node>, Box<Node>),
+    Subtract(Box<Node>, Box<Node>),
+    Multiply(Box<Node>, Box<Node>),
+    Divide(Box<Node>, Box<Node>),
+    Power(Box<Node>, u32),
+}
+
+/// Recursive descent parser over characters of the source, which positions are reported in
+/// errors
+struct Parser<'a, S> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    variables: &'a [S],
+}
+
+impl Expression {
+    /// Parses `source`, where every identifier should be one of `variables`
+    pub fn parse(source: &str, variables: &[impl AsRef<str>]) -> Result<Self, Error> {
+        let mut parser = Parser {
+            source,
+            chars: source.char_indices().peekable(),
+            variables,
+        };
+        let root = parser.sum()?;
+        parser.skip_whitespace();
+        if let Some((position, c)) = parser.chars.peek() {
+            bail!("Unexpected `{c}` at {}", position + 1);
+        }
+        Ok(Self(root))
+    }
+
+    /// Evaluates the expression, where `variable` gives the value of a variable by its index
+    pub fn eval<N: Number>(&self, variable: &impl Fn(usize) -> N) -> N {
+        self.0.eval(variable)
+    }
+}
+
+impl Node {
+    fn eval<N: Number>(&self, variable: &impl Fn(usize) -> N) -> N {
+        match self {
+            Node::Constant(value) => N::from(*value),
+            Node::Variable(index) => variable(*index),
+            Node::Negate(it) => -it.eval(variable),
+            Node::Add(a, b) => a.eval(variable) + b.eval(variable),
+            Node::Subtract(a, b) => a.eval(variable) - b.eval(variable),
+            Node::Multiply(a, b) => a.eval(variable) * b.eval(variable),
+            Node::Divide(a, b) => a.eval(variable) / b.eval(variable),
+            Node::Power(base, exponent) => {
+                let base = base.eval(variable);
+                (1..*exponent).fold(base, |result, _| result * base)
+            }
+        }
+    }
+}
+
+impl<'a, S: AsRef<str>> Parser<'a, S> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    /// Next character after whitespace, which is consumed if it is `expected`
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if(|&(_, c)| c == expected).is_some()
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.source.len(), |(it, _)| *it)
+    }
+
+    /// Consumes characters while `predicate` holds, returns them as a slice of the source
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let start = self.position();
+        while self.chars.next_if(|&(_, c)| predicate(c)).is_some() {}
+        &self.source[start..self.position()]
+    }
+
+    fn sum(&mut self) -> Result<Node, Error> {
+        let mut result = self.product()?;
+        loop {
+            if self.eat('+') {
+                result = Node::Add(Box::new(result), Box::new(self.product()?));
+            } else if self.eat('-') {
+                result = Node::Subtract(Box::new(result), Box::new(self.product()?));
+            } else {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Node, Error> {
+        let mut result = self.unary()?;
+        loop {
+            if self.eat('*') {
+                result = Node::Multiply(Box::new(result), Box::new(self.unary()?));
+            } else if self.eat('/') {
+                result = Node::Divide(Box::new(result), Box::new(self.unary()?));
+            } else {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Unary minus binds weaker than power, so `-x^2` is `-(x^2)`
+    fn unary(&mut self) -> Result<Node, Error> {
+        if self.eat('-') {
+            Ok(Node::Negate(Box::new(self.unary()?)))
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Node, Error> {
+        let base = self.atom()?;
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        self.skip_whitespace();
+        let position = self.position();
+        let exponent = self.take_while(|c| c.is_ascii_digit());
+        match exponent.parse::<u32>() {
+            Ok(exponent) if exponent > 0 => Ok(Node::Power(Box::new(base), exponent)),
+            _ => bail!("Expected natural exponent at {}", position + 1),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        self.skip_whitespace();
+        let position = self.position();
+        match self.chars.peek().map(|(_, c)| *c) {
+            Some('(') => {
+                self.chars.next();
+                let result = self.sum()?;
+                if !self.eat(')') {
+                    bail!("Expected `)` at {} for `(` at {}", self.position() + 1, position + 1);
+                }
+                Ok(result)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(position),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                let index = self.variables.iter().position(|it| it.as_ref() == name);
+                match index {
+                    Some(index) => Ok(Node::Variable(index)),
+                    None => bail!(
+                        "Unknown variable `{name}` at {}, known are: {}",
+                        position + 1,
+                        self.variables.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(", ")
+                    ),
+                }
+            }
+            Some(c) => bail!("Unexpected `{c}` at {}", position + 1),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+
+    fn number(&mut self, position: usize) -> Result<Node, Error> {
+        self.take_while(|c| c.is_ascii_digit() || c == '.');
+        // Exponent is taken only with its digits, otherwise `e` is left for the next token
+        let mut exponent = self.chars.clone();
+        if exponent.next_if(|(_, c)| matches!(c, 'e' | 'E')).is_some() {
+            exponent.next_if(|(_, c)| matches!(c, '+' | '-'));
+            if exponent.peek().is_some_and(|(_, c)| c.is_ascii_digit()) {
+                self.chars = exponent;
+                self.take_while(|c| c.is_ascii_digit());
+            }
+        }
+        let literal = &self.source[position..self.position()];
+        literal
+            .parse()
+            .map(Node::Constant)
+            .map_err(|_| anyhow!("Invalid number `{literal}` at {}", position + 1))
+    }
+}
+
+/// System of equations `x' = <expression>` over time `t`, state variables and parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equations {
+    variables: Vec<String>,
+    parameters: Vec<String>,
+    /// Variables of every expression are time, then state, then parameters
+    derivatives: Vec<Expression>,
+}
+
+impl Equations {
+    /// Name of time in expressions
+    pub const TIME: &'static str = "t";
+
+    /// Parses equations such as `x1' = -k1 * x1`, where right-hand sides may refer to time,
+    /// any variable from the left-hand sides and `parameters`
+    pub fn parse(equations: &[impl AsRef<str>], parameters: &[impl AsRef<str>]) -> Result<Self, Error> {
+        let mut variables = vec![];
+        let mut sources = vec![];
+        for equation in equations {
+            let equation = equation.as_ref();
+            let (variable, source) = equation
+                .split_once('=')
+                .and_then(|(left, right)| Some((left.trim().strip_suffix('\'')?.trim(), right.trim())))
+                .ok_or_else(|| anyhow!("Expected `x' = <expression>`, got `{equation}`"))?;
+            let valid = variable.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && variable.chars().all(|c| c.is_alphanumeric() || c == '_');
+            if !valid || variable == Self::TIME {
+                bail!("Invalid variable name `{variable}` in `{equation}`");
+            }
+            if variables.iter().any(|it| it == variable) {
+                bail!("Variable `{variable}` has more than one equation");
+            }
+            variables.push(variable.to_string());
+            sources.push(source);
+        }
+        let parameters = parameters.iter().map(|it| it.as_ref().to_string()).collect::<Vec<_>>();
+        if let Some(it) = parameters.iter().find(|it| *it == Self::TIME || variables.contains(it)) {
+            bail!("Parameter `{it}` has the same name as a variable");
+        }
+
+        let names = [Self::TIME.to_string()]
+            .into_iter()
+            .chain(variables.iter().cloned())
+            .chain(parameters.iter().cloned())
+            .collect::<Vec<_>>();
+        let derivatives = variables
+            .iter()
+            .zip(sources)
+            .map(|(variable, source)| {
+                Expression::parse(source, &names).with_context(|| format!("Invalid equation for `{variable}`"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            variables,
+            parameters,
+            derivatives,
+        })
+    }
+
+    /// Names of state variables in the order of equations
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    /// Task with `parameters` and `initial_conditions` in the order of [`Self::parameters`]
+    /// and [`Self::variables`]
+    pub fn build<N: Number>(&self, parameters: &[N], initial_time: f64, initial_conditions: Vec<N>) -> CauchyTask<f64, N> {
+        assert_eq!(parameters.len(), self.parameters.len(), "Every parameter should have a value");
+        let size = self.variables.len();
+        let derivatives = self
+            .derivatives
+            .iter()
+            .map(|expression| {
+                let (expression, parameters) = (expression.clone(), parameters.to_vec());
+                Function::from_slice(size, move |t, state: &[N]| {
+                    expression.eval(&|i| match i {
+                        0 => N::from(t),
+                        i if i <= size => state[i - 1],
+                        i => parameters[i - 1 - size],
+                    })
+                })
+            })
+            .collect();
+        CauchyTask::from_parts(derivatives, initial_time, initial_conditions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, names: &[&str], values: &[f64]) -> f64 {
+        Expression::parse(source, names).unwrap().eval(&|i| values[i])
+    }
+
+    fn error(source: &str, names: &[&str]) -> String {
+        Expression::parse(source, names).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        let x = || Box::new(Node::Variable(0));
+        assert_eq!(
+            Expression::parse("-x^2", &["x"]).unwrap(),
+            Expression(Node::Negate(Box::new(Node::Power(x(), 2))))
+        );
+        assert_eq!(eval("-x^2", &["x"], &[3.0]), -9.0);
+        assert_eq!(eval("a - b - c", &["a", "b", "c"], &[10.0, 3.0, 2.0]), 5.0);
+        assert_eq!(eval("a / b / c", &["a", "b", "c"], &[12.0, 3.0, 2.0]), 2.0);
+        assert_eq!(eval("1 + 2 * 3^2", &[], &[]), 19.0);
+        assert_eq!(eval("(1 + 2) * -(3)", &[], &[]), -9.0);
+    }
+
+    #[test]
+    fn power_is_not_chained() {
+        assert_eq!(error("2^3^2", &[]), "Unexpected `^` at 4");
+        assert_eq!(error("x^0", &["x"]), "Expected natural exponent at 3");
+        assert_eq!(error("x^-1", &["x"]), "Expected natural exponent at 3");
+    }
+
+    #[test]
+    fn exponent_literals() {
+        assert_eq!(eval("1e-3", &[], &[]), 1e-3);
+        assert_eq!(eval("2.5E+2", &[], &[]), 250.0);
+        // `e` without digits is an identifier, which cannot follow a number
+        assert_eq!(error("2e", &["e"]), "Unexpected `e` at 2");
+        assert_eq!(eval("2 * e", &["e"], &[1.5]), 3.0);
+        assert_eq!(error("1.2.3", &[]), "Invalid number `1.2.3` at 1");
+    }
+
+    #[test]
+    fn unknown_identifiers() {
+        assert_eq!(error("x + y", &["x"]), "Unknown variable `y` at 5, known are: x");
+        assert_eq!(error("  k1*x", &["x", "k2"]), "Unknown variable `k1` at 3, known are: x, k2");
+        assert_eq!(error("(x", &["x"]), "Expected `)` at 3 for `(` at 1");
+        assert_eq!(error("x +", &["x"]), "Unexpected end of expression");
+    }
+
+    #[test]
+    fn equations() {
+        let equations = Equations::parse(&["x' = -k * x + t", "y' = x"], &["k"]).unwrap();
+        assert_eq!(equations.variables(), ["x", "y"]);
+        assert_eq!(equations.parameters(), ["k"]);
+
+        let error = |equations: &[&str], parameters: &[&str]| {
+            format!("{:#}", Equations::parse(equations, parameters).unwrap_err())
+        };
+        assert_eq!(error(&["x' = 1", "x' = 2"], &[]), "Variable `x` has more than one equation");
+        assert_eq!(error(&["t' = 1"], &[]), "Invalid variable name `t` in `t' = 1`");
+        assert_eq!(error(&["1x' = 1"], &[]), "Invalid variable name `1x` in `1x' = 1`");
+        assert_eq!(error(&["x = 1"], &[]), "Expected `x' = <expression>`, got `x = 1`");
+        assert_eq!(error(&["x' = k"], &["x"]), "Parameter `x` has the same name as a variable");
+        assert_eq!(error(&["x' = 1"], &["t"]), "Parameter `t` has the same name as a variable");
+        assert_eq!(
+            error(&["x' = y"], &[]),
+            "Invalid equation for `x`: Unknown variable `y` at 1, known are: t, x"
+        );
+    }
+}
@@ -1,10 +1,13 @@
-use crate::solver::{Solver};
-use crate::task::{CauchyTask, Function};
-use anyhow::Error;
-use libloading::{Library, Symbol};
-use std::iter::{once, repeat_with};
+use crate::solver::Solver;
+use crate::task::{panic_message, take_panic, CauchyTask, Derivatives, Function, VectorFunction};
+use crate::plugin::{ParameterBlock, Parameters, Plugin, RawParameters};
+use crate::layout::{describe, Layout, RawLayoutEntry};
+use anyhow::{anyhow, bail, Error};
+use libloading::Symbol;
+use std::ffi::{c_char, CStr};
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
+use std::ptr::{self, NonNull};
 use std::slice;
 use crate::Frozen;
 use crate::interval::Interval;
@@ -12,66 +15,311 @@ use crate::interval::Interval;
 #[repr(C)]
 struct CauchyTaskRef<'a, T, N> {
     size: usize,
+    /// Null if the task is given by [`Self::system`]
     derivatives: *const Function<T, N>,
+    /// Null if the task is given by [`Self::derivatives`]
+    system: *const VectorFunction<T, N>,
     initial_conditions: *const N,
     initial_time: T,
     _phantom: PhantomData<&'a ()>,
 }
 
 pub trait CanSolve<T, N> {
-    const SUFFIX: &'static [u8];
+    /// Suffix of plugin symbols for `(T, N)`, e.g. `solver_create_f64_If64`
+    fn suffix() -> String;
 }
 
-pub struct ExternalSolver<'lib, T, N> {
-    prepare: Symbol<'lib, extern "C" fn(CauchyTaskRef<T, N>)>,
-    next: Symbol<'lib, extern "C" fn(CauchyTaskRef<T, N>, *mut T) -> *const N>,
-    _phantom: PhantomData<&'lib (T, N)>,
+/// Scalar which crosses the plugin ABI as is
+pub trait FfiScalar {
+    /// Stable tag of the type in symbol suffixes
+    const TAG: &'static str;
+    /// Name of the type in C++
+    const CPP: &'static str;
+}
+
+/// Type of solution components, which is either a scalar or a structure of scalars
+pub trait FfiNumber {
+    type Scalar: FfiScalar;
+    /// Prefix of the scalar tag, empty for scalars themselves
+    const KIND: &'static str;
+    /// C++ type with `{}` in place of the name of the scalar
+    const TEMPLATE: &'static str;
+}
+
+impl FfiScalar for f32 {
+    const TAG: &'static str = "f32";
+    const CPP: &'static str = "float";
+}
+
+impl FfiScalar for f64 {
+    const TAG: &'static str = "f64";
+    const CPP: &'static str = "double";
 }
 
-impl CanSolve<f32, f32> for ExternalSolver<'_, f32, f32> {
-    const SUFFIX: &'static [u8] = b"f32_f32";
+impl<S: FfiScalar> FfiNumber for S {
+    type Scalar = S;
+    const KIND: &'static str = "";
+    const TEMPLATE: &'static str = "{}";
 }
 
-impl CanSolve<f64, f64> for ExternalSolver<'_, f64, f64> {
-    const SUFFIX: &'static [u8] = b"f64_f64";
+impl<S: FfiScalar> FfiNumber for Interval<S> {
+    type Scalar = S;
+    const KIND: &'static str = "I";
+    const TEMPLATE: &'static str = "Interval<{}>";
 }
 
-impl CanSolve<f64, Interval<f64>> for ExternalSolver<'_, f64, Interval<f64>> {
-    const SUFFIX: &'static [u8] = b"f64_If64";
+/// Suffix of plugin symbols for time of type `T` and components of type `N`
+pub fn suffix<T: FfiScalar, N: FfiNumber>() -> String {
+    format!("{}_{}{}", T::TAG, N::KIND, <N::Scalar as FfiScalar>::TAG)
 }
 
-impl CanSolve<f64, Interval<f32>> for ExternalSolver<'_, f64, Interval<f32>> {
-    const SUFFIX: &'static [u8] = b"f64_If32";
+/// Pair of types which plugins are built for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub suffix: String,
+    /// C++ type of time
+    pub time: &'static str,
+    /// C++ type of components
+    pub number: String,
 }
 
-impl CanSolve<f32, Interval<f64>> for ExternalSolver<'_, f32, Interval<f64>> {
-    const SUFFIX: &'static [u8] = b"f32_If64";
+impl Binding {
+    pub fn of<T: FfiScalar, N: FfiNumber>() -> Self {
+        Self {
+            suffix: suffix::<T, N>(),
+            time: <T as FfiScalar>::CPP,
+            number: N::TEMPLATE.replace("{}", <N::Scalar as FfiScalar>::CPP),
+        }
+    }
+}
+
+/// Calls `$callback!(T, N)` for every pair of types plugins are built for
+#[macro_export]
+macro_rules! for_each_binding {
+    ($callback:ident) => {
+        $crate::for_each_binding!(@time $callback, f64);
+        $crate::for_each_binding!(@time $callback, f32);
+    };
+    (@time $callback:ident, $t:ty) => {
+        $callback!($t, f64);
+        $callback!($t, f32);
+        $callback!($t, $crate::interval::Interval<f64>);
+        $callback!($t, $crate::interval::Interval<f32>);
+    };
 }
 
-impl CanSolve<f32, Interval<f32>> for ExternalSolver<'_, f32, Interval<f32>> {
-    const SUFFIX: &'static [u8] = b"f32_If32";
+/// Every pair of types plugins are built for
+pub fn bindings() -> Vec<Binding> {
+    let mut result = vec![];
+    macro_rules! push {
+        ($t:ty, $n:ty) => {
+            result.push(Binding::of::<$t, $n>())
+        };
+    }
+    for_each_binding!(push);
+    result
+}
+
+/// Contents of `solvers/include/bindings.h`, which instantiates C++ solvers for [`bindings`]
+pub fn bindings_header() -> String {
+    let bindings = bindings();
+    let mut result = String::from(
+        "// Generated by `project gen-bindings` from `bindings` in `src/ffi.rs`, do not edit\n\
+         #pragma once\n\n\
+         /// Exports all `solver_*` symbols for every supported pair of types, `solver_ty` is a class template\n\
+         #define gen_bindings(solver_ty) \\\n",
+    );
+    for binding in &bindings {
+        result += &format!(
+            "    gen_binding(solver_ty, {}, {}, {}) \\\n",
+            binding.time, binding.number, binding.suffix
+        );
+    }
+    result += "\ninline constexpr const char *SOLVER_SUFFIXES[] = {\n";
+    for binding in &bindings {
+        result += &format!("    \"{}\",\n", binding.suffix);
+    }
+    result += "    nullptr,\n};\n";
+    result
+}
+
+/// Size of the buffer for error messages of the plugin, longer ones are truncated
+const ERROR_CAPACITY: usize = 1024;
+
+/// Status returned by plugin calls on success, see `SolverStatus` in `solvers/include/solver.h`
+const STATUS_OK: i32 = 0;
+
+/// Signature of `solver_prepare_*`
+type PrepareFn<T, N> = extern "C" fn(
+    NonNull<Handle>,
+    CauchyTaskRef<T, N>,
+    *const RawParameters,
+    *mut c_char,
+    usize,
+) -> i32;
+
+/// Signature of `solver_eval_next_*`
+type NextFn<T, N> = extern "C" fn(
+    NonNull<Handle>,
+    CauchyTaskRef<T, N>,
+    *mut T,
+    *mut *const N,
+    *mut c_char,
+    usize,
+) -> i32;
+
+/// Signature of `solver_eval_batch_*`
+type BatchFn<T, N> = extern "C" fn(
+    NonNull<Handle>,
+    CauchyTaskRef<T, N>,
+    usize,
+    T,
+    *mut T,
+    *mut N,
+    *mut usize,
+    *mut c_char,
+    usize,
+) -> i32;
+
+/// Signature of `solver_layout_*`
+type LayoutFn = extern "C" fn(*mut usize) -> *const RawLayoutEntry;
+
+/// Opaque solver instance, created and destroyed by the plugin
+#[repr(C)]
+struct Handle {
+    _private: [u8; 0],
+}
+
+/// Solver from a plugin, which owns its own solver instance, so several of them
+/// can be used simultaneously with the same library
+pub struct ExternalSolver<'lib, T, N> {
+    handle: NonNull<Handle>,
+    destroy: Symbol<'lib, extern "C" fn(NonNull<Handle>)>,
+    prepare: Symbol<'lib, PrepareFn<T, N>>,
+    next: Symbol<'lib, NextFn<T, N>>,
+    batch: Symbol<'lib, BatchFn<T, N>>,
+    parameters: ParameterBlock,
+    name: &'lib str,
+    _phantom: PhantomData<&'lib (T, N)>,
+}
+
+impl<T: FfiScalar, N: FfiNumber> CanSolve<T, N> for ExternalSolver<'_, T, N> {
+    fn suffix() -> String {
+        suffix::<T, N>()
+    }
 }
 
 impl<'lib, T, N> ExternalSolver<'lib, T, N>
 where
     Self: CanSolve<T, N>,
 {
-    pub unsafe fn build(library: &'lib Library) -> Result<Frozen<Self>, Error> {
-        let mut buffer = vec![];
-        buffer.extend_from_slice(b"solver_prepare_");
-        buffer.extend_from_slice(Self::SUFFIX);
-        buffer.push(0);
-        let prepare = library.get(&buffer)?;
-        buffer.clear();
-        buffer.extend_from_slice(b"solver_eval_next_");
-        buffer.extend_from_slice(Self::SUFFIX);
-        buffer.push(0);
-
-        Ok(Frozen(Self {
-            prepare,
-            next: library.get(buffer.as_slice())?,
-            _phantom: Default::default(),
-        }))
+    /// Binds solver symbols for `(T, N)` after checking that plugin declares support for them
+    /// and for every option in `parameters`
+    pub fn build(plugin: &'lib Plugin, parameters: &Parameters) -> Result<Frozen<Self>, Error> {
+        let metadata = plugin.metadata();
+        let suffix = Self::suffix();
+        if !metadata.supports(&suffix) {
+            bail!(
+                "Solver `{}` does not support `{}`, supported are: {}",
+                metadata.name,
+                suffix,
+                metadata.suffixes.join(", ")
+            );
+        }
+
+        if let Some(key) = parameters.options.keys().find(|it| !metadata.accepts(it)) {
+            bail!(
+                "Solver `{}` does not accept parameter `{key}`, accepted are: {}",
+                metadata.name,
+                metadata.parameters.join(", ")
+            );
+        }
+        let parameters = parameters.to_ffi()?;
+
+        let symbol = |prefix: &str| format!("solver_{prefix}_{suffix}\0");
+        // SAFETY: plugin has declared these symbols in its metadata of the verified ABI version
+        unsafe {
+            let layout = plugin.library().get::<LayoutFn>(symbol("layout").as_bytes())?;
+            let mut count = 0;
+            let entries = layout(&mut count);
+            let differences = Layout::from_raw(entries, count)?.differences(&Self::layout());
+            if !differences.is_empty() {
+                bail!(
+                    "Solver `{}` was built with types incompatible with `{}`: {}",
+                    metadata.name,
+                    suffix,
+                    differences.join(", ")
+                );
+            }
+
+            let create = plugin
+                .library()
+                .get::<extern "C" fn() -> *mut Handle>(symbol("create").as_bytes())?;
+            let handle = NonNull::new(create()).ok_or_else(|| {
+                anyhow!("Solver `{}` could not create an instance", metadata.name)
+            })?;
+            Ok(Frozen(Self {
+                handle,
+                destroy: plugin.library().get(symbol("destroy").as_bytes())?,
+                prepare: plugin.library().get(symbol("prepare").as_bytes())?,
+                next: plugin.library().get(symbol("eval_next").as_bytes())?,
+                batch: plugin.library().get(symbol("eval_batch").as_bytes())?,
+                parameters,
+                name: &metadata.name,
+                _phantom: Default::default(),
+            }))
+        }
+    }
+}
+
+impl<T, N> ExternalSolver<'_, T, N> {
+    /// Layout of every type shared with the plugin for `(T, N)` as the host sees it
+    pub fn layout() -> Layout {
+        let mut layout = Layout::default();
+        describe!(layout, "T", T {});
+        describe!(layout, "N", N {});
+        Interval::<f32>::describe(&mut layout, "Interval<float>");
+        Interval::<f64>::describe(&mut layout, "Interval<double>");
+        Function::<T, N>::describe(&mut layout);
+        VectorFunction::<T, N>::describe(&mut layout);
+        RawParameters::describe(&mut layout);
+        describe!(layout, "CauchyTask", CauchyTaskRef<'static, T, N> {
+            size: size,
+            derivatives: derivatives,
+            system: system,
+            initial_conditions: initial_conditions,
+            initial_time: initial_time,
+        });
+        layout
+    }
+
+    /// Turns panic in any callback of the task or non-OK `status` into an error.
+    /// Panic takes precedence, since the plugin most likely failed because of the sentinel value.
+    fn check(&self, status: i32, error: &[c_char], action: &str) -> Result<(), Error> {
+        if let Some(payload) = take_panic() {
+            bail!(
+                "Task function panicked while solver `{}` tried to {action}: {}",
+                self.name,
+                panic_message(&*payload)
+            );
+        }
+        if status == STATUS_OK {
+            return Ok(());
+        }
+        let bytes = error.iter().map(|&it| it as u8).collect::<Vec<_>>();
+        let message = CStr::from_bytes_until_nul(&bytes)
+            .map(|it| it.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| String::from_utf8_lossy(&bytes).into_owned());
+        if message.is_empty() {
+            bail!("Solver `{}` failed to {action} with status {status}", self.name)
+        }
+        bail!("Solver `{}` failed to {action}: {message}", self.name)
+    }
+}
+
+impl<T, N> Drop for ExternalSolver<'_, T, N> {
+    fn drop(&mut self) {
+        (self.destroy)(self.handle)
     }
 }
 
@@ -79,10 +327,15 @@ impl<T, N> CauchyTask<T, N>
 where
     T: Clone,
 {
-    fn as_ffi(&self) -> CauchyTaskRef<T, N> {
+    fn as_ffi(&self) -> CauchyTaskRef<'_, T, N> {
+        let (derivatives, system) = match &self.derivatives {
+            Derivatives::Components(functions) => (functions.as_ptr(), ptr::null()),
+            Derivatives::System(function) => (ptr::null(), function as *const _),
+        };
         CauchyTaskRef {
             size: self.size,
-            derivatives: self.derivatives.as_ptr(),
+            derivatives,
+            system,
             initial_conditions: self.initial_conditions.as_ptr(),
             initial_time: self.initial_time.clone(),
             _phantom: PhantomData,
@@ -95,31 +348,88 @@ where
     T: Clone,
     N: Clone,
 {
-    fn solve_task(
-        this: Frozen<&mut Self>,
-        task: &CauchyTask<T, N>,
-    ) -> impl Iterator<Item = (T, Box<[N]>)> {
+    fn prepare<'a>(this: Frozen<&'a mut Self>, task: &CauchyTask<T, N>) -> Result<&'a mut Self, Error> {
         let ffi = task.as_ffi();
-        let this = this.init(|it| (it.prepare)(ffi));
-        
-        once((task.initial_time.clone(), task.initial_conditions.clone()))
-            .chain(repeat_with(move || {
-                let (t, xs) = this.next_solution(task);
-                assert_eq!(task.size, xs.len(), "Task size should be equal to outputs size");
-                (t, Box::from(xs))
-            }))
+        let mut prepared = Ok(());
+        let this = this.init(|it| {
+            let mut error = [0; ERROR_CAPACITY];
+            let status = (it.prepare)(
+                it.handle,
+                ffi,
+                it.parameters.as_ptr(),
+                error.as_mut_ptr(),
+                ERROR_CAPACITY,
+            );
+            prepared = it.check(status, &error, "prepare for the task");
+        });
+        prepared.map(|()| this)
     }
 
-    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> (T, &[N]) {
+    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> Result<(T, &[N]), Error> {
         let ffi = task.as_ffi();
         let mut time = MaybeUninit::uninit();
-        let xs = (self.next)(ffi, time.as_mut_ptr());
-        assert!(!xs.is_null(), "Got solution, but it is null");
+        let mut xs = ptr::null();
+        let mut error = [0; ERROR_CAPACITY];
+        let status = (self.next)(
+            self.handle,
+            ffi,
+            time.as_mut_ptr(),
+            &mut xs,
+            error.as_mut_ptr(),
+            ERROR_CAPACITY,
+        );
+        self.check(status, &error, "compute next solution")?;
+        if xs.is_null() {
+            bail!("Solver `{}` reported success, but returned no solution", self.name);
+        }
+        // SAFETY: on success plugin has written the time and `task.size` outputs
         unsafe {
-            (
+            Ok((
                 time.assume_init(),
-                slice::from_raw_parts(xs as *const _, task.size),
-            )
+                slice::from_raw_parts(xs, task.size),
+            ))
+        }
+    }
+
+    fn next_batch(
+        &mut self,
+        task: &CauchyTask<T, N>,
+        max_steps: usize,
+        until: &T,
+        times: &mut Vec<T>,
+        states: &mut Vec<N>,
+    ) -> Result<bool, Error>
+    where
+        T: PartialOrd,
+        N: Clone,
+    {
+        times.reserve(max_steps);
+        states.reserve(max_steps * task.size);
+        let mut count = 0;
+        let mut error = [0; ERROR_CAPACITY];
+        let status = (self.batch)(
+            self.handle,
+            task.as_ffi(),
+            max_steps,
+            until.clone(),
+            times.spare_capacity_mut().as_mut_ptr().cast(),
+            states.spare_capacity_mut().as_mut_ptr().cast(),
+            &mut count,
+            error.as_mut_ptr(),
+            ERROR_CAPACITY,
+        );
+        self.check(status, &error, "compute next solutions")?;
+        if count > max_steps {
+            bail!(
+                "Solver `{}` reported {count} points, but at most {max_steps} were requested",
+                self.name
+            );
+        }
+        // SAFETY: on success plugin has written `count` times and `count * task.size` outputs
+        unsafe {
+            times.set_len(times.len() + count);
+            states.set_len(states.len() + count * task.size);
         }
+        Ok(count < max_steps)
     }
 }
@@ -0,0 +1,629 @@
+use crate::model::Model;
+use crate::sensitivity::{Sensitivity, SensitivityTask};
+use crate::solution::{Solution, StopCondition};
+use crate::solver::Solver;
+use crate::Frozen;
+use anyhow::{anyhow, bail, Context, Error};
+use std::array;
+use std::io::BufRead;
+
+/// Single measured value of the component with index [`Self::component`] (zero-based)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub component: usize,
+    pub time: f64,
+    pub value: f64,
+}
+
+/// Measured time series, which a [`Model`] is fitted to
+pub struct Observations {
+    measurements: Vec<Measurement>,
+    weights: Vec<f64>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    #[default]
+    LevenbergMarquardt,
+    NelderMead,
+}
+
+pub struct Options {
+    pub method: Method,
+    pub max_iterations: usize,
+    /// Relative decrease of the cost, which is considered as convergence
+    pub tolerance: f64,
+}
+
+/// Result of the fitting
+pub struct Fit<const P: usize> {
+    pub parameters: [f64; P],
+    /// Approximate covariance of the parameters `s^2 (J^T J)^-1`,
+    /// where `s^2` is the residual variance
+    pub covariance: [[f64; P]; P],
+    /// Weighted residuals in the order of [`Observations::measurements`]
+    pub residuals: Vec<f64>,
+    /// Weighted sum of squared residuals
+    pub cost: f64,
+    pub iterations: usize,
+    /// Method, which produced the result. Differs from requested on fallback
+    pub method: Method,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            method: Method::default(),
+            max_iterations: 100,
+            tolerance: 1e-10,
+        }
+    }
+}
+
+impl Observations {
+    pub fn new(measurements: Vec<Measurement>) -> Self {
+        Self {
+            measurements,
+            weights: vec![],
+        }
+    }
+
+    /// Reads measurements from csv file in the same format as `data.csv` output:
+    /// ```csv
+    /// t, x1, x2, x3
+    /// 0.5, 0.75, 0.2,
+    /// ```
+    /// Columns after the time are named `x<i>`, where `i` is one-based component index.
+    /// Empty cells are treated as missing values, lines starting with `#` are ignored.
+    pub fn from_csv(reader: impl BufRead) -> Result<Self, Error> {
+        let mut lines = reader.lines().enumerate().filter(|(_, line)| match line {
+            Ok(line) => !line.trim().is_empty() && !line.trim_start().starts_with('#'),
+            Err(_) => true,
+        });
+        let Some((_, header)) = lines.next() else {
+            bail!("Measurements file is empty")
+        };
+        let columns = header?
+            .split(',')
+            .skip(1)
+            .map(|name| {
+                let name = name.trim();
+                name.strip_prefix('x')
+                    .and_then(|idx| idx.parse::<usize>().ok())
+                    .filter(|&idx| idx > 0)
+                    .map(|idx| idx - 1)
+                    .ok_or_else(|| anyhow!("Unknown column `{name}`, expected `x<i>`"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut measurements = vec![];
+        for (line_idx, line) in lines {
+            let line = line?;
+            let mut cells = line.split(',').map(str::trim);
+            let time = cells
+                .next()
+                .unwrap_or_default()
+                .parse::<f64>()
+                .with_context(|| format!("Invalid time at line {}", line_idx + 1))?;
+            for (&component, cell) in columns.iter().zip(cells) {
+                if cell.is_empty() {
+                    continue;
+                }
+                let value = cell
+                    .parse()
+                    .with_context(|| format!("Invalid value at line {}", line_idx + 1))?;
+                measurements.push(Measurement {
+                    component,
+                    time,
+                    value,
+                });
+            }
+        }
+
+        if measurements.is_empty() {
+            bail!("Measurements file contains no values");
+        }
+        Ok(Self::new(measurements))
+    }
+
+    /// Sets weight of every component, missing ones are equal to 1.
+    /// Fails if any weight is negative or not finite.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Result<Self, Error> {
+        if let Some((i, weight)) = weights
+            .iter()
+            .enumerate()
+            .find(|(_, it)| !(it.is_finite() && **it >= 0.0))
+        {
+            bail!(
+                "Weight of x{} should be finite and non-negative, got {weight}",
+                i + 1
+            );
+        }
+        self.weights = weights;
+        Ok(self)
+    }
+
+    pub fn measurements(&self) -> &[Measurement] {
+        &self.measurements
+    }
+
+    pub fn weight(&self, component: usize) -> f64 {
+        self.weights.get(component).copied().unwrap_or(1.0)
+    }
+
+    pub fn max_time(&self) -> f64 {
+        self.measurements
+            .iter()
+            .map(|it| it.time)
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+}
+
+impl<const P: usize> Fit<P> {
+    /// Square roots of the covariance diagonal
+    pub fn standard_errors(&self) -> [f64; P] {
+        array::from_fn(|i| self.covariance[i][i].sqrt())
+    }
+}
+
+struct Problem<'a, M, S> {
+    model: &'a M,
+    observations: &'a Observations,
+    solver: &'a mut Frozen<S>,
+}
+
+impl<M, S> Problem<'_, M, S>
+where
+    S: Solver<f64, f64>,
+{
+    fn stop(&self) -> StopCondition<f64> {
+        StopCondition::Timed {
+            maximum: self.observations.max_time(),
+        }
+    }
+
+    fn residuals_of(&self, solution: &Solution<f64, f64>) -> Result<Vec<f64>, Error> {
+        self.observations
+            .measurements
+            .iter()
+            .map(|it| {
+                let weight = self.observations.weight(it.component).sqrt();
+                Ok(weight * (it.value - interpolate(solution, it)?))
+            })
+            .collect()
+    }
+
+    fn residuals<const P: usize>(&mut self, parameters: [f64; P]) -> Result<Vec<f64>, Error>
+    where
+        M: Model<f64, P>,
+    {
+        let task = self.model.build(parameters);
+        let stop = self.stop();
+        let solution = Solution::compute(self.solver.as_mut(), &task, stop)?;
+        self.residuals_of(&solution)
+    }
+
+    /// Cost at `parameters`, where the solver fails is considered infinitely bad
+    fn cost<const P: usize>(&mut self, parameters: [f64; P]) -> f64
+    where
+        M: Model<f64, P>,
+    {
+        self.residuals(parameters)
+            .map_or(f64::INFINITY, |it| cost(&it))
+    }
+
+    /// Residuals together with their jacobian with respect to the parameters
+    fn linearize<const P: usize>(
+        &mut self,
+        parameters: [f64; P],
+    ) -> Result<(Vec<f64>, Vec<[f64; P]>), Error>
+    where
+        M: Model<f64, P>,
+    {
+        let task = SensitivityTask::automatic(self.model, parameters);
+        let stop = self.stop();
+        let sensitivity = Sensitivity::compute(self.solver.as_mut(), &task, stop)?;
+        let jacobian = self
+            .observations
+            .measurements
+            .iter()
+            .map(|it| {
+                let weight = self.observations.weight(it.component).sqrt();
+                let mut row = [0.0; P];
+                for (j, derivative) in row.iter_mut().enumerate() {
+                    *derivative = -weight * interpolate(sensitivity.parameter(j), it)?;
+                }
+                Ok(row)
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok((self.residuals_of(sensitivity.state())?, jacobian))
+    }
+}
+
+/// Value of `solution` at the time and component of `measurement`
+fn interpolate(solution: &Solution<f64, f64>, measurement: &Measurement) -> Result<f64, Error> {
+    solution
+        .interpolate(measurement.component, measurement.time)
+        .ok_or_else(|| {
+            anyhow!(
+                "Solution has no value of x{} at t = {}",
+                measurement.component + 1,
+                measurement.time
+            )
+        })
+}
+
+fn cost(residuals: &[f64]) -> f64 {
+    let cost = residuals.iter().map(|r| r * r).sum::<f64>();
+    if cost.is_finite() {
+        cost
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// Returns `J^T J` and `J^T r`
+fn normal_equations<const P: usize>(
+    jacobian: &[[f64; P]],
+    residuals: &[f64],
+) -> ([[f64; P]; P], [f64; P]) {
+    let mut a = [[0.0; P]; P];
+    let mut g = [0.0; P];
+    for (row, r) in jacobian.iter().zip(residuals) {
+        for i in 0..P {
+            g[i] += row[i] * r;
+            for j in 0..P {
+                a[i][j] += row[i] * row[j];
+            }
+        }
+    }
+    (a, g)
+}
+
+/// Solves `a x = b` with gaussian elimination, returns [`None`] if `a` is singular
+fn solve_linear<const P: usize>(mut a: [[f64; P]; P], mut b: [f64; P]) -> Option<[f64; P]> {
+    for col in 0..P {
+        let pivot = (col..P).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        let pivot_value = a[pivot][col].abs();
+        if pivot_value.is_nan() || pivot_value <= f64::MIN_POSITIVE {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..P {
+            let pivot_row = a[col];
+            let factor = a[row][col] / pivot_row[col];
+            for (x, p) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; P];
+    for row in (0..P).rev() {
+        let tail = (row + 1..P).map(|k| a[row][k] * x[k]).sum::<f64>();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    x.iter().all(|it| it.is_finite()).then_some(x)
+}
+
+fn covariance<const P: usize>(jacobian: &[[f64; P]], residuals: &[f64]) -> [[f64; P]; P] {
+    let (a, _) = normal_equations(jacobian, residuals);
+    let dof = residuals.len().saturating_sub(P).max(1) as f64;
+    let variance = cost(residuals) / dof;
+    let inverse: [Option<[f64; P]>; P] =
+        array::from_fn(|j| solve_linear(a, array::from_fn(|i| if i == j { 1.0 } else { 0.0 })));
+    array::from_fn(|i| array::from_fn(|j| inverse[j].map_or(f64::NAN, |it| variance * it[i])))
+}
+
+fn levenberg_marquardt<M, S, const P: usize>(
+    problem: &mut Problem<M, S>,
+    initial: [f64; P],
+    options: &Options,
+) -> Result<([f64; P], usize), Error>
+where
+    M: Model<f64, P>,
+    S: Solver<f64, f64>,
+{
+    let mut parameters = initial;
+    let (mut residuals, mut jacobian) = problem.linearize(parameters)?;
+    let mut current = cost(&residuals);
+    if !current.is_finite() || jacobian.iter().flatten().any(|it| !it.is_finite()) {
+        bail!("Model cannot be evaluated at initial parameters {initial:?}");
+    }
+    let mut lambda = 1e-3;
+
+    for iteration in 1..=options.max_iterations {
+        let (a, g) = normal_equations(&jacobian, &residuals);
+        let mut damped = a;
+        for (i, row) in damped.iter_mut().enumerate() {
+            row[i] += lambda * a[i][i].max(f64::EPSILON);
+        }
+        let Some(delta) = solve_linear(damped, g.map(|it| -it)) else {
+            bail!("Normal equations are singular at {parameters:?}");
+        };
+
+        let candidate = array::from_fn(|i| parameters[i] + delta[i]);
+        let next = problem.cost(candidate);
+        if next < current {
+            let decrease = (current - next) / current.max(f64::MIN_POSITIVE);
+            parameters = candidate;
+            (residuals, jacobian) = problem.linearize(parameters)?;
+            current = cost(&residuals);
+            lambda = (lambda / 10.0).max(1e-12);
+            if decrease < options.tolerance {
+                return Ok((parameters, iteration));
+            }
+        } else {
+            lambda *= 10.0;
+            if lambda > 1e12 {
+                return Ok((parameters, iteration));
+            }
+        }
+    }
+
+    Ok((parameters, options.max_iterations))
+}
+
+fn nelder_mead<M, S, const P: usize>(
+    problem: &mut Problem<M, S>,
+    initial: [f64; P],
+    options: &Options,
+) -> ([f64; P], usize)
+where
+    M: Model<f64, P>,
+    S: Solver<f64, f64>,
+{
+    let mut objective = |point: [f64; P]| problem.cost(point);
+    let mut simplex = vec![(initial, objective(initial))];
+    for i in 0..P {
+        let mut vertex = initial;
+        vertex[i] = if vertex[i] == 0.0 {
+            2.5e-4
+        } else {
+            vertex[i] * 1.05
+        };
+        simplex.push((vertex, objective(vertex)));
+    }
+
+    for iteration in 1..=options.max_iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (simplex[0].1, simplex[P].1);
+        if (worst - best).abs() <= options.tolerance * best.abs().max(f64::MIN_POSITIVE) {
+            return (simplex[0].0, iteration);
+        }
+
+        let centroid: [f64; P] =
+            array::from_fn(|i| simplex[..P].iter().map(|(v, _)| v[i]).sum::<f64>() / P as f64);
+        let towards = |factor: f64| -> [f64; P] {
+            array::from_fn(|i| centroid[i] + factor * (simplex[P].0[i] - centroid[i]))
+        };
+
+        let reflected = towards(-1.0);
+        let reflected_cost = objective(reflected);
+        if reflected_cost < simplex[0].1 {
+            let expanded = towards(-2.0);
+            let expanded_cost = objective(expanded);
+            simplex[P] = if expanded_cost < reflected_cost {
+                (expanded, expanded_cost)
+            } else {
+                (reflected, reflected_cost)
+            };
+        } else if reflected_cost < simplex[P - 1].1 {
+            simplex[P] = (reflected, reflected_cost);
+        } else {
+            let contracted = towards(0.5);
+            let contracted_cost = objective(contracted);
+            if contracted_cost < simplex[P].1 {
+                simplex[P] = (contracted, contracted_cost);
+            } else {
+                let best = simplex[0].0;
+                for (vertex, value) in &mut simplex[1..] {
+                    *vertex = array::from_fn(|i| best[i] + 0.5 * (vertex[i] - best[i]));
+                    *value = objective(*vertex);
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    (simplex[0].0, options.max_iterations)
+}
+
+/// Fits parameters of `model` to `observations` by minimizing weighted sum of squared residuals,
+/// starting from `initial`. Each evaluation of the model is solved with `solver`.
+///
+/// Levenberg–Marquardt uses jacobian derived from the sensitivity equations. If it fails
+/// (e.g. normal equations become singular), Nelder–Mead is used as a fallback.
+pub fn fit<M, S, const P: usize>(
+    model: &M,
+    observations: &Observations,
+    initial: [f64; P],
+    solver: &mut Frozen<S>,
+    options: &Options,
+) -> Result<Fit<P>, Error>
+where
+    M: Model<f64, P>,
+    S: Solver<f64, f64>,
+{
+    if P == 0 {
+        bail!("At least one parameter should be fitted");
+    }
+    let size = model.build(initial).size;
+    if let Some(it) = observations
+        .measurements
+        .iter()
+        .find(|it| it.component >= size)
+    {
+        bail!(
+            "Measurements of x{} are given, but the model has only {size} components",
+            it.component + 1
+        );
+    }
+
+    let mut problem = Problem {
+        model,
+        observations,
+        solver,
+    };
+
+    // Error of Levenberg–Marquardt is kept to explain the failure of the fallback
+    let mut fallback_reason = None;
+    let (parameters, iterations, method) = match options.method {
+        Method::LevenbergMarquardt => match levenberg_marquardt(&mut problem, initial, options) {
+            Ok((parameters, iterations)) => (parameters, iterations, Method::LevenbergMarquardt),
+            Err(e) => {
+                fallback_reason = Some(e);
+                let (parameters, iterations) = nelder_mead(&mut problem, initial, options);
+                (parameters, iterations, Method::NelderMead)
+            }
+        },
+        Method::NelderMead => {
+            let (parameters, iterations) = nelder_mead(&mut problem, initial, options);
+            (parameters, iterations, Method::NelderMead)
+        }
+    };
+
+    let result = problem
+        .linearize(parameters)
+        .and_then(|(residuals, jacobian)| {
+            let cost = cost(&residuals);
+            if !cost.is_finite() {
+                bail!("Fitting diverged, last parameters are {parameters:?}");
+            }
+            Ok(Fit {
+                parameters,
+                covariance: covariance(&jacobian, &residuals),
+                residuals,
+                cost,
+                iterations,
+                method,
+            })
+        });
+    match (result, fallback_reason) {
+        (Err(e), Some(reason)) => Err(e.context(format!(
+            "Nelder–Mead has failed after Levenberg–Marquardt: {reason:#}"
+        ))),
+        (result, _) => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Number, Reaction};
+    use crate::solver::EulerSolver;
+    use crate::task::{f, CauchyTask};
+
+    const RATES: [f64; 2] = [0.577, 0.422];
+
+    /// Measurements of the first two components taken from the solution with [`RATES`]
+    fn synthetic() -> Observations {
+        let mut solver = EulerSolver::new(1e-2);
+        let task = Reaction.build(RATES);
+        let stop = StopCondition::Timed { maximum: 5.0 };
+        let solution = Solution::compute(solver.as_mut(), &task, stop).unwrap();
+        let measurements = (1..=10)
+            .flat_map(|i| {
+                let time = i as f64 * 0.5;
+                (0..2).map(move |component| (component, time))
+            })
+            .map(|(component, time)| Measurement {
+                component,
+                time,
+                value: solution.interpolate(component, time).unwrap(),
+            })
+            .collect();
+        Observations::new(measurements)
+    }
+
+    #[test]
+    fn levenberg_marquardt_recovers_rates() {
+        let mut solver = EulerSolver::new(1e-2);
+        let fit = fit(
+            &Reaction,
+            &synthetic(),
+            [0.3, 0.8],
+            &mut solver,
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(fit.method, Method::LevenbergMarquardt);
+        for (found, expected) in fit.parameters.iter().zip(RATES) {
+            assert!((found - expected).abs() < 1e-6, "{found} != {expected}");
+        }
+        assert!(fit.cost < 1e-12);
+    }
+
+    #[test]
+    fn rejects_unknown_components() {
+        let observations = Observations::from_csv("t, x4\n0.5, 0.1\n".as_bytes()).unwrap();
+        let mut solver = EulerSolver::new(1e-2);
+        let error = fit(
+            &Reaction,
+            &observations,
+            RATES,
+            &mut solver,
+            &Options::default(),
+        );
+        assert!(error.is_err());
+    }
+
+    /// `x' = p * x^2`, which blows up before the first measurement for any positive `p`
+    struct Explosion;
+
+    impl Model<f64, 1> for Explosion {
+        fn build<N: Number>(&self, [p]: [N; 1]) -> CauchyTask<f64, N> {
+            CauchyTask::new([f(move |_, &[x]| p * x * x)], 0.0, [N::from(1e3)])
+        }
+    }
+
+    #[test]
+    fn reports_failure_of_both_methods() {
+        let observations = Observations::from_csv("t, x1\n1, 1\n".as_bytes()).unwrap();
+        let mut solver = EulerSolver::new(1e-2);
+        let error = fit(
+            &Explosion,
+            &observations,
+            [1.0],
+            &mut solver,
+            &Options::default(),
+        )
+        .err()
+        .unwrap();
+        let message = format!("{error:#}");
+        assert!(
+            message.contains("Nelder–Mead has failed after Levenberg–Marquardt"),
+            "{message}"
+        );
+        assert!(message.contains("cannot be evaluated"), "{message}");
+    }
+
+    /// Model without parameters
+    struct Decay;
+
+    impl Model<f64, 0> for Decay {
+        fn build<N: Number>(&self, _: [N; 0]) -> CauchyTask<f64, N> {
+            CauchyTask::new([f(|_, &[x]: &[N; 1]| -x)], 0.0, [N::from(1.0)])
+        }
+    }
+
+    #[test]
+    fn rejects_empty_parameters() {
+        let mut solver = EulerSolver::new(1e-2);
+        let options = Options {
+            method: Method::NelderMead,
+            ..Default::default()
+        };
+        assert!(fit(&Decay, &synthetic(), [], &mut solver, &options).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_weights() {
+        assert!(synthetic().with_weights(vec![1.0, 0.0]).is_ok());
+        assert!(synthetic().with_weights(vec![1.0, -1.0]).is_err());
+        assert!(synthetic().with_weights(vec![f64::NAN]).is_err());
+    }
+}
@@ -0,0 +1,382 @@
+use crate::model::Model;
+use crate::solution::{Solution, StopCondition};
+use crate::solver::Solver;
+use crate::Frozen;
+use anyhow::{bail, Error};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::array;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::panic::resume_unwind;
+use std::thread;
+
+/// Global sensitivity study of a scalar quantity of interest, derived from the solution of
+/// [`Self::model`], with respect to parameters varying within [`Self::ranges`]
+pub struct Study<'a, M, F, Q, const P: usize> {
+    pub model: &'a M,
+    pub ranges: [Range<f64>; P],
+    /// Creates a fresh solver for every worker thread, its error stops the study
+    pub solver: F,
+    pub quantity: Q,
+    pub stop: StopCondition<f64>,
+}
+
+pub struct Sampling {
+    /// Base sample size for Sobol indices or amount of trajectories for Morris screening
+    pub samples: usize,
+    pub seed: u64,
+    /// Amount of worker threads, zero means available parallelism
+    pub threads: usize,
+}
+
+/// First-order and total Sobol indices
+pub struct SobolIndices<const P: usize> {
+    pub first_order: [f64; P],
+    pub total: [f64; P],
+    /// Variance of the quantity of interest
+    pub variance: f64,
+    pub evaluations: usize,
+}
+
+/// Statistics of Morris elementary effects
+pub struct ElementaryEffects<const P: usize> {
+    pub mean: [f64; P],
+    /// Mean of absolute values, `mu*`
+    pub mean_absolute: [f64; P],
+    pub deviation: [f64; P],
+    pub evaluations: usize,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            samples: 1024,
+            seed: 0,
+            threads: 0,
+        }
+    }
+}
+
+/// Indices of parameters sorted by descending `measure`
+fn rank<const P: usize>(measure: &[f64; P]) -> [usize; P] {
+    let mut ranking = array::from_fn(|i| i);
+    ranking.sort_by(|&a, &b| measure[b].total_cmp(&measure[a]));
+    ranking
+}
+
+fn mean(values: impl IntoIterator<Item = f64>) -> f64 {
+    let (sum, len) = values
+        .into_iter()
+        .fold((0.0, 0), |(sum, len), x| (sum + x, len + 1));
+    sum / len.max(1) as f64
+}
+
+impl<const P: usize> SobolIndices<P> {
+    /// Parameters from the most to the least influential by total index
+    pub fn ranking(&self) -> [usize; P] {
+        rank(&self.total)
+    }
+}
+
+impl<const P: usize> ElementaryEffects<P> {
+    /// Parameters from the most to the least influential by `mu*`
+    pub fn ranking(&self) -> [usize; P] {
+        rank(&self.mean_absolute)
+    }
+}
+
+impl<M, F, S, Q, const P: usize> Study<'_, M, F, Q, P>
+where
+    M: Model<f64, P> + Sync,
+    F: Fn() -> Result<Frozen<S>, Error> + Sync,
+    S: Solver<f64, f64>,
+    Q: Fn(&Solution<f64, f64>) -> f64 + Sync,
+{
+    fn scale(&self, unit: [f64; P]) -> [f64; P] {
+        array::from_fn(|i| {
+            let range = &self.ranges[i];
+            range.start + unit[i] * (range.end - range.start)
+        })
+    }
+
+    /// Evaluates quantity of interest at every point of the unit cube in parallel
+    fn evaluate(&self, points: &[[f64; P]], threads: usize) -> Result<Vec<f64>, Error> {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            threads => threads,
+        };
+        let chunk_size = points.len().div_ceil(threads).max(1);
+
+        thread::scope(|scope| {
+            let workers = points
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        let mut solver = (self.solver)()?;
+                        chunk
+                            .iter()
+                            .map(|&point| {
+                                let task = self.model.build(self.scale(point));
+                                let solution =
+                                    Solution::compute(solver.as_mut(), &task, self.stop)?;
+                                Ok((self.quantity)(&solution))
+                            })
+                            .collect::<Result<Vec<_>, Error>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut values = Vec::with_capacity(points.len());
+            for worker in workers {
+                values.extend(worker.join().unwrap_or_else(|e| resume_unwind(e))?);
+            }
+            Ok(values)
+        })
+    }
+
+    /// Estimates Sobol indices with Saltelli sampling scheme, which takes
+    /// `samples * (P + 2)` evaluations of the model.
+    /// First-order indices are estimated as in Saltelli (2010), total ones as in Jansen (1999).
+    pub fn sobol(&self, sampling: &Sampling) -> Result<SobolIndices<P>, Error> {
+        let mut rng = ChaCha8Rng::seed_from_u64(sampling.seed);
+        let n = sampling.samples;
+        let a = (0..n)
+            .map(|_| array::from_fn(|_| rng.gen::<f64>()))
+            .collect::<Vec<[f64; P]>>();
+        let b = (0..n)
+            .map(|_| array::from_fn(|_| rng.gen::<f64>()))
+            .collect::<Vec<[f64; P]>>();
+
+        // [A, B, A_B^1, ..., A_B^P], where A_B^i is A with i-th column from B
+        let mut points = Vec::with_capacity(n * (P + 2));
+        points.extend_from_slice(&a);
+        points.extend_from_slice(&b);
+        for i in 0..P {
+            points.extend(a.iter().zip(&b).map(|(a, b)| {
+                let mut point = *a;
+                point[i] = b[i];
+                point
+            }));
+        }
+
+        let values = self.evaluate(&points, sampling.threads)?;
+        let (f_a, rest) = values.split_at(n);
+        let (f_b, f_ab) = rest.split_at(n);
+
+        let all = mean(f_a.iter().chain(f_b).copied());
+        let variance = mean(f_a.iter().chain(f_b).map(|f| (f - all).powi(2)));
+        if variance.is_nan() || variance <= 0.0 {
+            bail!(
+                "Quantity has variance {variance} over the ranges, so Sobol indices are undefined"
+            );
+        }
+        let f_ab = |i: usize| &f_ab[n * i..n * (i + 1)];
+
+        Ok(SobolIndices {
+            // Centering f_B does not change the estimate, but greatly reduces its variance
+            first_order: array::from_fn(|i| {
+                mean(
+                    f_b.iter()
+                        .zip(f_a)
+                        .zip(f_ab(i))
+                        .map(|((b, a), ab)| (b - all) * (ab - a)),
+                ) / variance
+            }),
+            total: array::from_fn(|i| {
+                mean(f_a.iter().zip(f_ab(i)).map(|(a, ab)| (a - ab).powi(2))) / (2.0 * variance)
+            }),
+            variance,
+            evaluations: values.len(),
+        })
+    }
+
+    /// Estimates statistics of elementary effects along `samples` one-at-a-time trajectories
+    /// on a grid with `levels` levels, which takes `samples * (P + 1)` evaluations of the model.
+    /// Effects are computed in the parameter scale.
+    pub fn morris(
+        &self,
+        levels: usize,
+        sampling: &Sampling,
+    ) -> Result<ElementaryEffects<P>, Error> {
+        if levels < 2 {
+            bail!("Morris grid should have at least 2 levels, got {levels}");
+        }
+        let (points, orders) = trajectories::<P>(levels, sampling);
+
+        let values = self.evaluate(&points, sampling.threads)?;
+        let mut effects: [Vec<f64>; P] = array::from_fn(|_| Vec::with_capacity(sampling.samples));
+        for (trajectory, order) in orders.iter().enumerate() {
+            let offset = trajectory * (P + 1);
+            for (k, &i) in order.iter().enumerate() {
+                let (before, after) = (points[offset + k][i], points[offset + k + 1][i]);
+                let delta = (after - before) * (self.ranges[i].end - self.ranges[i].start);
+                effects[i].push((values[offset + k + 1] - values[offset + k]) / delta);
+            }
+        }
+
+        let mean_of = |i: usize| mean(effects[i].iter().copied());
+        Ok(ElementaryEffects {
+            mean: array::from_fn(mean_of),
+            mean_absolute: array::from_fn(|i| mean(effects[i].iter().map(|it| it.abs()))),
+            deviation: array::from_fn(|i| {
+                let mean = mean_of(i);
+                let len = effects[i].len().saturating_sub(1).max(1) as f64;
+                (effects[i].iter().map(|it| (it - mean).powi(2)).sum::<f64>() / len).sqrt()
+            }),
+            evaluations: values.len(),
+        })
+    }
+}
+
+/// Points of one-at-a-time trajectories on the unit grid with `levels` levels together with the
+/// order in which each trajectory moves the parameters.
+///
+/// The jump is the standard `levels / (2 (levels - 1))` for even `levels`. It is not a multiple
+/// of the grid step for odd ones, so `(levels - 1) / (2 (levels - 1))` is taken instead.
+fn trajectories<const P: usize>(
+    levels: usize,
+    sampling: &Sampling,
+) -> (Vec<[f64; P]>, Vec<[usize; P]>) {
+    let mut rng = ChaCha8Rng::seed_from_u64(sampling.seed);
+    let jump = levels / 2;
+    let step = jump as f64 / (levels - 1) as f64;
+    // Starting levels, from which the jump stays within the grid
+    let starts = levels - jump;
+
+    // Every trajectory moves each parameter exactly once in random order
+    let mut points = Vec::with_capacity(sampling.samples * (P + 1));
+    let mut orders = Vec::with_capacity(sampling.samples);
+    for _ in 0..sampling.samples {
+        let mut point: [f64; P] =
+            array::from_fn(|_| rng.gen_range(0..starts) as f64 / (levels - 1) as f64);
+        let mut order: [usize; P] = array::from_fn(|i| i);
+        order.shuffle(&mut rng);
+        points.push(point);
+        for &i in &order {
+            point[i] += step;
+            points.push(point);
+        }
+        orders.push(order);
+    }
+    (points, orders)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Number;
+    use crate::solver::EulerSolver;
+    use crate::task::{f, CauchyTask};
+
+    /// Constant `2 k1 + k2`, which does not depend on `k3`
+    struct Additive;
+
+    impl Model<f64, 3> for Additive {
+        fn build<N: Number>(&self, [k1, k2, _]: [N; 3]) -> CauchyTask<f64, N> {
+            CauchyTask::new([f(|_, &[_]| N::from(0.0))], 0.0, [N::from(2.0) * k1 + k2])
+        }
+    }
+
+    type AdditiveStudy = Study<
+        'static,
+        Additive,
+        fn() -> Result<Frozen<EulerSolver<f64, f64>>, Error>,
+        fn(&Solution<f64, f64>) -> f64,
+        3,
+    >;
+
+    fn study() -> AdditiveStudy {
+        Study {
+            model: &Additive,
+            ranges: [0.0..1.0, 0.0..1.0, 0.0..1.0],
+            solver: || Ok(EulerSolver::new(0.1)),
+            quantity: |solution| solution[0][0],
+            stop: StopCondition::Timed { maximum: 0.0 },
+        }
+    }
+
+    #[test]
+    fn sobol_indices_of_additive_function() {
+        let sampling = Sampling {
+            samples: 8192,
+            ..Default::default()
+        };
+        let indices = study().sobol(&sampling).unwrap();
+
+        // Var(2 k1 + k2) = 4/12 + 1/12, first-order and total indices coincide
+        for (i, expected) in [0.8, 0.2, 0.0].into_iter().enumerate() {
+            assert!(
+                (indices.first_order[i] - expected).abs() < 0.03,
+                "S{}",
+                i + 1
+            );
+            assert!((indices.total[i] - expected).abs() < 0.03, "ST{}", i + 1);
+        }
+        assert!((indices.variance - 5.0 / 12.0).abs() < 0.02);
+        assert_eq!(indices.ranking(), [0, 1, 2]);
+        assert_eq!(indices.evaluations, 8192 * 5);
+    }
+
+    #[test]
+    fn morris_effects_of_additive_function() {
+        let sampling = Sampling {
+            samples: 16,
+            ..Default::default()
+        };
+        let effects = study().morris(4, &sampling).unwrap();
+        for (i, expected) in [2.0, 1.0, 0.0].into_iter().enumerate() {
+            assert!((effects.mean[i] - expected).abs() < 1e-9);
+            assert!(effects.deviation[i] < 1e-9);
+        }
+        assert!(study().morris(1, &sampling).is_err());
+    }
+
+    #[test]
+    fn trajectories_stay_on_grid() {
+        let sampling = Sampling {
+            samples: 64,
+            ..Default::default()
+        };
+        for levels in 2..=7 {
+            let (points, _) = trajectories::<3>(levels, &sampling);
+            for value in points.iter().flatten() {
+                let level = value * (levels - 1) as f64;
+                assert!(
+                    (level - level.round()).abs() < 1e-9,
+                    "{value} with {levels} levels"
+                );
+                assert!((0.0..=1.0).contains(value));
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_rejects_constant_quantity() {
+        let study: AdditiveStudy = Study {
+            quantity: |_| 1.0,
+            ..study()
+        };
+        let sampling = Sampling {
+            samples: 16,
+            ..Default::default()
+        };
+        assert!(study.sobol(&sampling).is_err());
+    }
+
+    #[test]
+    fn propagates_solver_errors() {
+        let study: AdditiveStudy = Study {
+            solver: || bail!("Solver is unavailable"),
+            ..study()
+        };
+        let sampling = Sampling {
+            samples: 16,
+            threads: 2,
+            ..Default::default()
+        };
+        let error = study.morris(4, &sampling).err().unwrap();
+        assert_eq!(error.to_string(), "Solver is unavailable");
+    }
+}
@@ -1,6 +1,8 @@
 use std::fmt::{Display, Formatter};
 use std::ops::{Add, Div, Mul, Neg, Sub};
 use itertools::{Itertools, MinMaxResult};
+use crate::layout::{describe, Layout};
+use crate::task::Sentinel;
 
 #[derive(Debug, Default, Hash, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 #[repr(C)]
@@ -16,6 +18,44 @@ impl<T> Interval<T> {
     }
     pub fn start(self) -> T { self.0 }
     pub fn end(self) -> T { self.1 }
+
+    /// Adds layout of the interval under `name`, it is mirrored by `Interval` in `solvers/include/interval.h`
+    pub(crate) fn describe(layout: &mut Layout, name: &str) {
+        describe!(layout, name, Self { start: 0, end: 1 });
+    }
+}
+
+impl<T: PartialOrd + Copy> Interval<T> {
+    /// Smallest interval containing both `self` and `other`
+    pub fn hull(self, other: Self) -> Self {
+        let start = if other.0 < self.0 { other.0 } else { self.0 };
+        let end = if other.1 > self.1 { other.1 } else { self.1 };
+        Self(start, end)
+    }
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 <= other.1 && other.0 <= self.1
+    }
+
+    pub fn is_subset(self, other: Self) -> bool {
+        other.0 <= self.0 && self.1 <= other.1
+    }
+}
+
+impl Interval<f64> {
+    pub fn width(self) -> f64 {
+        self.1 - self.0
+    }
+
+    pub fn midpoint(self) -> f64 {
+        self.0 + self.width() / 2.0
+    }
+
+    /// Splits interval by its midpoint
+    pub fn bisect(self) -> (Self, Self) {
+        let midpoint = self.midpoint();
+        (Self(self.0, midpoint), Self(midpoint, self.1))
+    }
 }
 
 impl<T: Add> Add for Interval<T> {
@@ -62,7 +102,11 @@ impl Mul<Interval<f64>> for f64 {
     type Output = Interval<f64>;
 
     fn mul(self, rhs: Interval<f64>) -> Self::Output {
-        Interval(rhs.0 * self, rhs.1 * self)
+        if self < 0.0 {
+            Interval(rhs.1 * self, rhs.0 * self)
+        } else {
+            Interval(rhs.0 * self, rhs.1 * self)
+        }
     }
 }
 
@@ -92,7 +136,13 @@ impl<T: Neg> Neg for Interval<T> {
     type Output = Interval<T::Output>;
 
     fn neg(self) -> Self::Output {
-        Interval(-self.0, -self.1)
+        Interval(-self.1, -self.0)
+    }
+}
+
+impl<T: Sentinel> Sentinel for Interval<T> {
+    fn sentinel() -> Self {
+        Interval(T::sentinel(), T::sentinel())
     }
 }
 
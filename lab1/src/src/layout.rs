@@ -0,0 +1,145 @@
+use anyhow::{bail, Error};
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr};
+use std::slice;
+
+/// Sizes, alignments and field offsets of the types shared with plugins, keyed by names like
+/// `offsetof(CauchyTask, initial_time)`, the same as `SolverLayout` in `solvers/include/solver.h` uses
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Layout(BTreeMap<String, usize>);
+
+/// Entry of the table exported by plugin via `solver_layout_*` symbols
+#[repr(C)]
+pub(crate) struct RawLayoutEntry {
+    name: *const c_char,
+    value: usize,
+}
+
+/// Adds size, alignment and offsets of the listed fields of `$ty` to `$layout` under `$name`.
+/// Each field is given by its name in C++ and its name in Rust, which differ for tuple structs.
+macro_rules! describe {
+    ($layout:expr, $name:expr, $ty:ty { $($field:ident: $path:tt),* $(,)? }) => {{
+        let name = $name;
+        $layout.insert(format!("sizeof({name})"), ::std::mem::size_of::<$ty>());
+        $layout.insert(format!("alignof({name})"), ::std::mem::align_of::<$ty>());
+        $(
+            $layout.insert(
+                format!("offsetof({name}, {})", stringify!($field)),
+                ::std::mem::offset_of!($ty, $path),
+            );
+        )*
+    }};
+}
+
+pub(crate) use describe;
+
+impl Layout {
+    pub fn insert(&mut self, name: String, value: usize) {
+        self.0.insert(name, value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.0.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.0.iter().map(|(name, &value)| (name.as_str(), value))
+    }
+
+    /// Reads table of `count` entries exported by a plugin
+    pub(crate) unsafe fn from_raw(entries: *const RawLayoutEntry, count: usize) -> Result<Self, Error> {
+        if entries.is_null() {
+            bail!("Layout table is missing");
+        }
+        let mut result = Self::default();
+        for entry in slice::from_raw_parts(entries, count) {
+            if entry.name.is_null() {
+                bail!("Layout table contains an entry without name");
+            }
+            result.insert(CStr::from_ptr(entry.name).to_str()?.to_string(), entry.value);
+        }
+        Ok(result)
+    }
+
+    /// Every difference of `self` from the `expected` layout, empty if they match
+    pub fn differences(&self, expected: &Self) -> Vec<String> {
+        let mut result = vec![];
+        for (name, value) in expected.iter() {
+            match self.get(name) {
+                Some(actual) if actual != value => {
+                    result.push(format!("{name} is {actual}, but {value} is expected"))
+                }
+                Some(_) => {}
+                None => result.push(format!("{name} is missing")),
+            }
+        }
+        for (name, _) in self.iter().filter(|(name, _)| expected.get(name).is_none()) {
+            result.push(format!("{name} is unknown"));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Pair {
+        first: u8,
+        second: u32,
+    }
+
+    /// Same fields as [`Pair`] without padding, as if a plugin was built with other packing
+    #[repr(C, packed)]
+    struct PackedPair {
+        first: u8,
+        second: u32,
+    }
+
+    #[repr(C)]
+    struct Triple {
+        first: u8,
+        second: u32,
+        third: u16,
+    }
+
+    fn pair() -> Layout {
+        let mut layout = Layout::default();
+        describe!(layout, "Pair", Pair { first: first, second: second });
+        layout
+    }
+
+    #[test]
+    fn same_layouts_match() {
+        assert!(pair().differences(&pair()).is_empty());
+    }
+
+    #[test]
+    fn reports_size_alignment_and_offset() {
+        let mut packed = Layout::default();
+        describe!(packed, "Pair", PackedPair { first: first, second: second });
+        assert_eq!(
+            packed.differences(&pair()),
+            [
+                "alignof(Pair) is 1, but 4 is expected",
+                "offsetof(Pair, second) is 1, but 4 is expected",
+                "sizeof(Pair) is 5, but 8 is expected",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_and_unknown_entries() {
+        let mut triple = Layout::default();
+        describe!(triple, "Pair", Triple { first: first, second: second, third: third });
+        assert_eq!(
+            triple.differences(&pair()),
+            ["sizeof(Pair) is 12, but 8 is expected", "offsetof(Pair, third) is unknown"]
+        );
+        assert_eq!(
+            pair().differences(&triple),
+            ["offsetof(Pair, third) is missing", "sizeof(Pair) is 8, but 12 is expected"]
+        );
+    }
+}
@@ -1,8 +1,21 @@
 pub mod task;
 pub mod solver;
 pub mod ffi;
+pub mod plugin;
+pub mod layout;
 pub mod interval;
 pub mod solution;
+pub mod dual;
+pub mod model;
+pub mod expression;
+pub mod sensitivity;
+pub mod fitting;
+pub mod sivia;
+pub mod global_sensitivity;
+pub mod sde;
+pub mod capi;
+pub mod sandbox;
+pub mod watch;
 
 pub struct Frozen<T>(pub(crate) T);
 
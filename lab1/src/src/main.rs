@@ -1,21 +1,51 @@
+mod cli;
 mod config;
+mod diagnostics;
+mod experiment;
+mod manifest;
+mod sweep;
 pub mod plot;
 
-use crate::config::Config;
-use crate::plot::{Line, Plotter};
-use anyhow::Error;
-use libloading::{library_filename, Library};
-use plotters::prelude::{Color, ShapeStyle, BLUE, GREEN, RED};
-use project::ffi::{CanSolve, ExternalSolver};
+use crate::cli::{Cli, Command};
+use crate::config::{
+    Compare as CompareConfig, Config, FitMethod, Fitting as FittingConfig, GlobalMethod,
+    GlobalSensitivity as GlobalSensitivityConfig, Quantity, Sensitivity as SensitivityConfig,
+    Sivia as SiviaConfig, Stochastic as StochasticConfig, StochasticScheme,
+};
+use crate::diagnostics::Problem;
+use crate::experiment::Prepared;
+use crate::plot::{Area, Line, Plotter};
+use anyhow::{bail, Context, Error};
+use clap::Parser;
+use itertools::Itertools;
+use plotters::prelude::{Color, ShapeStyle, BLACK, BLUE, CYAN, GREEN, MAGENTA, RED, YELLOW};
+use project::capi;
+use project::ffi::{self, CanSolve, ExternalSolver};
+use project::fitting::{self, Method, Observations, Options};
+use project::global_sensitivity::{Sampling, Study};
 use project::interval::Interval;
+use project::plugin::{Parameters, Registry, BUILTIN};
+use project::sandbox::{self, SandboxSolver, Wire};
 use project::solution::{Solution, StopCondition};
 use project::solver::{Either, EulerSolver, Solver};
-use project::task::{f, CauchyTask};
+use project::model::{Model, Reaction};
+use project::sde::{
+    Ensemble, EulerMaruyama, Milstein, SdeTask, StochasticRungeKutta, StochasticSolver,
+};
+use project::sensitivity::{Sensitivity, SensitivityTask};
+use project::sivia::{self, Classification, Constraint};
+use project::task::{f, Sentinel};
+use project::watch::{Snapshot, Staging};
 use project::Frozen;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::ops::{Add, Mul, Neg, Sub};
-use std::sync::LazyLock;
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::iter::once;
+use std::ops::{Add, Deref, Mul, Range};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::{LazyLock, OnceLock};
+use std::thread;
+use std::time::Duration;
 
 fn build_line(
     xs: &[f64],
@@ -57,105 +87,972 @@ fn build_line_interval(
     ]
 }
 
-const CONFIG_PATH: &'static str = "config.toml";
+/// Config with command-line overrides applied, which is set once at startup by [`run`]
+static CONFIG: Loaded = Loaded(OnceLock::new());
 
-static CONFIG: LazyLock<Config> = LazyLock::new(|| {
-    let Ok(mut config_file) = File::open(CONFIG_PATH) else {
-        return Config::default();
-    };
-    let mut buffer = String::new();
-    config_file
-        .read_to_string(&mut buffer)
-        .expect("Could not read config file");
-    toml::from_str(&buffer)
-        .map_err(|e| format!("{} at {:?}", e.message(), e.span()))
-        .expect("Could not parse config file")
-});
-
-static LIBRARY: LazyLock<Library> = LazyLock::new(|| {
-    let mut path = CONFIG.general.lib_dir.clone();
-    let solver_lib_name = library_filename(&CONFIG.general.solver);
-    path.push(solver_lib_name);
-    unsafe { Library::new(path).expect("Could not load solver library") }
-});
-
-fn get_task<N>(coeffs: [N; 2]) -> CauchyTask<f64, N>
+struct Loaded(OnceLock<Config>);
+
+impl Deref for Loaded {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        self.0.get().expect("Config should be loaded before use")
+    }
+}
+
+/// How often plugin libraries are checked for changes in watch mode
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+static PLUGINS: LazyLock<Registry> =
+    LazyLock::new(|| unsafe { Registry::scan(CONFIG.general.plugin_dirs()) });
+
+fn data_range<'a>(values: impl IntoIterator<Item = &'a f64>) -> Range<f64> {
+    let (min, max) = values
+        .into_iter()
+        .fold((0.0f64, 0.0f64), |(min, max), &x| (min.min(x), max.max(x)));
+    let margin = (max - min).max(f64::EPSILON) * 0.05;
+    min - margin..max + margin
+}
+
+fn draw_sensitivity(config: &SensitivityConfig) -> Result<(), Error> {
+    let task = SensitivityTask::automatic(&Reaction, config.parameters);
+    let sensitivity = Sensitivity::compute(
+        get_solver()?.as_mut(),
+        &task,
+        StopCondition::Timed {
+            maximum: CONFIG.general.t_max,
+        },
+    )?;
+
+    let normalized = [sensitivity.normalized(0), sensitivity.normalized(1)];
+    let ts = sensitivity.state().time();
+    let colors = [RED, GREEN, BLUE];
+    let lines = normalized.iter().enumerate().flat_map(|(j, solution)| {
+        (0..solution.components()).map(move |i| {
+            Line::new(
+                ts.iter().cloned().zip(solution[i].iter().cloned()),
+                colors[i % colors.len()],
+                format!("k_{} : x_{}", j + 1, i + 1),
+                j > 0,
+            )
+        })
+    });
+
+    Plotter::new(
+        CONFIG.general.output_dir.join("sensitivity.svg"),
+        CONFIG.plotting.plot_size,
+        (
+            CONFIG.plotting.viewport.x.clone(),
+            data_range(normalized.iter().flat_map(|it| (0..it.components()).flat_map(|i| &it[i]))),
+        ),
+        lines,
+    )
+    .draw(CONFIG.plotting.output_type)
+}
+
+/// Builtin Euler solver takes only the step into account
+fn builtin_parameters() -> Result<Parameters, Error> {
+    CONFIG.solver_parameters(BUILTIN, &[Parameters::STEP])
+}
+
+/// Parameters of the builtin solver or the one from `registry` from its config section
+fn solver_parameters(registry: &Registry, name: &str) -> Result<Parameters, Error> {
+    match name {
+        BUILTIN => builtin_parameters(),
+        name => CONFIG.solver_parameters(name, &registry.find(name)?.metadata().parameters),
+    }
+}
+
+fn get_solver<N>() -> Result<Frozen<impl Solver<f64, N>>, Error>
 where
-    N: From<f64> + Copy + Neg<Output = N> + Mul<Output = N> + Sub<Output = N> + 'static,
+    for<'a> ExternalSolver<'a, f64, N>: CanSolve<f64, N>,
+    N: Clone + Add<Output = N> + Wire + Sentinel + 'static,
+    f64: Mul<N, Output = N>,
 {
-    CauchyTask::new(
-        [
-            f(move |_, &[x1, _, _]| -coeffs[0] * x1),
-            f(move |_, &[x1, x2, _]| coeffs[0] * x1 - coeffs[1] * x2),
-            f(move |_, &[_, x2, _]| coeffs[1] * x2),
-        ],
-        0.0,
-        [1.0, 0.0, 0.0].map(N::from),
-    )
+    solver_by_name(&CONFIG.general.solver)
 }
 
-fn get_solver<N>() -> Frozen<impl Solver<f64, N>>
+/// Builtin solver or the one from [`PLUGINS`] with parameters from its config section
+fn solver_by_name<N>(name: &str) -> Result<Frozen<impl Solver<f64, N>>, Error>
 where
     for<'a> ExternalSolver<'a, f64, N>: CanSolve<f64, N>,
-    N: Clone + Add<Output = N> + 'static,
+    N: Clone + Add<Output = N> + Wire + Sentinel + 'static,
     f64: Mul<N, Output = N>,
 {
-    if CONFIG.general.solver == "builtin" {
-        Either::Left(EulerSolver::new(0.1))
+    solver_from(&PLUGINS, name)
+}
+
+/// Builtin solver or the one from `registry` with parameters from its config section
+fn solver_from<'r, N>(registry: &'r Registry, name: &str) -> Result<Frozen<impl Solver<f64, N> + 'r>, Error>
+where
+    for<'a> ExternalSolver<'a, f64, N>: CanSolve<f64, N>,
+    N: Clone + Add<Output = N> + Wire + Sentinel + 'static,
+    f64: Mul<N, Output = N>,
+{
+    solver_with(registry, name, &solver_parameters(registry, name)?)
+}
+
+/// Builtin solver or the one from `registry` with given `parameters`, which runs in a helper
+/// process if sandbox is enabled
+fn solver_with<'r, N>(
+    registry: &'r Registry,
+    name: &str,
+    parameters: &Parameters,
+) -> Result<Frozen<impl Solver<f64, N> + 'r>, Error>
+where
+    for<'a> ExternalSolver<'a, f64, N>: CanSolve<f64, N>,
+    N: Clone + Add<Output = N> + Wire + Sentinel + 'static,
+    f64: Mul<N, Output = N>,
+{
+    Ok(if name == BUILTIN {
+        Either::Left(EulerSolver::new(parameters.step))
     } else {
-        Either::Right(unsafe { ExternalSolver::build(&*LIBRARY) }.expect("Cannot build solver"))
+        let plugin = registry.find(name)?;
+        Either::Right(
+            if CONFIG.general.sandbox {
+                let timeout = Duration::from_secs_f64(CONFIG.general.sandbox_timeout);
+                Either::Right(SandboxSolver::build(plugin, parameters, timeout)?)
+            } else {
+                Either::Left(ExternalSolver::build(plugin, parameters)?)
+            }
+            .rewrap(),
+        )
     }
-    .rewrap()
+    .rewrap())
 }
 
-fn main() -> Result<(), Error> {
-    let solution_interval = Solution::compute(
-        get_solver().as_mut(),
-        &get_task([Interval::new(0.576, 0.578), Interval::from(0.422)]),
-        StopCondition::Timed {
-            maximum: CONFIG.general.t_max,
+/// Order of accuracy of the solver, builtin one is Euler method
+fn solver_order(name: &str) -> Result<u32, Error> {
+    match name {
+        BUILTIN => Ok(1),
+        name => Ok(PLUGINS.find(name)?.metadata().order),
+    }
+}
+
+/// Solves the same experiment with every solver and compares them with the one of the highest order
+fn run_compare(config: &CompareConfig) -> Result<(), Error> {
+    if config.solvers.is_empty() {
+        bail!("At least one solver should be listed for comparison");
+    }
+    let experiment = experiment::select(config.experiment.as_deref())?;
+    let prepared = Prepared::new(&experiment)?;
+    let variables = prepared.equations.variables();
+    let solutions = config
+        .solvers
+        .iter()
+        .map(|name| {
+            let mut solver = solver_with(&PLUGINS, name, &prepared.parameters_of(&PLUGINS, name)?)?;
+            let solution = prepared.solve_with(solver.as_mut()).with_context(|| format!("Solver `{name}` failed"))?;
+            Ok((name.as_str(), solver_order(name)?, solution))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // The first one of the highest order is considered the most accurate
+    let reference = solutions
+        .iter()
+        .enumerate()
+        .max_by(|(i, a), (j, b)| a.1.cmp(&b.1).then(j.cmp(i)))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let (reference_name, _, reference_solution) = &solutions[reference];
+
+    let mut csv = File::create(CONFIG.general.output_dir.join("compare.csv"))?;
+    writeln!(csv, "solver, order, component, max_error, rms_error")?;
+    println!("Errors of experiment `{}` against `{reference_name}`:", experiment.name);
+    println!("{:<20} {:>5} {:>9} {:>12} {:>12}", "solver", "order", "component", "max", "rms");
+    for (name, order, solution) in &solutions {
+        for (i, variable) in variables.iter().enumerate() {
+            let errors = solution
+                .time()
+                .iter()
+                .zip(&solution[i])
+                .map(|(&t, x)| (x - reference_solution.interpolate(i, t).unwrap_or(f64::NAN)).abs())
+                .collect::<Vec<_>>();
+            let max = errors.iter().copied().fold(0.0, f64::max);
+            let rms = (errors.iter().map(|it| it * it).sum::<f64>()
+                / errors.len().max(1) as f64)
+                .sqrt();
+            writeln!(csv, "{name}, {order}, {variable}, {max}, {rms}")?;
+            println!("{name:<20} {order:>5} {variable:>9} {max:>12.3e} {rms:>12.3e}");
+        }
+    }
+
+    let colors = [RED, GREEN, BLUE, MAGENTA, CYAN, BLACK, YELLOW];
+    let lines = solutions
+        .iter()
+        .enumerate()
+        .flat_map(|(k, (name, _, solution))| {
+            variables.iter().enumerate().map(move |(i, variable)| {
+                Line::new(
+                    solution.time().iter().copied().zip(solution[i].iter().copied()),
+                    colors[k % colors.len()].stroke_width(if k == reference { 2 } else { 1 }),
+                    format!("{name}: {variable}"),
+                    k != reference,
+                )
+            })
+        });
+
+    Plotter::new(
+        CONFIG.general.output_dir.join("compare.svg"),
+        CONFIG.plotting.plot_size,
+        (
+            CONFIG.plotting.viewport.x.clone(),
+            CONFIG.plotting.viewport.y.clone(),
+        ),
+        lines,
+    )
+    .draw(CONFIG.plotting.output_type)
+}
+
+fn run_fitting(config: &FittingConfig) -> Result<(), Error> {
+    let observations = Observations::from_csv(BufReader::new(File::open(&config.data)?))?
+        .with_weights(config.weights.clone())?;
+    let options = Options {
+        method: match config.method {
+            FitMethod::LevenbergMarquardt => Method::LevenbergMarquardt,
+            FitMethod::NelderMead => Method::NelderMead,
         },
+        max_iterations: config.max_iterations,
+        ..Default::default()
+    };
+    let result = fitting::fit(
+        &Reaction,
+        &observations,
+        config.initial,
+        &mut get_solver()?,
+        &options,
+    )?;
+
+    println!(
+        "Fitted with {:?} in {} iterations, cost = {:e}",
+        result.method, result.iterations, result.cost
     );
+    for (idx, (p, e)) in result
+        .parameters
+        .iter()
+        .zip(result.standard_errors())
+        .enumerate()
+    {
+        println!("k_{} = {} ± {}", idx + 1, p, e);
+    }
+    println!("covariance = {:?}", result.covariance);
 
-    let solution_bench = Solution::compute(
-        get_solver().as_mut(),
-        &get_task([0.577, 0.422]),
+    let fitted = Solution::compute(
+        get_solver()?.as_mut(),
+        &Reaction.build(result.parameters),
         StopCondition::Timed {
             maximum: CONFIG.general.t_max,
         },
-    );
+    )?;
 
-    // Save csv file with computed values
-    let mut csv_output_file = File::create(CONFIG.general.output_dir.join("data.csv"))?;
-    writeln!(csv_output_file, "t, x1, x2, x3")?;
-    for (idx, t) in solution_bench.time().iter().enumerate() {
+    let mut csv_output_file = File::create(CONFIG.general.output_dir.join("fit.csv"))?;
+    writeln!(csv_output_file, "t, component, observed, fitted, residual")?;
+    for (m, r) in observations.measurements().iter().zip(&result.residuals) {
         writeln!(
             csv_output_file,
-            "{}, {}, {}, {}",
-            t, solution_bench[0][idx], solution_bench[1][idx], solution_bench[2][idx]
+            "{}, x{}, {}, {}, {}",
+            m.time,
+            m.component + 1,
+            m.value,
+            fitted.interpolate(m.component, m.time).unwrap_or(f64::NAN),
+            r
         )?;
     }
 
-    let ts = solution_bench.time();
+    let ts = fitted.time();
+    let colors = [RED, GREEN, BLUE];
+    let lines = (0..fitted.components()).flat_map(|i| {
+        let color = colors[i % colors.len()];
+        [
+            Line::new(
+                ts.iter().cloned().zip(fitted[i].iter().cloned()),
+                color.stroke_width(2),
+                format!("x_{}", i + 1),
+                false,
+            ),
+            Line::points(
+                observations
+                    .measurements()
+                    .iter()
+                    .filter(|m| m.component == i)
+                    .map(|m| (m.time, m.value)),
+                color,
+                format!("x_{} measured", i + 1),
+            ),
+        ]
+    });
     Plotter::new(
-        CONFIG.general.output_dir.join("plot.svg"),
+        CONFIG.general.output_dir.join("fit.svg"),
         CONFIG.plotting.plot_size,
         (
             CONFIG.plotting.viewport.x.clone(),
             CONFIG.plotting.viewport.y.clone(),
         ),
+        lines,
+    )
+    .draw(CONFIG.plotting.output_type)
+}
+
+fn run_sivia(config: &SiviaConfig) -> Result<(), Error> {
+    let observations = Observations::from_csv(BufReader::new(File::open(&config.data)?))?;
+    let constraints = Constraint::from_observations(&observations, config.error);
+    let domain = config
+        .domain
+        .clone()
+        .map(|range| Interval::new(range.start, range.end));
+    let paving = sivia::sivia(
+        &Reaction,
+        &constraints,
+        domain,
+        &mut get_solver()?,
+        &sivia::Options {
+            epsilon: config.epsilon,
+            ..Default::default()
+        },
+    )?;
+
+    let mut csv_output_file = File::create(CONFIG.general.output_dir.join("paving.csv"))?;
+    writeln!(csv_output_file, "class, k1_start, k1_end, k2_start, k2_end")?;
+    for ([k1, k2], classification) in &paving.boxes {
+        writeln!(
+            csv_output_file,
+            "{:?}, {}, {}, {}, {}",
+            classification,
+            k1.start(),
+            k1.end(),
+            k2.start(),
+            k2.end()
+        )?;
+    }
+
+    for classification in [
+        Classification::Consistent,
+        Classification::Undetermined,
+        Classification::Inconsistent,
+    ] {
+        println!(
+            "{:?}: {} boxes",
+            classification,
+            paving.classified(classification).count()
+        );
+    }
+    println!("Paving is approximate, the discretization error of the solver is not enclosed");
+
+    let areas = paving.boxes.iter().map(|([k1, k2], classification)| {
+        let color = match classification {
+            Classification::Consistent => RED.filled(),
+            Classification::Inconsistent => BLUE.mix(0.3).filled(),
+            Classification::Undetermined => YELLOW.filled(),
+        };
+        Area::new(k1.start()..k1.end(), k2.start()..k2.end(), color)
+    });
+    Plotter::new(
+        CONFIG.general.output_dir.join("paving.svg"),
+        CONFIG.plotting.plot_size,
+        (config.domain[0].clone(), config.domain[1].clone()),
+        [],
+    )
+    .with_areas(areas)
+    .draw(CONFIG.plotting.output_type)
+}
+
+/// Scalar `quantity` of the `component` of the solution
+fn quantity_of(solution: &Solution<f64, f64>, component: usize, quantity: Quantity) -> f64 {
+    let values = &solution[component];
+    let peak = (0..values.len()).max_by(|&a, &b| values[a].total_cmp(&values[b]));
+    match (quantity, peak) {
+        (Quantity::Final, _) => values.last().copied().unwrap_or(f64::NAN),
+        (Quantity::Peak, Some(peak)) => values[peak],
+        (Quantity::PeakTime, Some(peak)) => solution.time()[peak],
+        (_, None) => f64::NAN,
+    }
+}
+
+fn run_global_sensitivity(config: &GlobalSensitivityConfig) -> Result<(), Error> {
+    if config.component == 0 || config.component > 3 {
+        bail!("Component should be in 1..=3, got {}", config.component);
+    }
+    let component = config.component - 1;
+    let quantity = config.quantity;
+    let study = Study {
+        model: &Reaction,
+        ranges: config.ranges.clone(),
+        solver: get_solver,
+        quantity: move |solution: &Solution<f64, f64>| quantity_of(solution, component, quantity),
+        stop: StopCondition::Timed {
+            maximum: CONFIG.general.t_max,
+        },
+    };
+    let sampling = Sampling {
+        samples: config.samples,
+        seed: config.seed,
+        threads: config.threads,
+    };
+
+    let mut csv_output_file = File::create(CONFIG.general.output_dir.join("gsa.csv"))?;
+    match config.method {
+        GlobalMethod::Sobol => {
+            let indices = study.sobol(&sampling)?;
+            println!(
+                "Sobol indices from {} evaluations, variance = {:e}",
+                indices.evaluations, indices.variance
+            );
+            writeln!(csv_output_file, "rank, parameter, first_order, total")?;
+            for (rank, idx) in indices.ranking().into_iter().enumerate() {
+                println!(
+                    "{}. k_{}: S = {:.4}, S_T = {:.4}",
+                    rank + 1,
+                    idx + 1,
+                    indices.first_order[idx],
+                    indices.total[idx]
+                );
+                writeln!(
+                    csv_output_file,
+                    "{}, k{}, {}, {}",
+                    rank + 1,
+                    idx + 1,
+                    indices.first_order[idx],
+                    indices.total[idx]
+                )?;
+            }
+        }
+        GlobalMethod::Morris => {
+            let effects = study.morris(config.levels, &sampling)?;
+            println!("Morris screening from {} evaluations", effects.evaluations);
+            writeln!(csv_output_file, "rank, parameter, mean, mean_absolute, deviation")?;
+            for (rank, idx) in effects.ranking().into_iter().enumerate() {
+                println!(
+                    "{}. k_{}: mu = {:.4}, mu* = {:.4}, sigma = {:.4}",
+                    rank + 1,
+                    idx + 1,
+                    effects.mean[idx],
+                    effects.mean_absolute[idx],
+                    effects.deviation[idx]
+                );
+                writeln!(
+                    csv_output_file,
+                    "{}, k{}, {}, {}, {}",
+                    rank + 1,
+                    idx + 1,
+                    effects.mean[idx],
+                    effects.mean_absolute[idx],
+                    effects.deviation[idx]
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn compute_ensemble(config: &StochasticConfig) -> Result<Ensemble, Error> {
+    let [s1, s2, s3] = config.noise;
+    let task = SdeTask::new(
+        Reaction.build(config.parameters),
         [
-            build_line_interval(ts, &solution_interval[0], &RED, "x_1", false),
-            build_line_interval(ts, &solution_interval[1], &GREEN, "x_2", false),
-            build_line_interval(ts, &solution_interval[2], &BLUE, "x_3", false),
-            build_line(ts, &solution_bench[0], RED.stroke_width(2), "x_1"),
-            build_line(ts, &solution_bench[1], GREEN.stroke_width(2), "x_1"),
-            build_line(ts, &solution_bench[2], BLUE.stroke_width(2), "x_1"),
-        ]
-        .into_iter()
-        .flatten(),
+            f(move |_, &[x1, _, _]| s1 * x1),
+            f(move |_, &[_, x2, _]| s2 * x2),
+            f(move |_, &[_, _, x3]| s3 * x3),
+        ],
+    );
+    let stop = StopCondition::Timed {
+        maximum: CONFIG.general.t_max,
+    };
+
+    match config.scheme {
+        StochasticScheme::EulerMaruyama => Ensemble::compute(
+            &mut StochasticSolver::new(EulerMaruyama, config.step),
+            &task,
+            stop,
+            config.paths,
+            config.seed,
+        ),
+        StochasticScheme::Milstein => Ensemble::compute(
+            &mut StochasticSolver::new(Milstein, config.step),
+            &task,
+            stop,
+            config.paths,
+            config.seed,
+        ),
+        StochasticScheme::RungeKutta => Ensemble::compute(
+            &mut StochasticSolver::new(StochasticRungeKutta, config.step),
+            &task,
+            stop,
+            config.paths,
+            config.seed,
+        ),
+    }
+}
+
+fn run_stochastic(config: &StochasticConfig) -> Result<(), Error> {
+    let ensemble = compute_ensemble(config)?;
+    let (lower, upper) = config.quantiles;
+    let mean = ensemble.mean();
+    let variance = ensemble.variance();
+    let quantiles = [ensemble.quantile(lower), ensemble.quantile(upper)];
+
+    let mut csv_output_file = File::create(CONFIG.general.output_dir.join("sde.csv"))?;
+    write!(csv_output_file, "t")?;
+    for i in 1..=mean.components() {
+        write!(
+            csv_output_file,
+            ", x{i}_mean, x{i}_variance, x{i}_q{lower}, x{i}_q{upper}"
+        )?;
+    }
+    writeln!(csv_output_file)?;
+    for (idx, t) in mean.time().iter().enumerate() {
+        write!(csv_output_file, "{}", t)?;
+        for i in 0..mean.components() {
+            write!(
+                csv_output_file,
+                ", {}, {}, {}, {}",
+                mean[i][idx], variance[i][idx], quantiles[0][i][idx], quantiles[1][i][idx]
+            )?;
+        }
+        writeln!(csv_output_file)?;
+    }
+
+    let ts = mean.time();
+    let colors = [RED, GREEN, BLUE];
+    let lines = (0..mean.components()).flat_map(|i| {
+        let color = colors[i % colors.len()];
+        once(Line::new(
+            ts.iter().cloned().zip(mean[i].iter().cloned()),
+            color.stroke_width(2),
+            format!("E[x_{}]", i + 1),
+            false,
+        ))
+        .chain(quantiles.iter().zip([lower, upper]).map(move |(q, level)| {
+            Line::new(
+                ts.iter().cloned().zip(q[i].iter().cloned()),
+                color.mix(0.5),
+                format!("q_{level}[x_{}]", i + 1),
+                true,
+            )
+        }))
+    });
+    Plotter::new(
+        CONFIG.general.output_dir.join("sde.svg"),
+        CONFIG.plotting.plot_size,
+        (
+            CONFIG.plotting.viewport.x.clone(),
+            CONFIG.plotting.viewport.y.clone(),
+        ),
+        lines,
     )
-    .draw(CONFIG.plotting.output_type)?;
+    .draw(CONFIG.plotting.output_type)
+}
 
+/// Solves the experiment with its solver, loading plugins from fresh copies of the libraries.
+/// Solver, plugins and copies are all dropped before returning.
+fn watch_run(prepared: &Prepared) -> Result<Solution<f64, f64>, Error> {
+    let staging = Staging::new(CONFIG.general.plugin_dirs())?;
+    // SAFETY: the same libraries as in `PLUGINS` are loaded
+    let registry = unsafe { Registry::scan(staging.dirs()) };
+    for (path, error) in registry.rejected() {
+        eprintln!("Skipped {}: {error:#}", path.file_name().unwrap_or_default().to_string_lossy());
+    }
+    let name = prepared.solver();
+    let mut solver = solver_with(&registry, name, &prepared.parameters_of(&registry, name)?)?;
+    prepared.solve_with(solver.as_mut())
+}
+
+/// Prints how `current` solution differs from the `previous` one, interpolated to its points
+fn print_difference(variables: &[String], previous: &Solution<f64, f64>, current: &Solution<f64, f64>) {
+    println!("{:>9}  {:>12}  {:>12}  {:>12}", "component", "max |diff|", "rms diff", "final value");
+    for (i, variable) in variables.iter().enumerate() {
+        let differences = current
+            .time()
+            .iter()
+            .zip(&current[i])
+            .map(|(&t, x)| x - previous.interpolate(i, t).unwrap_or(f64::NAN))
+            .collect::<Vec<_>>();
+        let max = differences.iter().fold(0.0f64, |max, it| max.max(it.abs()));
+        let rms = (differences.iter().map(|it| it * it).sum::<f64>() / differences.len().max(1) as f64).sqrt();
+        let last = current[i].last().copied().unwrap_or(f64::NAN);
+        println!("{variable:>9}  {max:>12.3e}  {rms:>12.3e}  {last:>12.6}");
+    }
+}
+
+/// Solves the experiment called `name` again whenever a library in plugin directories changes,
+/// printing how the solution differs from the previous successful run
+fn run_watch(name: Option<&str>) -> Result<(), Error> {
+    let experiment = experiment::select(name)?;
+    let prepared = Prepared::new(&experiment)?;
+    let dirs = CONFIG.general.plugin_dirs();
+    let mut snapshot = Snapshot::take(dirs);
+    let mut previous = None;
+    for run in 1.. {
+        match watch_run(&prepared) {
+            Ok(solution) => {
+                println!(
+                    "Run {run} of `{}`: {} points with `{}`",
+                    experiment.name,
+                    solution.time().len(),
+                    prepared.solver()
+                );
+                if let Some(previous) = &previous {
+                    print_difference(prepared.equations.variables(), previous, &solution);
+                }
+                previous = Some(solution);
+            }
+            Err(e) => eprintln!("Run {run} failed: {e:#}"),
+        }
+
+        println!(
+            "Watching {} for changes",
+            dirs.iter().map(|it| it.display().to_string()).collect::<Vec<_>>().join(", ")
+        );
+        // Change is taken only once it stays the same for an interval, so that a library
+        // which is still being written is not loaded
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let current = Snapshot::take(dirs);
+            if current == snapshot {
+                continue;
+            }
+            thread::sleep(WATCH_INTERVAL);
+            if Snapshot::take(dirs) == current {
+                for path in current.changes(&snapshot) {
+                    println!("Changed {}", path.display());
+                }
+                snapshot = current;
+                break;
+            }
+        }
+    }
     Ok(())
 }
+
+/// Prints every discovered solver together with supported types and parameters
+fn list_solvers() {
+    println!("builtin: Euler method of order 1, any type, parameters: step");
+    for plugin in PLUGINS.plugins() {
+        let metadata = plugin.metadata();
+        println!(
+            "{}: order {}, {}, types: {}, parameters: {} ({})",
+            metadata.name,
+            metadata.order,
+            if metadata.implicit { "implicit" } else { "explicit" },
+            metadata.suffixes.join(", "),
+            metadata.parameters.join(", "),
+            plugin.path().display()
+        );
+    }
+    for (path, error) in PLUGINS.rejected() {
+        eprintln!("Skipped {}: {error:#}", path.display());
+    }
+}
+
+/// Halves the step of the solver `levels` times and estimates the order of accuracy from
+/// differences between solutions of the `experiment` with consecutive steps. Solver of the
+/// experiment is used if `name` is not given.
+fn run_converge(experiment: Option<&str>, name: Option<&str>, levels: u32) -> Result<(), Error> {
+    let experiment = experiment::select(experiment)?;
+    let prepared = Prepared::new(&experiment)?;
+    let name = name.unwrap_or(prepared.solver());
+    let base = prepared.parameters_of(&PLUGINS, name)?;
+    let solutions = (0..=levels)
+        .map(|level| {
+            let parameters = Parameters {
+                step: base.step / 2f64.powi(level as i32),
+                ..base.clone()
+            };
+            let solution = prepared
+                .solve_with(solver_with(&PLUGINS, name, &parameters)?.as_mut())
+                .with_context(|| format!("Solver `{name}` failed with step {}", parameters.step))?;
+            Ok((parameters.step, solution))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    // Points of the coarser solution are on the grid of the finer one as well, except for the
+    // last one which may be beyond its end
+    let differences = solutions
+        .windows(2)
+        .map(|pair| {
+            let [(_, coarse), (_, fine)] = pair else { unreachable!() };
+            let end = fine.time().last().copied().unwrap_or(f64::NEG_INFINITY);
+            (0..coarse.components())
+                .flat_map(|i| {
+                    coarse
+                        .time()
+                        .iter()
+                        .zip(&coarse[i])
+                        .filter(move |(&t, _)| t <= end)
+                        .map(move |(&t, x)| (x - fine.interpolate(i, t).unwrap_or(f64::NAN)).abs())
+                })
+                // Unlike `f64::max`, a NaN difference is kept
+                .fold(0.0, |max: f64, x| if x > max || x.is_nan() { x } else { max })
+        })
+        .collect::<Vec<_>>();
+    if let Some(k) = differences.iter().position(|it| !it.is_finite()) {
+        bail!(
+            "Solutions with steps {} and {} differ by {}, the solver has probably diverged",
+            solutions[k].0,
+            solutions[k + 1].0,
+            differences[k]
+        );
+    }
+
+    let mut csv = File::create(CONFIG.general.output_dir.join("converge.csv"))?;
+    writeln!(csv, "step, points, difference, order")?;
+    println!(
+        "Convergence of `{name}` of declared order {} on experiment `{}`:",
+        solver_order(name)?,
+        experiment.name
+    );
+    println!("{:>12} {:>8} {:>12} {:>8}", "step", "points", "difference", "order");
+    for (k, (step, solution)) in solutions.iter().enumerate().take(differences.len()) {
+        let difference = differences[k];
+        let order = k
+            .checked_sub(1)
+            .map(|previous| (differences[previous] / difference).log2())
+            .unwrap_or(f64::NAN);
+        let points = solution.time().len();
+        writeln!(csv, "{step}, {points}, {difference}, {order}")?;
+        println!("{step:>12.4e} {points:>8} {difference:>12.3e} {order:>8.3}");
+    }
+    Ok(())
+}
+
+/// Checks values of the config, its experiments and the solvers they refer to, returns every
+/// problem found with its place in the config
+fn validate_config() -> Vec<String> {
+    let mut problems = CONFIG.source.unknown_keys.clone();
+    problems.extend(CONFIG.problems());
+
+    let mut solvers = vec![(CONFIG.general.solver.as_str(), vec!["general".to_string(), "solver".to_string()])];
+    for (i, it) in CONFIG.experiments.iter().enumerate() {
+        let keys = ["experiment".to_string(), i.to_string()];
+        if CONFIG.experiments[..i].iter().any(|other| other.name == it.name) {
+            problems.push(Problem::new(
+                &["experiment", &i.to_string(), "name"],
+                format!("Experiment `{}` is defined more than once", it.name),
+            ));
+        }
+        match Prepared::new(it) {
+            Ok(prepared) => solvers.push((prepared.solver(), [&keys[..], &["solver".to_string()]].concat())),
+            Err(e) => problems.push(Problem {
+                keys: keys.to_vec(),
+                message: format!("Experiment `{}`: {e:#}", it.name),
+            }),
+        }
+    }
+    if let Some(compare) = &CONFIG.compare {
+        let keys = ["compare".to_string(), "solvers".to_string()];
+        solvers.extend(compare.solvers.iter().enumerate().map(|(i, it)| {
+            (it.as_str(), [&keys[..], &[i.to_string()]].concat())
+        }));
+    }
+    let mut unknown_solver = false;
+    for (name, keys) in solvers.into_iter().unique_by(|(name, _)| *name) {
+        if let Err(e) = solver_parameters(&PLUGINS, name) {
+            unknown_solver |= PLUGINS.get(name).is_none();
+            problems.push(Problem {
+                keys,
+                message: format!("Solver `{name}`: {e:#}"),
+            });
+        }
+    }
+    // Missing solver is often explained by a wrong directory or a library which is not loaded
+    if unknown_solver {
+        let key = if CONFIG.general.plugin_dirs.is_empty() { "lib_dir" } else { "plugin_dirs" };
+        for dir in CONFIG.general.plugin_dirs().iter().filter(|it| !it.is_dir()) {
+            problems.push(Problem::new(
+                &["general", key],
+                format!("Plugin directory {} does not exist", dir.display()),
+            ));
+        }
+        for (path, error) in PLUGINS.rejected() {
+            problems.push(Problem::new(
+                &["general", key],
+                format!("Library {} is rejected: {error:#}", path.display()),
+            ));
+        }
+    }
+
+    let data = [
+        CONFIG.fitting.as_ref().map(|it| ("fitting", &it.data)),
+        CONFIG.sivia.as_ref().map(|it| ("sivia", &it.data)),
+    ];
+    // Both sections fit the reaction model, so measured components should be within its size
+    let size = Reaction.build([1.0, 1.0]).size();
+    for (section, path) in data.into_iter().flatten() {
+        if !path.is_file() {
+            problems.push(Problem::new(
+                &[section, "data"],
+                format!("[{section}] data file {} does not exist", path.display()),
+            ));
+            continue;
+        }
+        let observations = File::open(path)
+            .map_err(Error::from)
+            .and_then(|it| Observations::from_csv(BufReader::new(it)));
+        let message = match observations {
+            Ok(observations) => match observations.measurements().iter().find(|it| it.component >= size) {
+                Some(it) => format!(
+                    "[{section}] data file {} has column x{}, but the model has {size} components",
+                    path.display(),
+                    it.component + 1
+                ),
+                None => continue,
+            },
+            Err(e) => format!("[{section}] data file {}: {e:#}", path.display()),
+        };
+        problems.push(Problem::new(&[section, "data"], message));
+    }
+    if let Some(compare) = &CONFIG.compare {
+        if compare.solvers.is_empty() {
+            problems.push(Problem::new(&["compare", "solvers"], "[compare] should list at least one solver"));
+        }
+        if let Err(e) = experiment::select(compare.experiment.as_deref()) {
+            problems.push(Problem::new(&["compare", "experiment"], format!("[compare]: {e:#}")));
+        }
+    }
+    if let Some(Err(e)) = CONFIG.sweep.as_ref().map(sweep::check) {
+        problems.push(Problem::new(&["sweep"], format!("[sweep]: {e:#}")));
+    }
+    CONFIG.source.sort(&mut problems);
+    problems.iter().map(|it| CONFIG.source.render(it)).collect()
+}
+
+/// Runs experiments from the config or the default one if there are none
+fn run_experiments(csv: bool, plot: bool) -> Result<(), Error> {
+    for it in experiment::configured().iter() {
+        experiment::run(it, &CONFIG.general.output_dir, csv, plot)
+            .with_context(|| format!("Experiment `{}` failed", it.name))?;
+    }
+    Ok(())
+}
+
+/// Runs every experiment, then every analysis which has its config section
+fn run_all() -> Result<(), Error> {
+    run_experiments(true, true)?;
+
+    if let Some(sensitivity) = &CONFIG.sensitivity {
+        draw_sensitivity(sensitivity)?;
+    }
+
+    if let Some(fitting) = &CONFIG.fitting {
+        run_fitting(fitting)?;
+    }
+
+    if let Some(sivia) = &CONFIG.sivia {
+        run_sivia(sivia)?;
+    }
+
+    if let Some(global_sensitivity) = &CONFIG.global_sensitivity {
+        run_global_sensitivity(global_sensitivity)?;
+    }
+
+    if let Some(stochastic) = &CONFIG.stochastic {
+        run_stochastic(stochastic)?;
+    }
+
+    if let Some(compare) = &CONFIG.compare {
+        run_compare(compare)?;
+    }
+
+    if let Some(sweep) = &CONFIG.sweep {
+        sweep::run_sweep(sweep)?;
+    }
+
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Error> {
+    // Helper process gets everything from its host, so config is not loaded for it
+    if let Some(Command::SandboxHost { plugin, suffix }) = &cli.command {
+        sandbox::serve(plugin, suffix)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let loaded = match &cli.command {
+        Some(Command::Rerun { manifest, .. }) => manifest::config(manifest, &cli.overrides)?,
+        _ => Config::load(cli.config.as_deref(), &cli.overrides)?,
+    };
+    if CONFIG.0.set(loaded).is_err() {
+        unreachable!("Config is loaded only once");
+    }
+
+    // Everything which computes is checked first, so that all problems are reported together
+    // instead of one of them after a part of the work
+    let computes = !matches!(
+        cli.command,
+        Some(
+            Command::ListSolvers
+                | Command::ValidateConfig
+                | Command::GenBindings { .. }
+                | Command::GenHeader { .. }
+        )
+    );
+    if computes {
+        let problems = validate_config();
+        if !problems.is_empty() {
+            for problem in &problems {
+                eprintln!("{problem}");
+            }
+            bail!("Config has {} problem(s), nothing is run", problems.len());
+        }
+    }
+
+    match cli.command {
+        None => run_all()?,
+        Some(Command::Solve) => run_experiments(true, false)?,
+        Some(Command::Plot) => run_experiments(false, true)?,
+        Some(Command::Compare { solvers, experiment }) => {
+            let configured = CONFIG.compare.as_ref();
+            let solvers = match configured {
+                _ if !solvers.is_empty() => solvers,
+                Some(compare) => compare.solvers.clone(),
+                None => bail!("No solvers are given and the config has no [compare] section"),
+            };
+            let experiment = experiment.or_else(|| configured.and_then(|it| it.experiment.clone()));
+            run_compare(&CompareConfig { solvers, experiment })?
+        }
+        Some(Command::Sweep) => match &CONFIG.sweep {
+            Some(sweep) => sweep::run_sweep(sweep)?,
+            None => bail!("The config has no [sweep] section"),
+        },
+        Some(Command::Converge { solver, experiment, levels }) => {
+            run_converge(experiment.as_deref(), solver.as_deref(), levels)?
+        }
+        Some(Command::ListSolvers) => list_solvers(),
+        Some(Command::ValidateConfig) => {
+            let problems = validate_config();
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("{problem}");
+                }
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("Config is valid");
+        }
+        Some(Command::Watch { experiment }) => run_watch(experiment.as_deref())?,
+        Some(Command::Rerun { manifest, dir }) => {
+            let dir = dir.unwrap_or_else(|| manifest.parent().unwrap_or(Path::new("")).join("rerun"));
+            manifest::rerun(&manifest, &dir)?
+        }
+        Some(Command::GenBindings { path }) => write_bindings(&path)?,
+        Some(Command::GenHeader { path }) => write_header(&path)?,
+        Some(Command::SandboxHost { .. }) => unreachable!(),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn write_bindings(path: &Path) -> Result<(), Error> {
+    fs::write(path, ffi::bindings_header()).with_context(|| format!("Could not write {}", path.display()))
+}
+
+fn write_header(path: &Path) -> Result<(), Error> {
+    fs::write(path, capi::HEADER).with_context(|| format!("Could not write {}", path.display()))
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::config::{Config, Experiment, Override, Uncertain};
+use crate::experiment::{self, Prepared};
+use crate::{CONFIG, PLUGINS};
+use anyhow::{bail, Context, Error};
+use project::solution::Solution;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Record of a single run of an experiment, which is enough to reproduce it with `rerun`
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the program which has made the run
+    pub version: String,
+    /// Seconds since the Unix epoch when the run has finished
+    pub created: u64,
+    /// Experiment with every value it has been run with, e.g. ones of a sweep
+    pub experiment: Experiment,
+    pub task: Task,
+    pub solver: SolverRecord,
+    pub statistics: Statistics,
+    /// SHA-256 of every written file by its path relative to the run directory
+    pub outputs: BTreeMap<String, String>,
+    /// Config with defaults and command-line overrides applied, directories are absolute
+    pub config: toml::Table,
+}
+
+/// Task as it has been solved, with equations and initial values of the model resolved
+#[derive(Serialize, Deserialize)]
+pub struct Task {
+    pub equations: Vec<String>,
+    pub parameters: BTreeMap<String, Uncertain>,
+    pub initial: BTreeMap<String, Uncertain>,
+    pub initial_time: f64,
+    pub t_max: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SolverRecord {
+    pub name: String,
+    /// Plugin library as an absolute path, none for the builtin solver
+    pub library: Option<PathBuf>,
+    /// SHA-256 of the library
+    pub sha256: Option<String>,
+    pub sandbox: bool,
+    pub step: f64,
+    pub absolute_tolerance: f64,
+    pub relative_tolerance: f64,
+    pub max_iterations: usize,
+    pub options: BTreeMap<String, String>,
+}
+
+/// Timing and steps of the nominal solution
+#[derive(Serialize, Deserialize)]
+pub struct Statistics {
+    /// Seconds spent on the nominal solution
+    pub nominal_seconds: f64,
+    /// Seconds spent on the interval solution, if anything is uncertain
+    pub interval_seconds: Option<f64>,
+    pub points: usize,
+    pub min_step: f64,
+    pub max_step: f64,
+    pub mean_step: f64,
+    /// Final value of every variable
+    pub last: BTreeMap<String, f64>,
+}
+
+/// Canonical form of `path` if it exists or at least an absolute one, so that `rerun` from
+/// another directory finds the same files
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Config of the run with directories of `general` made absolute
+fn recorded_config() -> Result<toml::Table, Error> {
+    let mut config = toml::Table::try_from(&*CONFIG)?;
+    let general = &CONFIG.general;
+    let dirs = [
+        ("output_dir", toml::Value::try_from(absolute(&general.output_dir))?),
+        ("lib_dir", toml::Value::try_from(absolute(&general.lib_dir))?),
+        (
+            "plugin_dirs",
+            toml::Value::try_from(general.plugin_dirs.iter().map(|it| absolute(it)).collect::<Vec<_>>())?,
+        ),
+    ];
+    if let Some(toml::Value::Table(table)) = config.get_mut("general") {
+        table.extend(dirs.map(|(key, value)| (key.to_string(), value)));
+    }
+    Ok(config)
+}
+
+/// Hex SHA-256 of the file contents
+fn sha256(path: &Path) -> Result<String, Error> {
+    let hash = Sha256::digest(fs::read(path).with_context(|| format!("Could not read {}", path.display()))?);
+    Ok(hash.iter().map(|it| format!("{it:02x}")).collect())
+}
+
+impl Manifest {
+    /// Manifest of `prepared` solved into `solution` in `nominal` and `interval` time, which has
+    /// written `outputs` into `dir`
+    pub fn new(
+        prepared: &Prepared,
+        solution: &Solution<f64, f64>,
+        (nominal, interval): (Duration, Option<Duration>),
+        dir: &Path,
+        outputs: &[PathBuf],
+    ) -> Result<Self, Error> {
+        let experiment = prepared.experiment;
+        let variables = prepared.equations.variables();
+        let name = prepared.solver();
+        let library = PLUGINS.get(name).map(|it| absolute(it.path()));
+        let parameters = prepared.solver_parameters()?;
+
+        let time = solution.time();
+        let steps = time.windows(2).map(|it| it[1] - it[0]).collect::<Vec<_>>();
+        let statistics = Statistics {
+            nominal_seconds: nominal.as_secs_f64(),
+            interval_seconds: interval.map(|it| it.as_secs_f64()),
+            points: time.len(),
+            min_step: steps.iter().copied().fold(f64::NAN, f64::min),
+            max_step: steps.iter().copied().fold(f64::NAN, f64::max),
+            mean_step: steps.iter().sum::<f64>() / steps.len() as f64,
+            last: variables
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), solution[i].last().copied().unwrap_or(f64::NAN)))
+                .collect(),
+        };
+
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |it| it.as_secs()),
+            experiment: experiment.clone(),
+            task: Task {
+                equations: prepared.sources.clone(),
+                parameters: experiment.parameters.clone(),
+                initial: variables.iter().cloned().zip(prepared.initial.iter().copied()).collect(),
+                initial_time: experiment.initial_time,
+                t_max: prepared.t_max(),
+            },
+            solver: SolverRecord {
+                name: name.to_string(),
+                sha256: library.as_deref().map(sha256).transpose()?,
+                library,
+                sandbox: CONFIG.general.sandbox,
+                step: parameters.step,
+                absolute_tolerance: parameters.absolute_tolerance,
+                relative_tolerance: parameters.relative_tolerance,
+                max_iterations: parameters.max_iterations,
+                options: parameters.options,
+            },
+            statistics,
+            outputs: outputs
+                .iter()
+                .map(|it| Ok((relative(it, dir), sha256(it)?)))
+                .collect::<Result<_, Error>>()?,
+            config: recorded_config().context("Could not record the config")?,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid manifest {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, toml::to_string(self)?).with_context(|| format!("Could not write {}", path.display()))
+    }
+}
+
+/// Path of an output relative to the run directory, so that `rerun` finds it in its own one.
+/// Absolute outputs stay as they are.
+fn relative(path: &Path, dir: &Path) -> String {
+    path.strip_prefix(dir).unwrap_or(path).to_string_lossy().into_owned()
+}
+
+/// Config of the run recorded in the manifest at `path` with `overrides` applied
+pub fn config(path: &Path, overrides: &[Override]) -> Result<Config, Error> {
+    let manifest = Manifest::read(path)?;
+    Config::parse(None, toml::to_string(&manifest.config)?, overrides)
+}
+
+/// Runs the experiment of the manifest at `path` again into `dir`, then compares written files
+/// with the recorded ones. Config should be the one of the manifest, see [`config`].
+pub fn rerun(path: &Path, dir: &Path) -> Result<(), Error> {
+    let manifest = Manifest::read(path)?;
+    if let (Some(library), Some(recorded)) = (&manifest.solver.library, &manifest.solver.sha256) {
+        let current = PLUGINS.get(&manifest.solver.name).map(|it| absolute(it.path()));
+        if current.as_ref() != Some(library) {
+            eprintln!(
+                "Warning: solver `{}` was loaded from {}, now it is {}",
+                manifest.solver.name,
+                library.display(),
+                current.map_or("not found".to_string(), |it| it.display().to_string())
+            );
+        } else if &sha256(library)? != recorded {
+            eprintln!("Warning: library {} has changed since the run", library.display());
+        }
+    }
+
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    experiment::run(&manifest.experiment, dir, true, true)?;
+
+    let mut differ = vec![];
+    for (name, recorded) in &manifest.outputs {
+        let current = sha256(&dir.join(name))?;
+        println!("{name}: {}", if &current == recorded { "identical" } else { "differs" });
+        if &current != recorded {
+            differ.push(name.as_str());
+        }
+    }
+    if !differ.is_empty() {
+        bail!("Rerun has produced different {}", differ.join(", "));
+    }
+    Ok(())
+}
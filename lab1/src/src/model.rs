@@ -0,0 +1,55 @@
+use crate::task::{f, CauchyTask, Sentinel};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Number type which a [`Model`] can be instantiated with.
+/// Implemented for `f64`, [`crate::interval::Interval<f64>`] and [`crate::dual::Dual<f64>`].
+pub trait Number:
+    From<f64>
+    + Copy
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + Sentinel
+    + 'static
+{
+}
+
+impl<N> Number for N where
+    N: From<f64>
+        + Copy
+        + Add<Output = N>
+        + Sub<Output = N>
+        + Mul<Output = N>
+        + Div<Output = N>
+        + Neg<Output = N>
+        + Sentinel
+        + 'static
+{
+}
+
+/// Cauchy task parameterized by [P] numbers, such as rate constants or initial conditions.
+///
+/// The task must be buildable for any [`Number`], so the same model can be solved with plain
+/// numbers, intervals or dual numbers for differentiation with respect to parameters.
+pub trait Model<T, const P: usize> {
+    fn build<N: Number>(&self, parameters: [N; P]) -> CauchyTask<T, N>;
+}
+
+/// Consecutive reactions `x1 -> x2 -> x3` with rate constants `[k1, k2]`, starting from `x1 = 1`
+pub struct Reaction;
+
+impl Model<f64, 2> for Reaction {
+    fn build<N: Number>(&self, [k1, k2]: [N; 2]) -> CauchyTask<f64, N> {
+        CauchyTask::new(
+            [
+                f(move |_, &[x1, _, _]| -k1 * x1),
+                f(move |_, &[x1, x2, _]| k1 * x1 - k2 * x2),
+                f(move |_, &[_, x2, _]| k2 * x2),
+            ],
+            0.0,
+            [1.0, 0.0, 0.0].map(N::from),
+        )
+    }
+}
@@ -11,6 +11,13 @@ pub struct Line {
     style: ShapeStyle,
     label: String,
     dashed: bool,
+    markers: bool,
+}
+
+/// Filled rectangle between two opposite corners
+pub struct Area {
+    corners: [(f64, f64); 2],
+    style: ShapeStyle,
 }
 
 pub struct Plotter {
@@ -19,6 +26,7 @@ pub struct Plotter {
     range_y: Range<f64>,
     range_x: Range<f64>,
     lines: Vec<Line>,
+    areas: Vec<Area>,
 }
 
 impl Line {
@@ -33,6 +41,28 @@ impl Line {
             style: style.into(),
             label: label.into(),
             dashed,
+            markers: false,
+        }
+    }
+
+    /// Data points drawn as separate markers, e.g. measurements
+    pub fn points(
+        data_points: impl IntoIterator<Item = (f64, f64)>,
+        style: impl Into<ShapeStyle>,
+        label: impl Into<String>,
+    ) -> Self {
+        Self {
+            markers: true,
+            ..Self::new(data_points, style, label, false)
+        }
+    }
+}
+
+impl Area {
+    pub fn new(x: Range<f64>, y: Range<f64>, style: impl Into<ShapeStyle>) -> Self {
+        Self {
+            corners: [(x.start, y.start), (x.end, y.end)],
+            style: style.into(),
         }
     }
 }
@@ -50,9 +80,16 @@ impl Plotter {
             range_y: viewport.1,
             range_x: viewport.0,
             lines: lines.into_iter().collect(),
+            areas: vec![],
         }
     }
 
+    /// Adds rectangles drawn below the lines
+    pub fn with_areas(mut self, areas: impl IntoIterator<Item = Area>) -> Self {
+        self.areas.extend(areas);
+        self
+    }
+
     fn draw_raw<DB: DrawingBackend>(self, root: DrawingArea<DB, Shift>) -> Result<(), Error>
     where
         <DB as DrawingBackend>::ErrorType: 'static,
@@ -72,8 +109,17 @@ impl Plotter {
 
         chart.configure_mesh().draw()?;
 
+        chart.draw_series(
+            self.areas
+                .into_iter()
+                .map(|area| Rectangle::new(area.corners, area.style)),
+        )?;
+
         for line in self.lines {
-            if line.dashed {
+            if line.markers {
+                let style = line.style.filled();
+                chart.draw_series(line.data_points.into_iter().map(|p| Circle::new(p, 3, style)))
+            } else if line.dashed {
                 chart.draw_series(DashedLineSeries::new(line.data_points, 5, 5, line.style))
             } else {
                 chart.draw_series(LineSeries::new(line.data_points, line.style))
@@ -84,8 +130,8 @@ impl Plotter {
 
         chart
             .configure_series_labels()
-            .background_style(&WHITE.mix(0.8))
-            .border_style(&BLACK)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
             .draw()?;
 
         root.present()?;
@@ -94,10 +140,7 @@ impl Plotter {
     }
 
     pub fn draw(self, output_type: Output) -> Result<(), Error> {
-        let output_path = match output_type {
-            Output::Png => self.output_path.with_extension("png"),
-            Output::Svg => self.output_path.with_extension("svg"),
-        };
+        let output_path = self.output_path.with_extension(output_type.extension());
         let size = self.size;
         match output_type {
             Output::Png => {
@@ -0,0 +1,452 @@
+use crate::layout::{describe, Layout};
+use anyhow::{anyhow, bail, Context, Error};
+use libloading::Library;
+use std::collections::BTreeMap;
+use std::ffi::{c_char, CStr, CString};
+use std::env::consts::DLL_EXTENSION;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the solver ABI, which must match `SOLVER_ABI_VERSION` in `solvers/include/solver.h`
+pub const ABI_VERSION: u32 = 7;
+
+/// Name of the Euler solver built into the host, which is available without any plugins
+pub const BUILTIN: &str = "builtin";
+
+#[repr(C)]
+struct RawMetadata {
+    abi_version: u32,
+    name: *const c_char,
+    order: u32,
+    implicit: bool,
+    suffixes: *const *const c_char,
+    parameters: *const *const c_char,
+}
+
+/// Description of a solver, exported by plugin via `solver_metadata` symbol
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub abi_version: u32,
+    pub name: String,
+    pub order: u32,
+    pub implicit: bool,
+    /// Suffixes of exported `solver_*` symbols, e.g. `f64_If64`
+    pub suffixes: Vec<String>,
+    /// Names of accepted parameters
+    pub parameters: Vec<String>,
+}
+
+/// Parameters of a solver, passed to it when it is prepared for a task.
+/// Plugin declares which of them it takes into account in [`Metadata::parameters`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+    pub step: f64,
+    pub absolute_tolerance: f64,
+    pub relative_tolerance: f64,
+    pub max_iterations: usize,
+    /// Solver-specific options, which are passed as is
+    pub options: BTreeMap<String, String>,
+}
+
+#[repr(C)]
+struct RawOption {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+pub(crate) struct RawParameters {
+    step: f64,
+    absolute_tolerance: f64,
+    relative_tolerance: f64,
+    max_iterations: usize,
+    options: *const RawOption,
+    options_count: usize,
+}
+
+/// [`RawParameters`] together with the strings it points to
+pub(crate) struct ParameterBlock {
+    raw: RawParameters,
+    _options: Vec<RawOption>,
+    _strings: Vec<CString>,
+}
+
+/// Loaded solver library together with its verified metadata
+pub struct Plugin {
+    library: Library,
+    metadata: Metadata,
+    path: PathBuf,
+}
+
+/// Set of loaded plugins with distinct solver names, e.g. found in plugin directories
+#[derive(Default)]
+pub struct Registry {
+    plugins: Vec<Plugin>,
+    /// Libraries which could not be loaded as plugins together with the reason
+    rejected: Vec<(PathBuf, Error)>,
+}
+
+/// Reads null-terminated array of C strings
+unsafe fn read_list(mut list: *const *const c_char) -> Result<Vec<String>, Error> {
+    let mut result = vec![];
+    if list.is_null() {
+        return Ok(result);
+    }
+    while !(*list).is_null() {
+        result.push(CStr::from_ptr(*list).to_str()?.to_string());
+        list = list.add(1);
+    }
+    Ok(result)
+}
+
+impl Metadata {
+    pub fn supports(&self, suffix: &str) -> bool {
+        self.suffixes.iter().any(|it| it == suffix)
+    }
+
+    pub fn accepts(&self, parameter: &str) -> bool {
+        self.parameters.iter().any(|it| it == parameter)
+    }
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            step: 0.1,
+            absolute_tolerance: 1e-8,
+            relative_tolerance: 1e-6,
+            max_iterations: 100,
+            options: BTreeMap::new(),
+        }
+    }
+}
+
+impl Parameters {
+    pub const STEP: &'static str = "step";
+    pub const ABSOLUTE_TOLERANCE: &'static str = "absolute_tolerance";
+    pub const RELATIVE_TOLERANCE: &'static str = "relative_tolerance";
+    pub const MAX_ITERATIONS: &'static str = "max_iterations";
+
+    /// Parses common parameters out of `values`, the rest of them become [`Self::options`].
+    /// Every key should be one of `accepted`.
+    pub fn parse(
+        values: impl IntoIterator<Item = (String, String)>,
+        accepted: &[impl AsRef<str>],
+    ) -> Result<Self, Error> {
+        let mut result = Self::default();
+        for (key, value) in values {
+            if !accepted.iter().any(|it| it.as_ref() == key) {
+                bail!(
+                    "Unknown solver parameter `{key}`, accepted are: {}",
+                    accepted.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(", ")
+                );
+            }
+            let invalid = || format!("Invalid value `{value}` of solver parameter `{key}`");
+            match key.as_str() {
+                Self::STEP => result.step = value.parse().with_context(invalid)?,
+                Self::ABSOLUTE_TOLERANCE => {
+                    result.absolute_tolerance = value.parse().with_context(invalid)?
+                }
+                Self::RELATIVE_TOLERANCE => {
+                    result.relative_tolerance = value.parse().with_context(invalid)?
+                }
+                Self::MAX_ITERATIONS => {
+                    result.max_iterations = value.parse().with_context(invalid)?
+                }
+                _ => {
+                    result.options.insert(key, value);
+                }
+            }
+        }
+        if !(result.step > 0.0 && result.step.is_finite()) {
+            bail!("Solver step should be positive, got {}", result.step);
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn to_ffi(&self) -> Result<ParameterBlock, Error> {
+        let strings = self
+            .options
+            .iter()
+            .flat_map(|(key, value)| [key, value])
+            .map(|it| CString::new(it.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Solver options should not contain null characters")?;
+        let options = strings
+            .chunks(2)
+            .map(|pair| RawOption {
+                key: pair[0].as_ptr(),
+                value: pair[1].as_ptr(),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(ParameterBlock {
+            raw: RawParameters {
+                step: self.step,
+                absolute_tolerance: self.absolute_tolerance,
+                relative_tolerance: self.relative_tolerance,
+                max_iterations: self.max_iterations,
+                options: options.as_ptr(),
+                options_count: options.len(),
+            },
+            _options: options,
+            _strings: strings,
+        })
+    }
+}
+
+impl RawParameters {
+    /// Adds layout of the parameters, which are mirrored by `SolverParameters` in `solvers/include/solver.h`
+    pub(crate) fn describe(layout: &mut Layout) {
+        describe!(layout, "SolverParameters", Self {
+            step: step,
+            absolute_tolerance: absolute_tolerance,
+            relative_tolerance: relative_tolerance,
+            max_iterations: max_iterations,
+            options: options,
+            options_count: options_count,
+        });
+    }
+}
+
+impl ParameterBlock {
+    pub(crate) fn as_ptr(&self) -> *const RawParameters {
+        &self.raw
+    }
+}
+
+impl Plugin {
+    /// Loads library at `path` and verifies that it implements compatible solver ABI
+    ///
+    /// # Safety
+    /// Library initialization routines are run, and exported `solver_metadata` symbol must have
+    /// the signature declared in `solvers/include/solver.h`.
+    pub unsafe fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let library = Library::new(path)
+            .with_context(|| format!("Could not load solver library {}", path.display()))?;
+        let metadata = {
+            let symbol = library
+                .get::<extern "C" fn() -> *const RawMetadata>(b"solver_metadata\0")
+                .map_err(|_| {
+                    anyhow!(
+                        "{} does not export `solver_metadata`, it is either not a solver plugin \
+                         or built for outdated ABI",
+                        path.display()
+                    )
+                })?;
+            let raw = symbol()
+                .as_ref()
+                .ok_or_else(|| anyhow!("{} returned null metadata", path.display()))?;
+            if raw.abi_version != ABI_VERSION {
+                bail!(
+                    "{} is built for solver ABI v{}, but v{} is required, rebuild the plugin",
+                    path.display(),
+                    raw.abi_version,
+                    ABI_VERSION
+                );
+            }
+            if raw.name.is_null() {
+                bail!("{} has no solver name in its metadata", path.display());
+            }
+
+            Metadata {
+                abi_version: raw.abi_version,
+                name: CStr::from_ptr(raw.name).to_str()?.to_string(),
+                order: raw.order,
+                implicit: raw.implicit,
+                suffixes: read_list(raw.suffixes)?,
+                parameters: read_list(raw.parameters)?,
+            }
+        };
+
+        Ok(Self {
+            library,
+            metadata,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn library(&self) -> &Library {
+        &self.library
+    }
+}
+
+/// Shared libraries directly in `dir` in the order they are loaded, empty if it does not exist
+pub fn libraries(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths = entries
+        .filter_map(|it| it.ok().map(|it| it.path()))
+        .filter(|it| it.is_file() && it.extension().is_some_and(|it| it == DLL_EXTENSION))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Levenshtein distance between `a` and `b`, where swapping two adjacent characters is a single
+/// edit, as it is the most common typo
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let mut before = vec![0; b.len() + 1];
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for i in 1..=a.len() {
+        let mut next = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = row[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            next[j] = substitution.min(row[j] + 1).min(next[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                next[j] = next[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut row, next);
+    }
+    row[b.len()]
+}
+
+/// The most similar of `candidates` to `name`, if it is similar enough to be a misspelling
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|it| (edit_distance(name, it), it))
+        .filter(|&(distance, it)| distance <= (name.len().max(it.len()) / 3).max(1))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, it)| it)
+}
+
+impl Registry {
+    /// Tries to load every shared library in `dirs` (non-recursively) as a solver plugin.
+    /// Missing directories are skipped, libraries with the same solver name as an earlier one
+    /// are rejected.
+    ///
+    /// # Safety
+    /// Same as for [`Plugin::load`], for every library found.
+    pub unsafe fn scan(dirs: impl IntoIterator<Item = impl AsRef<Path>>) -> Self {
+        let mut result = Self::default();
+        for dir in dirs {
+            for path in libraries(dir.as_ref()) {
+                if let Err(error) = Plugin::load(&path).and_then(|it| result.insert(it).map(drop)) {
+                    result.rejected.push((path, error));
+                }
+            }
+        }
+        result
+    }
+
+    /// Adds `plugin` unless there is already one with the same solver name
+    pub fn insert(&mut self, plugin: Plugin) -> Result<&Plugin, Error> {
+        if let Some(other) = self.get(&plugin.metadata.name) {
+            bail!(
+                "Solver `{}` is already provided by {}",
+                plugin.metadata.name,
+                other.path.display()
+            );
+        }
+        self.plugins.push(plugin);
+        Ok(&self.plugins[self.plugins.len() - 1])
+    }
+
+    pub fn plugins(&self) -> &[Plugin] {
+        &self.plugins
+    }
+
+    pub fn rejected(&self) -> &[(PathBuf, Error)] {
+        &self.rejected
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Plugin> {
+        self.plugins.iter().find(|it| it.metadata.name == name)
+    }
+
+    /// Plugin by its solver name, error suggests the closest name if there is no such one
+    pub fn find(&self, name: &str) -> Result<&Plugin, Error> {
+        self.get(name).ok_or_else(|| {
+            let plugins = self.plugins.iter().map(|it| it.metadata.name.as_str());
+            let names = [BUILTIN].into_iter().chain(plugins);
+            match suggest(name, names.clone()) {
+                Some(similar) => anyhow!("Unknown solver `{name}`, did you mean `{similar}`?"),
+                None if self.plugins.is_empty() => anyhow!(
+                    "Unknown solver `{name}`, no plugins are found, only `{BUILTIN}` is available"
+                ),
+                None => anyhow!(
+                    "Unknown solver `{name}`, available are: {}",
+                    names.collect::<Vec<_>>().join(", ")
+                ),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|&(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("euler", "euler"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("eular", "euler"), 1);
+        assert_eq!(edit_distance("eulr", "euler"), 1);
+        // Swap of adjacent characters is a single edit
+        assert_eq!(edit_distance("ueler", "euler"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggestions() {
+        let names = ["euler", "runge-kutta", "adams-bashforth"];
+        assert_eq!(suggest("eulr", names), Some("euler"));
+        assert_eq!(suggest("runge-kuta", names), Some("runge-kutta"));
+        assert_eq!(suggest("adams", names), None);
+        assert_eq!(suggest("x", names), None);
+        assert_eq!(suggest("euler", []), None);
+    }
+
+    #[test]
+    fn unknown_solvers() {
+        let registry = Registry::default();
+        let error = registry.find("runge-kutta").err().unwrap().to_string();
+        assert_eq!(
+            error,
+            "Unknown solver `runge-kutta`, no plugins are found, only `builtin` is available"
+        );
+        let error = registry.find("buitlin").err().unwrap().to_string();
+        assert_eq!(error, "Unknown solver `buitlin`, did you mean `builtin`?");
+    }
+
+    #[test]
+    fn parses_parameters() {
+        let accepted = [Parameters::STEP, Parameters::MAX_ITERATIONS, "mode"];
+        let values = values(&[("step", "0.01"), ("max_iterations", "5"), ("mode", "fast")]);
+        let parameters = Parameters::parse(values, &accepted).unwrap();
+        assert_eq!(parameters.step, 0.01);
+        assert_eq!(parameters.max_iterations, 5);
+        assert_eq!(parameters.absolute_tolerance, Parameters::default().absolute_tolerance);
+        assert_eq!(parameters.options, BTreeMap::from([("mode".to_string(), "fast".to_string())]));
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let accepted = [Parameters::STEP];
+        let error = Parameters::parse(values(&[("order", "4")]), &accepted).err().unwrap();
+        assert_eq!(error.to_string(), "Unknown solver parameter `order`, accepted are: step");
+        for step in ["0", "-0.1", "inf", "NaN"] {
+            assert!(Parameters::parse(values(&[("step", step)]), &accepted).is_err(), "{step}");
+        }
+        let error = Parameters::parse(values(&[("step", "fast")]), &accepted).err().unwrap();
+        assert_eq!(error.to_string(), "Invalid value `fast` of solver parameter `step`");
+    }
+}
@@ -0,0 +1,814 @@
+//! Plugin solvers running in a separate helper process, so that a crash or a hang of the plugin
+//! becomes an error instead of taking the whole run down.
+//!
+//! The helper is the current executable started with [`HOST_COMMAND`] as its first argument,
+//! which should call [`serve`]. Both sides exchange length-prefixed frames over the standard
+//! streams of the helper, stderr of the helper is captured and attached to errors.
+//! A broken helper is reported as [`ProcessError`], which [`anyhow::Error::downcast_ref`] finds.
+
+use crate::ffi::{CanSolve, ExternalSolver};
+use crate::interval::Interval;
+use crate::plugin::{Parameters, Plugin};
+use crate::solver::Solver;
+use crate::task::{panic_message, CauchyTask, Derivatives, Function, Sentinel, VectorFunction};
+use crate::Frozen;
+use anyhow::{anyhow, bail, Context, Error};
+use std::any::Any;
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// First argument of the helper process, followed by the plugin path and the type suffix
+pub const HOST_COMMAND: &str = "sandbox-host";
+
+/// Frames longer than this are treated as a broken stream
+const MAX_FRAME: usize = 1 << 30;
+
+/// Only the tail of stderr of the helper is kept for error messages
+const STDERR_CAPACITY: usize = 16 * 1024;
+
+// Requests of the host
+const START: u8 = 0;
+const PREPARE: u8 = 1;
+const NEXT: u8 = 2;
+const BATCH: u8 = 3;
+const OUTPUTS: u8 = 4;
+
+// Responses of the helper
+const EVAL: u8 = 16;
+const DONE: u8 = 17;
+const FAILED: u8 = 18;
+
+/// Component index of [`EVAL`] request for the whole system
+const SYSTEM: u64 = u64::MAX;
+
+/// Value which is sent between the host and the helper
+pub trait Wire: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+
+    fn read(input: &mut &[u8]) -> Result<Self, Error>;
+}
+
+fn take<'a>(input: &mut &'a [u8], count: usize) -> Result<&'a [u8], Error> {
+    if input.len() < count {
+        bail!("Frame is truncated");
+    }
+    let (head, tail) = input.split_at(count);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! wire_number {
+    ($($ty:ty),*) => {$(
+        impl Wire for $ty {
+            fn write(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read(input: &mut &[u8]) -> Result<Self, Error> {
+                Ok(Self::from_le_bytes(take(input, size_of::<Self>())?.try_into()?))
+            }
+        }
+    )*};
+}
+
+wire_number!(u8, u64, f32, f64);
+
+impl Wire for usize {
+    fn write(&self, out: &mut Vec<u8>) {
+        (*self as u64).write(out)
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(u64::read(input)?.try_into()?)
+    }
+}
+
+impl Wire for bool {
+    fn write(&self, out: &mut Vec<u8>) {
+        u8::from(*self).write(out)
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(u8::read(input)? != 0)
+    }
+}
+
+impl Wire for String {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.len().write(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        let length = usize::read(input)?;
+        Ok(String::from_utf8(take(input, length)?.to_vec())?)
+    }
+}
+
+impl<T: Wire + Copy> Wire for Interval<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.start().write(out);
+        self.end().write(out);
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(Interval::new(T::read(input)?, T::read(input)?))
+    }
+}
+
+impl Wire for Parameters {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.step.write(out);
+        self.absolute_tolerance.write(out);
+        self.relative_tolerance.write(out);
+        self.max_iterations.write(out);
+        self.options.len().write(out);
+        for (key, value) in &self.options {
+            key.write(out);
+            value.write(out);
+        }
+    }
+
+    fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        let mut result = Parameters {
+            step: f64::read(input)?,
+            absolute_tolerance: f64::read(input)?,
+            relative_tolerance: f64::read(input)?,
+            max_iterations: usize::read(input)?,
+            ..Parameters::default()
+        };
+        for _ in 0..usize::read(input)? {
+            result.options.insert(String::read(input)?, String::read(input)?);
+        }
+        Ok(result)
+    }
+}
+
+fn write_slice<T: Wire>(values: &[T], out: &mut Vec<u8>) {
+    for value in values {
+        value.write(out);
+    }
+}
+
+fn read_vec<T: Wire>(input: &mut &[u8], count: usize) -> Result<Vec<T>, Error> {
+    (0..count).map(|_| T::read(input)).collect()
+}
+
+fn write_frame(output: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    output.write_all(&u32::try_from(frame.len()).map_err(io::Error::other)?.to_le_bytes())?;
+    output.write_all(frame)?;
+    output.flush()
+}
+
+/// Next frame or `None` once the stream is closed
+fn read_frame(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    match input.read_exact(&mut length) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        other => other?,
+    }
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_FRAME {
+        return Err(io::Error::new(ErrorKind::InvalidData, "frame is too long"));
+    }
+    let mut frame = vec![0; length];
+    input.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+/// Why the connection with the helper process is broken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakdown {
+    /// Process has exited or closed its streams, e.g. the plugin has crashed
+    Crashed,
+    /// Process has not answered within the timeout, e.g. the plugin hangs
+    TimedOut,
+    /// Process has answered something, which does not follow the protocol
+    Protocol,
+}
+
+/// Failure of the helper process, which has been terminated. The solver starts a new process
+/// when it is prepared again.
+#[derive(Debug)]
+pub struct ProcessError {
+    pub kind: Breakdown,
+    /// Tail of stderr of the process, trimmed
+    pub stderr: String,
+    message: String,
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)?;
+        if !self.stderr.is_empty() {
+            write!(f, ", its stderr:\n{}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+/// Running helper process
+struct Process {
+    child: Child,
+    input: BufWriter<ChildStdin>,
+    frames: Receiver<io::Result<Vec<u8>>>,
+    stderr: Arc<Mutex<Vec<u8>>>,
+    stderr_reader: Option<JoinHandle<()>>,
+}
+
+impl Process {
+    fn spawn(program: &Path, plugin: &Path, suffix: &str) -> Result<Self, Error> {
+        let mut child = Command::new(program)
+            .arg(HOST_COMMAND)
+            .arg(plugin)
+            .arg(suffix)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Could not start solver process {}", program.display()))?;
+
+        let mut output = child.stdout.take().unwrap();
+        let (sender, frames) = mpsc::channel();
+        thread::spawn(move || {
+            while let Some(frame) = read_frame(&mut output).transpose() {
+                let failed = frame.is_err();
+                if sender.send(frame).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let mut errors = child.stderr.take().unwrap();
+        let stderr = Arc::new(Mutex::new(vec![]));
+        let stderr_reader = thread::spawn({
+            let stderr = stderr.clone();
+            move || {
+                let mut buffer = [0; 4096];
+                while let Ok(count @ 1..) = errors.read(&mut buffer) {
+                    let mut stderr = stderr.lock().unwrap();
+                    stderr.extend_from_slice(&buffer[..count]);
+                    let excess = stderr.len().saturating_sub(STDERR_CAPACITY);
+                    stderr.drain(..excess);
+                }
+            }
+        });
+
+        Ok(Self {
+            input: BufWriter::new(child.stdin.take().unwrap()),
+            child,
+            frames,
+            stderr,
+            stderr_reader: Some(stderr_reader),
+        })
+    }
+
+    /// Kills the process if it still runs, returns how it has ended and its stderr
+    fn terminate(mut self) -> (String, String) {
+        let _ = self.child.kill();
+        let status = self.child.wait();
+        if let Some(reader) = self.stderr_reader.take() {
+            let _ = reader.join();
+        }
+        let status = match status {
+            Ok(status) => format!("process has exited with {status}"),
+            Err(e) => format!("process state is unknown: {e}"),
+        };
+        let stderr = self.stderr.lock().unwrap();
+        (status, String::from_utf8_lossy(&stderr).trim().to_string())
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Plugin solver running in a helper process, see the [module documentation](self).
+/// After a crash or a timeout the process is restarted by the next [`Solver::prepare`].
+pub struct SandboxSolver<T, N> {
+    process: Option<Process>,
+    program: PathBuf,
+    plugin: PathBuf,
+    name: String,
+    suffix: String,
+    parameters: Parameters,
+    timeout: Duration,
+    time: Option<T>,
+    state: Vec<N>,
+}
+
+impl<T, N> SandboxSolver<T, N>
+where
+    for<'a> ExternalSolver<'a, T, N>: CanSolve<T, N>,
+    T: Wire + Copy,
+    N: Wire + Sentinel,
+{
+    /// Starts helper process for `plugin`, which waits at most `timeout` for every answer of the solver
+    pub fn build(plugin: &Plugin, parameters: &Parameters, timeout: Duration) -> Result<Frozen<Self>, Error> {
+        let program = env::current_exe().context("Could not find executable of the solver process")?;
+        Self::build_with_program(&program, plugin, parameters, timeout)
+    }
+
+    /// Same as [`Self::build`], but the helper is `program` instead of the current executable
+    pub fn build_with_program(
+        program: &Path,
+        plugin: &Plugin,
+        parameters: &Parameters,
+        timeout: Duration,
+    ) -> Result<Frozen<Self>, Error> {
+        let mut result = Self {
+            process: None,
+            program: program.to_path_buf(),
+            plugin: plugin.path().to_path_buf(),
+            name: plugin.metadata().name.clone(),
+            suffix: <ExternalSolver<T, N> as CanSolve<T, N>>::suffix(),
+            parameters: parameters.clone(),
+            timeout,
+            time: None,
+            state: vec![],
+        };
+        result.start()?;
+        Ok(Frozen(result))
+    }
+}
+
+impl<T, N> SandboxSolver<T, N>
+where
+    T: Wire + Copy,
+    N: Wire + Sentinel,
+{
+    fn start(&mut self) -> Result<(), Error> {
+        let mut start = vec![START];
+        self.parameters.write(&mut start);
+        self.process = Some(Process::spawn(&self.program, &self.plugin, &self.suffix)?);
+        self.exchange(None, &start, "start")?;
+        Ok(())
+    }
+
+    /// Sends `request` and serves evaluations of `task` until the helper answers it.
+    /// The process is terminated if it does not answer in time or the stream breaks.
+    fn exchange(
+        &mut self,
+        task: Option<&CauchyTask<T, N>>,
+        request: &[u8],
+        action: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let Some(process) = self.process.as_mut() else {
+            bail!("Process of solver `{}` is not running, it should be prepared again", self.name);
+        };
+        match talk(process, task, request, self.timeout) {
+            Ok(Answer::Done(answer)) => Ok(answer),
+            Ok(Answer::Failed(message)) => bail!("{message}"),
+            Ok(Answer::Panicked(payload)) => bail!(
+                "Task function panicked while solver `{}` tried to {action}: {}",
+                self.name,
+                panic_message(&*payload)
+            ),
+            Err((kind, reason)) => {
+                let (status, stderr) = self.process.take().unwrap().terminate();
+                let message = format!("Solver `{}` failed to {action}: {reason}, {status}", self.name);
+                Err(ProcessError { kind, stderr, message }.into())
+            }
+        }
+    }
+}
+
+/// Outcome of a request, which has been answered by the helper
+enum Answer {
+    Done(Vec<u8>),
+    Failed(String),
+    /// Task function has panicked while the solver was running
+    Panicked(Box<dyn Any + Send>),
+}
+
+/// Sends `request` and serves evaluations of `task` until the helper answers.
+/// Error is the kind and the reason of the broken connection with the process.
+fn talk<T, N>(
+    process: &mut Process,
+    task: Option<&CauchyTask<T, N>>,
+    request: &[u8],
+    timeout: Duration,
+) -> Result<Answer, (Breakdown, String)>
+where
+    T: Wire + Copy,
+    N: Wire + Sentinel,
+{
+    let protocol = |reason: String| (Breakdown::Protocol, reason);
+    let mut panic = None;
+    write_frame(&mut process.input, request)
+        .map_err(|e| (Breakdown::Crashed, format!("could not send the request ({e})")))?;
+    loop {
+        let frame = match process.frames.recv_timeout(timeout) {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(e)) if e.kind() == ErrorKind::InvalidData => {
+                return Err(protocol(format!("could not read the answer ({e})")))
+            }
+            Ok(Err(e)) => return Err((Breakdown::Crashed, format!("could not read the answer ({e})"))),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err((Breakdown::Crashed, "the connection was lost".into()))
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                return Err((Breakdown::TimedOut, format!("no answer within {timeout:?}")))
+            }
+        };
+        let Some((&tag, mut payload)) = frame.split_first() else {
+            return Err(protocol("empty response".into()));
+        };
+        match tag {
+            EVAL => {
+                let task = task.ok_or_else(|| protocol("unexpected evaluation request".into()))?;
+                let outputs =
+                    evaluate(task, &mut payload, &mut panic).map_err(|e| protocol(format!("{e:#}")))?;
+                let mut reply = vec![OUTPUTS];
+                write_slice(&outputs, &mut reply);
+                write_frame(&mut process.input, &reply)
+                    .map_err(|e| (Breakdown::Crashed, format!("could not send the values ({e})")))?;
+            }
+            DONE | FAILED if panic.is_some() => return Ok(Answer::Panicked(panic.unwrap())),
+            DONE => return Ok(Answer::Done(payload.to_vec())),
+            FAILED => return Ok(Answer::Failed(String::read(&mut payload).unwrap_or_default())),
+            other => return Err(protocol(format!("unknown response {other}"))),
+        }
+    }
+}
+
+/// Answers [`EVAL`] request of the helper with values of `task`. Panic of a task function is
+/// stored in `panic` and sentinels are sent instead, just like the in-process path does.
+fn evaluate<T, N>(
+    task: &CauchyTask<T, N>,
+    payload: &mut &[u8],
+    panic: &mut Option<Box<dyn Any + Send>>,
+) -> Result<Vec<N>, Error>
+where
+    T: Wire + Copy,
+    N: Wire + Sentinel,
+{
+    let component = u64::read(payload)?;
+    let time = T::read(payload)?;
+    let inputs = read_vec::<N>(payload, task.size)?;
+    let count = if component == SYSTEM { task.size } else { 1 };
+    let mut outputs = (0..count).map(|_| N::sentinel()).collect::<Vec<_>>();
+    let result = catch_unwind(AssertUnwindSafe(|| match &task.derivatives {
+        Derivatives::System(f) if component == SYSTEM => {
+            f.eval(time, &inputs, &mut outputs);
+            Ok(())
+        }
+        Derivatives::Components(functions) => match functions.get(component as usize) {
+            Some(f) => {
+                outputs[0] = f.eval(time, &inputs);
+                Ok(())
+            }
+            None => Err(anyhow!("derivative of component {component} was requested")),
+        },
+        Derivatives::System(_) => Err(anyhow!("derivative of component {component} was requested")),
+    }));
+    match result {
+        Ok(result) => result?,
+        Err(payload) => {
+            panic.get_or_insert(payload);
+            outputs.iter_mut().for_each(|it| *it = N::sentinel());
+        }
+    }
+    Ok(outputs)
+}
+
+impl<T, N> Solver<T, N> for SandboxSolver<T, N>
+where
+    T: Wire + Copy,
+    N: Wire + Sentinel + Clone,
+{
+    fn prepare<'a>(this: Frozen<&'a mut Self>, task: &CauchyTask<T, N>) -> Result<&'a mut Self, Error> {
+        let this = this.0;
+        if this.process.is_none() {
+            this.start()?;
+        }
+        let mut request = vec![PREPARE];
+        task.size.write(&mut request);
+        matches!(task.derivatives, Derivatives::System(_)).write(&mut request);
+        task.initial_time.write(&mut request);
+        write_slice(&task.initial_conditions, &mut request);
+        this.exchange(Some(task), &request, "prepare for the task")?;
+        this.time = None;
+        Ok(this)
+    }
+
+    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> Result<(T, &[N]), Error> {
+        let answer = self.exchange(Some(task), &[NEXT], "compute next solution")?;
+        let mut answer = answer.as_slice();
+        let time = T::read(&mut answer)?;
+        self.state = read_vec(&mut answer, task.size)?;
+        self.time = Some(time);
+        Ok((time, &self.state))
+    }
+
+    fn next_batch(
+        &mut self,
+        task: &CauchyTask<T, N>,
+        max_steps: usize,
+        until: &T,
+        times: &mut Vec<T>,
+        states: &mut Vec<N>,
+    ) -> Result<bool, Error>
+    where
+        T: PartialOrd,
+        N: Clone,
+    {
+        let mut request = vec![BATCH];
+        max_steps.write(&mut request);
+        until.write(&mut request);
+        let answer = self.exchange(Some(task), &request, "compute next solutions")?;
+        let mut answer = answer.as_slice();
+        let count = usize::read(&mut answer)?;
+        if count > max_steps {
+            bail!(
+                "Solver `{}` reported {count} points, but at most {max_steps} were requested",
+                self.name
+            );
+        }
+        times.extend(read_vec::<T>(&mut answer, count)?);
+        states.extend(read_vec::<N>(&mut answer, count * task.size)?);
+        Ok(count < max_steps)
+    }
+}
+
+/// Streams of the helper, shared by the task functions which ask the host for values
+struct Channel {
+    input: RefCell<BufReader<io::Stdin>>,
+    output: RefCell<Box<dyn Write>>,
+}
+
+impl Channel {
+    fn send(&self, frame: &[u8]) -> Result<(), Error> {
+        Ok(write_frame(&mut *self.output.borrow_mut(), frame)?)
+    }
+
+    fn receive(&self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(read_frame(&mut *self.input.borrow_mut())?)
+    }
+
+    fn reply<E: std::fmt::Display>(&self, result: Result<Vec<u8>, E>) -> Result<(), Error> {
+        match result {
+            Ok(frame) => self.send(&frame),
+            Err(e) => {
+                let mut frame = vec![FAILED];
+                e.to_string().write(&mut frame);
+                self.send(&frame)
+            }
+        }
+    }
+
+    /// Asks the host for the derivative of `component` or of the whole system.
+    /// Panics if the host is gone, which the solver then reports as a failure.
+    fn eval<T: Wire + Copy, N: Wire>(&self, component: u64, time: T, inputs: &[N], outputs: &mut [N]) {
+        let mut request = vec![EVAL];
+        component.write(&mut request);
+        time.write(&mut request);
+        write_slice(inputs, &mut request);
+        let values = self.send(&request).and_then(|()| {
+            let frame = self.receive()?.ok_or_else(|| anyhow!("host has closed the stream"))?;
+            match frame.split_first() {
+                Some((&OUTPUTS, mut payload)) => read_vec::<N>(&mut payload, outputs.len()),
+                _ => bail!("unexpected frame instead of values"),
+            }
+        });
+        match values {
+            Ok(values) => outputs.iter_mut().zip(values).for_each(|(output, value)| *output = value),
+            Err(e) => panic!("Lost connection to the host: {e:#}"),
+        }
+    }
+}
+
+/// Task of the host, which functions are evaluated by the host
+fn proxy_task<T, N>(channel: &Rc<Channel>, payload: &mut &[u8]) -> Result<CauchyTask<T, N>, Error>
+where
+    T: Wire + Copy + 'static,
+    N: Wire + Sentinel + 'static,
+{
+    let size = usize::read(payload)?;
+    let system = bool::read(payload)?;
+    let initial_time = T::read(payload)?;
+    let initial_conditions = read_vec::<N>(payload, size)?.into_boxed_slice();
+    let derivatives = if system {
+        let channel = channel.clone();
+        Derivatives::System(VectorFunction::from_slice(size, move |t, inputs, outputs| {
+            channel.eval(SYSTEM, t, inputs, outputs)
+        }))
+    } else {
+        Derivatives::Components(
+            (0..size as u64)
+                .map(|i| {
+                    let channel = channel.clone();
+                    Function::from_slice(size, move |t, inputs| {
+                        let mut output = [N::sentinel()];
+                        channel.eval(i, t, inputs, &mut output);
+                        let [output] = output;
+                        output
+                    })
+                })
+                .collect(),
+        )
+    };
+    Ok(CauchyTask {
+        size,
+        initial_conditions,
+        initial_time,
+        derivatives,
+    })
+}
+
+fn serve_typed<T, N>(plugin: &Plugin, parameters: &Parameters, channel: Rc<Channel>) -> Result<(), Error>
+where
+    for<'a> ExternalSolver<'a, T, N>: CanSolve<T, N>,
+    T: Wire + Copy + PartialOrd + 'static,
+    N: Wire + Sentinel + Clone + 'static,
+{
+    let mut solver = match ExternalSolver::<T, N>::build(plugin, parameters) {
+        Ok(it) => it,
+        Err(e) => return channel.reply(Err(format!("{e:#}"))),
+    };
+    channel.send(&[DONE])?;
+
+    let mut task = None;
+    while let Some(frame) = channel.receive()? {
+        let (&tag, mut payload) = frame.split_first().context("Empty request")?;
+        let prepared = || task.as_ref().ok_or_else(|| anyhow!("Solver should be prepared for a task first"));
+        let result = match tag {
+            PREPARE => proxy_task(&channel, &mut payload).and_then(|it| {
+                ExternalSolver::prepare(solver.as_mut(), task.insert(it))?;
+                Ok(vec![DONE])
+            }),
+            NEXT => prepared().and_then(|task| {
+                let (time, state) = solver.0.next_solution(task)?;
+                let mut answer = vec![DONE];
+                time.write(&mut answer);
+                write_slice(state, &mut answer);
+                Ok(answer)
+            }),
+            BATCH => prepared().and_then(|task| {
+                let max_steps = usize::read(&mut payload)?;
+                let until = T::read(&mut payload)?;
+                let (mut times, mut states) = (vec![], vec![]);
+                solver.0.next_batch(task, max_steps, &until, &mut times, &mut states)?;
+                let mut answer = vec![DONE];
+                times.len().write(&mut answer);
+                write_slice(&times, &mut answer);
+                write_slice(&states, &mut answer);
+                Ok(answer)
+            }),
+            other => bail!("Unknown request {other}"),
+        };
+        channel.reply(result.map_err(|e| format!("{e:#}")))?;
+    }
+    Ok(())
+}
+
+/// Entry point of the helper process for `plugin` and type `suffix`, returns once the host
+/// closes the stream
+pub fn serve(plugin: &Path, suffix: &str) -> Result<(), Error> {
+    let output = protocol_output()?;
+    let channel = Rc::new(Channel {
+        input: RefCell::new(BufReader::new(io::stdin())),
+        output: RefCell::new(output),
+    });
+    let parameters = match channel.receive()? {
+        Some(frame) if frame.first() == Some(&START) => Parameters::read(&mut &frame[1..])?,
+        _ => bail!("Expected start request from the host"),
+    };
+    // SAFETY: the host has loaded the same library with its metadata already
+    let plugin = match unsafe { Plugin::load(plugin) } {
+        Ok(it) => it,
+        Err(e) => return channel.reply::<String>(Err(format!("{e:#}"))),
+    };
+
+    macro_rules! dispatch {
+        ($t:ty, $n:ty) => {
+            if suffix == <ExternalSolver<$t, $n> as CanSolve<$t, $n>>::suffix() {
+                return serve_typed::<$t, $n>(&plugin, &parameters, channel);
+            }
+        };
+    }
+    crate::for_each_binding!(dispatch);
+    channel.reply::<String>(Err(format!("Unknown type suffix `{suffix}`")))
+}
+
+/// Stream for the frames, which is the original stdout. The plugin may print to stdout on its
+/// own, so that descriptor is redirected to stderr.
+#[cfg(unix)]
+fn protocol_output() -> Result<Box<dyn Write>, Error> {
+    use std::fs::File;
+    use std::os::fd::{AsFd, AsRawFd};
+
+    let stdout = io::stdout();
+    let protocol = File::from(stdout.as_fd().try_clone_to_owned()?);
+    // SAFETY: both descriptors are open for the whole life of the process
+    if unsafe { libc::dup2(io::stderr().as_raw_fd(), stdout.as_raw_fd()) } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(Box::new(BufWriter::new(protocol)))
+}
+
+#[cfg(not(unix))]
+fn protocol_output() -> Result<Box<dyn Write>, Error> {
+    Ok(Box::new(BufWriter::new(io::stdout())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Wire>(value: &T) -> T {
+        let mut frame = vec![];
+        value.write(&mut frame);
+        let mut input = frame.as_slice();
+        let result = T::read(&mut input).unwrap();
+        assert!(input.is_empty(), "{} bytes are left", input.len());
+        result
+    }
+
+    #[test]
+    fn values_round_trip() {
+        assert_eq!(round_trip(&0xABu8), 0xAB);
+        assert_eq!(round_trip(&u64::MAX), u64::MAX);
+        assert_eq!(round_trip(&usize::MAX), usize::MAX);
+        assert_eq!(round_trip(&-1.5f32), -1.5);
+        assert_eq!(round_trip(&f64::MIN_POSITIVE), f64::MIN_POSITIVE);
+        assert!(round_trip(&f64::NAN).is_nan());
+        assert!(round_trip(&true));
+        assert!(!round_trip(&false));
+        assert_eq!(round_trip(&"résumé".to_string()), "résumé");
+        assert_eq!(round_trip(&String::new()), "");
+        let interval = round_trip(&Interval::new(-1.0f64, 2.5));
+        assert_eq!((interval.start(), interval.end()), (-1.0, 2.5));
+    }
+
+    #[test]
+    fn parameters_round_trip() {
+        let mut parameters = Parameters {
+            step: 0.25,
+            absolute_tolerance: 1e-9,
+            relative_tolerance: 1e-6,
+            max_iterations: 42,
+            ..Parameters::default()
+        };
+        parameters.options.insert("mode".to_string(), "hang".to_string());
+        parameters.options.insert("order".to_string(), "4".to_string());
+        let result = round_trip(&parameters);
+        assert_eq!(result.step, parameters.step);
+        assert_eq!(result.absolute_tolerance, parameters.absolute_tolerance);
+        assert_eq!(result.relative_tolerance, parameters.relative_tolerance);
+        assert_eq!(result.max_iterations, parameters.max_iterations);
+        assert_eq!(result.options, parameters.options);
+    }
+
+    #[test]
+    fn rejects_truncated_values() {
+        let mut frame = vec![];
+        "text".to_string().write(&mut frame);
+        frame.pop();
+        assert!(String::read(&mut frame.as_slice()).is_err());
+        assert!(f64::read(&mut [0u8; 7].as_slice()).is_err());
+    }
+
+    #[test]
+    fn frames_are_length_prefixed() {
+        let mut stream = vec![];
+        write_frame(&mut stream, b"first").unwrap();
+        write_frame(&mut stream, b"").unwrap();
+        write_frame(&mut stream, &[DONE, 1, 2]).unwrap();
+        assert_eq!(stream[..4], 5u32.to_le_bytes());
+
+        let mut input = stream.as_slice();
+        assert_eq!(read_frame(&mut input).unwrap().as_deref(), Some(&b"first"[..]));
+        assert_eq!(read_frame(&mut input).unwrap().as_deref(), Some(&[][..]));
+        assert_eq!(read_frame(&mut input).unwrap().as_deref(), Some(&[DONE, 1, 2][..]));
+        assert_eq!(read_frame(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_broken_frames() {
+        let mut stream = vec![];
+        write_frame(&mut stream, b"frame").unwrap();
+        stream.pop();
+        let error = read_frame(&mut stream.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+        let stream = (MAX_FRAME as u32 + 1).to_le_bytes();
+        let error = read_frame(&mut stream.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::solution::{Solution, StopCondition};
+use crate::task::{CauchyTask, Function};
+use crate::Frozen;
+use anyhow::{bail, Error};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f64::consts::TAU;
+use std::iter::{once, repeat_with};
+use std::ops::Deref;
+
+/// Stochastic differential equation in Itô form with diagonal noise
+/// ```math
+/// dX_1 = f_1(t, X) dt + g_1(t, X) dW_1
+/// ...
+/// dX_n = f_n(t, X) dt + g_n(t, X) dW_n
+/// ```
+///
+/// where drift f_i comes from the underlying [`CauchyTask`], diffusion g_i is
+/// [`Self::diffusion`] and W_i are independent Wiener processes.
+pub struct SdeTask<T, N> {
+    task: CauchyTask<T, N>,
+    diffusion: Box<[Function<T, N>]>,
+}
+
+/// Reproducible source of Wiener process increments
+pub struct Wiener {
+    rng: ChaCha8Rng,
+    spare: Option<f64>,
+}
+
+/// Single step of a numerical scheme for [`SdeTask`]
+pub trait Scheme {
+    /// Computes state at `time + step` from `state` at `time` given Wiener increments `dw`
+    fn advance(
+        &self,
+        task: &SdeTask<f64, f64>,
+        time: f64,
+        state: &[f64],
+        step: f64,
+        dw: &[f64],
+    ) -> Box<[f64]>;
+}
+
+/// Strong order 0.5
+pub struct EulerMaruyama;
+
+/// Strong order 1.0, derivative of the diffusion is approximated by central differences
+pub struct Milstein;
+
+/// Derivative-free strong order 1.0 scheme of Platen
+pub struct StochasticRungeKutta;
+
+pub struct StochasticSolver<S> {
+    scheme: S,
+    step: f64,
+    current_time: f64,
+    last_solution: Box<[f64]>,
+}
+
+/// Set of sample paths of the same [`SdeTask`] on the same time grid
+pub struct Ensemble {
+    paths: Vec<Solution<f64, f64>>,
+}
+
+impl<T, N> SdeTask<T, N> {
+    pub fn new<const S: usize>(task: CauchyTask<T, N>, diffusion: [Function<T, N>; S]) -> Self {
+        Self::from_parts(task, Vec::from(diffusion))
+    }
+
+    pub fn from_parts(task: CauchyTask<T, N>, diffusion: Vec<Function<T, N>>) -> Self {
+        assert_eq!(
+            task.size,
+            diffusion.len(),
+            "Every component should have a diffusion term"
+        );
+        Self {
+            task,
+            diffusion: diffusion.into_boxed_slice(),
+        }
+    }
+
+    pub fn diffusion(&self) -> &[Function<T, N>] {
+        &self.diffusion
+    }
+}
+
+impl<T, N> Deref for SdeTask<T, N> {
+    type Target = CauchyTask<T, N>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.task
+    }
+}
+
+impl Wiener {
+    pub fn new(seed: u64) -> Self {
+        Self::with_stream(seed, 0)
+    }
+
+    /// Independent generator for the same seed, e.g. for every path of an ensemble
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(stream);
+        Self { rng, spare: None }
+    }
+
+    /// Standard normal sample via Box–Muller transform
+    fn normal(&mut self) -> f64 {
+        if let Some(spare) = self.spare.take() {
+            return spare;
+        }
+        let u = 1.0 - self.rng.gen::<f64>();
+        let v = self.rng.gen::<f64>();
+        let radius = (-2.0 * u.ln()).sqrt();
+        self.spare = Some(radius * (TAU * v).sin());
+        radius * (TAU * v).cos()
+    }
+
+    /// Increment of Wiener process over `step`, distributed as `N(0, step)`
+    pub fn increment(&mut self, step: f64) -> f64 {
+        self.normal() * step.sqrt()
+    }
+}
+
+fn drift(task: &SdeTask<f64, f64>, time: f64, state: &[f64]) -> Box<[f64]> {
+    let mut result = Box::<[f64]>::from(state);
+    task.derivatives.eval(time, state, &mut result);
+    result
+}
+
+fn diffusion(task: &SdeTask<f64, f64>, time: f64, state: &[f64]) -> Box<[f64]> {
+    task.diffusion.iter().map(|g| g.eval(time, state)).collect()
+}
+
+impl Scheme for EulerMaruyama {
+    fn advance(
+        &self,
+        task: &SdeTask<f64, f64>,
+        time: f64,
+        state: &[f64],
+        step: f64,
+        dw: &[f64],
+    ) -> Box<[f64]> {
+        let (f, g) = (drift(task, time, state), diffusion(task, time, state));
+        (0..state.len())
+            .map(|i| state[i] + f[i] * step + g[i] * dw[i])
+            .collect()
+    }
+}
+
+impl Scheme for Milstein {
+    fn advance(
+        &self,
+        task: &SdeTask<f64, f64>,
+        time: f64,
+        state: &[f64],
+        step: f64,
+        dw: &[f64],
+    ) -> Box<[f64]> {
+        let (f, g) = (drift(task, time, state), diffusion(task, time, state));
+        let mut shifted = state.to_vec();
+        (0..state.len())
+            .map(|i| {
+                let delta = f64::EPSILON.sqrt() * state[i].abs().max(1.0);
+                shifted[i] = state[i] + delta;
+                let forward = task.diffusion[i].eval(time, &shifted);
+                shifted[i] = state[i] - delta;
+                let backward = task.diffusion[i].eval(time, &shifted);
+                shifted[i] = state[i];
+
+                let dg = (forward - backward) / (2.0 * delta);
+                state[i] + f[i] * step + g[i] * dw[i] + 0.5 * g[i] * dg * (dw[i] * dw[i] - step)
+            })
+            .collect()
+    }
+}
+
+impl Scheme for StochasticRungeKutta {
+    fn advance(
+        &self,
+        task: &SdeTask<f64, f64>,
+        time: f64,
+        state: &[f64],
+        step: f64,
+        dw: &[f64],
+    ) -> Box<[f64]> {
+        let (f, g) = (drift(task, time, state), diffusion(task, time, state));
+        let sqrt_step = step.sqrt();
+        let support = (0..state.len())
+            .map(|i| state[i] + f[i] * step + g[i] * sqrt_step)
+            .collect::<Box<[_]>>();
+        let g_support = diffusion(task, time, &support);
+
+        (0..state.len())
+            .map(|i| {
+                state[i]
+                    + f[i] * step
+                    + g[i] * dw[i]
+                    + (g_support[i] - g[i]) * (dw[i] * dw[i] - step) / (2.0 * sqrt_step)
+            })
+            .collect()
+    }
+}
+
+impl<S: Scheme> StochasticSolver<S> {
+    pub fn new(scheme: S, step: f64) -> Frozen<Self> {
+        Frozen(Self {
+            scheme,
+            step,
+            current_time: 0.0,
+            last_solution: Box::new([]),
+        })
+    }
+
+    pub fn solve_task<'a>(
+        this: Frozen<&'a mut Self>,
+        task: &'a SdeTask<f64, f64>,
+        mut wiener: Wiener,
+    ) -> impl Iterator<Item = (f64, Box<[f64]>)> + 'a {
+        let this = this.init(|it| {
+            it.current_time = task.initial_time;
+            it.last_solution = task.initial_conditions.clone();
+        });
+
+        once((this.current_time, this.last_solution.clone())).chain(repeat_with(move || {
+            let (t, xs) = this.next_solution(task, &mut wiener);
+            (t, Box::from(xs))
+        }))
+    }
+
+    pub fn next_solution(
+        &mut self,
+        task: &SdeTask<f64, f64>,
+        wiener: &mut Wiener,
+    ) -> (f64, &[f64]) {
+        let dw = (0..task.size)
+            .map(|_| wiener.increment(self.step))
+            .collect::<Box<[_]>>();
+        self.last_solution =
+            self.scheme
+                .advance(task, self.current_time, &self.last_solution, self.step, &dw);
+        self.current_time += self.step;
+        (self.current_time, &self.last_solution)
+    }
+}
+
+impl Ensemble {
+    /// Computes `paths` sample paths, k-th of them driven by `Wiener::with_stream(seed, k)`
+    pub fn compute<S: Scheme>(
+        solver: &mut Frozen<StochasticSolver<S>>,
+        task: &SdeTask<f64, f64>,
+        stop: StopCondition<f64>,
+        paths: usize,
+        seed: u64,
+    ) -> Result<Self, Error> {
+        if paths == 0 {
+            bail!("Ensemble should contain at least one path");
+        }
+        let paths = (0..paths as u64)
+            .map(|stream| {
+                let wiener = Wiener::with_stream(seed, stream);
+                let data = StochasticSolver::solve_task(solver.as_mut(), task, wiener)
+                    .take_while(|(t, _)| match &stop {
+                        StopCondition::Timed { maximum } => t <= maximum,
+                    })
+                    .collect::<Vec<_>>();
+                let time = data.iter().map(|(t, _)| *t).collect();
+                let outputs = (0..task.size)
+                    .flat_map(|i| data.iter().map(move |(_, xs)| xs[i]))
+                    .collect();
+                Solution::from_raw(time, outputs)
+            })
+            .collect();
+
+        Ok(Self { paths })
+    }
+
+    pub fn paths(&self) -> &[Solution<f64, f64>] {
+        &self.paths
+    }
+
+    /// Applies `statistic` to the values of every component across all paths at every time point
+    fn reduce(&self, statistic: impl Fn(&mut [f64]) -> f64) -> Solution<f64, f64> {
+        let first = &self.paths[0];
+        let mut sample = vec![0.0; self.paths.len()];
+        let outputs = (0..first.components())
+            .flat_map(|i| (0..first.time().len()).map(move |k| (i, k)))
+            .map(|(i, k)| {
+                for (value, path) in sample.iter_mut().zip(&self.paths) {
+                    *value = path[i][k];
+                }
+                statistic(&mut sample)
+            })
+            .collect();
+
+        Solution::from_raw(Box::from(first.time()), outputs)
+    }
+
+    pub fn mean(&self) -> Solution<f64, f64> {
+        self.reduce(|sample| sample.iter().sum::<f64>() / sample.len() as f64)
+    }
+
+    /// Unbiased sample variance
+    pub fn variance(&self) -> Solution<f64, f64> {
+        self.reduce(|sample| {
+            let mean = sample.iter().sum::<f64>() / sample.len() as f64;
+            let len = sample.len().saturating_sub(1).max(1) as f64;
+            sample.iter().map(|it| (it - mean).powi(2)).sum::<f64>() / len
+        })
+    }
+
+    /// Linearly interpolated `q`-th quantile, `q` is within `[0, 1]`
+    pub fn quantile(&self, q: f64) -> Solution<f64, f64> {
+        self.reduce(|sample| {
+            sample.sort_by(f64::total_cmp);
+            let position = q.clamp(0.0, 1.0) * (sample.len() - 1) as f64;
+            let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+            sample[lower] + (sample[upper] - sample[lower]) * (position - lower as f64)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::f;
+
+    /// Geometric Brownian motion `dX = mu X dt + sigma X dW`, `X(0) = 1`
+    fn geometric(mu: f64, sigma: f64) -> SdeTask<f64, f64> {
+        SdeTask::new(
+            CauchyTask::new([f(move |_, &[x]| mu * x)], 0.0, [1.0]),
+            [f(move |_, &[x]| sigma * x)],
+        )
+    }
+
+    #[test]
+    fn rejects_empty_ensemble() {
+        let mut solver = StochasticSolver::new(EulerMaruyama, 0.01);
+        let stop = StopCondition::Timed { maximum: 1.0 };
+        assert!(Ensemble::compute(&mut solver, &geometric(0.5, 0.2), stop, 0, 0).is_err());
+    }
+
+    #[test]
+    fn euler_maruyama_mean_of_geometric_brownian_motion() {
+        let (mu, sigma) = (0.5, 0.2);
+        let mut solver = StochasticSolver::new(EulerMaruyama, 0.01);
+        let stop = StopCondition::Timed { maximum: 1.0 };
+        let ensemble =
+            Ensemble::compute(&mut solver, &geometric(mu, sigma), stop, 4000, 7).unwrap();
+
+        // E[X(t)] = exp(mu t), Var[X(t)] = exp(2 mu t) (exp(sigma^2 t) - 1)
+        let (mean, variance) = (ensemble.mean(), ensemble.variance());
+        for (k, &t) in mean.time().iter().enumerate().step_by(25) {
+            let expected = (mu * t).exp();
+            assert!((mean[0][k] - expected).abs() < 0.02, "mean at {t}");
+            let expected = (2.0 * mu * t).exp() * ((sigma * sigma * t).exp() - 1.0);
+            assert!(
+                (variance[0][k] - expected).abs() < 0.1 * expected + 1e-12,
+                "variance at {t}"
+            );
+        }
+        assert_eq!(ensemble.paths().len(), 4000);
+    }
+
+    const MU: f64 = 0.5;
+    const SIGMA: f64 = 0.8;
+    /// Amounts of steps over `[0, 1]`, each of which halves the previous step
+    const STEPS: [usize; 5] = [16, 32, 64, 128, 256];
+
+    /// Final value of `scheme` over `[0, 1]`, driven by `dw` summed in groups of `group`
+    fn final_value(
+        scheme: &impl Scheme,
+        task: &SdeTask<f64, f64>,
+        dw: &[f64],
+        group: usize,
+    ) -> f64 {
+        let step = group as f64 / dw.len() as f64;
+        let mut state = task.initial_conditions.clone();
+        for (k, increments) in dw.chunks(group).enumerate() {
+            let increment = increments.iter().sum::<f64>();
+            state = scheme.advance(task, k as f64 * step, &state, step, &[increment]);
+        }
+        state[0]
+    }
+
+    /// Mean absolute error at `t = 1` against the exact solution
+    /// `exp((mu - sigma^2 / 2) t + sigma W(t))` on the same Wiener paths for each of [`STEPS`]
+    fn strong_errors(scheme: &impl Scheme) -> Vec<f64> {
+        let task = geometric(MU, SIGMA);
+        let finest = STEPS[STEPS.len() - 1];
+        let paths = 200;
+        let mut errors = vec![0.0; STEPS.len()];
+        for stream in 0..paths {
+            let mut wiener = Wiener::with_stream(3, stream);
+            let dw = (0..finest)
+                .map(|_| wiener.increment(1.0 / finest as f64))
+                .collect::<Vec<_>>();
+            let exact = (MU - SIGMA * SIGMA / 2.0 + SIGMA * dw.iter().sum::<f64>()).exp();
+            for (error, steps) in errors.iter_mut().zip(STEPS) {
+                *error += (final_value(scheme, &task, &dw, finest / steps) - exact).abs();
+            }
+        }
+        errors.iter().map(|it| it / paths as f64).collect()
+    }
+
+    /// Slope of `log2(error)` against `log2(step)` between the coarsest and the finest step
+    fn order(errors: &[f64]) -> f64 {
+        (errors[0] / errors[errors.len() - 1]).log2() / (STEPS.len() - 1) as f64
+    }
+
+    #[test]
+    fn strong_orders_of_schemes() {
+        let euler = order(&strong_errors(&EulerMaruyama));
+        assert!(
+            (euler - 0.5).abs() < 0.2,
+            "Euler–Maruyama has order {euler}"
+        );
+        let milstein = order(&strong_errors(&Milstein));
+        assert!(
+            (milstein - 1.0).abs() < 0.2,
+            "Milstein has order {milstein}"
+        );
+        let runge_kutta = order(&strong_errors(&StochasticRungeKutta));
+        assert!(
+            (runge_kutta - 1.0).abs() < 0.2,
+            "Stochastic Runge–Kutta has order {runge_kutta}"
+        );
+    }
+
+    #[test]
+    fn runge_kutta_agrees_with_milstein() {
+        let task = geometric(MU, SIGMA);
+        let finest = STEPS[STEPS.len() - 1];
+        let mut wiener = Wiener::new(5);
+        let dw = (0..finest)
+            .map(|_| wiener.increment(1.0 / finest as f64))
+            .collect::<Vec<_>>();
+        let difference = |group: usize| {
+            let milstein = final_value(&Milstein, &task, &dw, group);
+            (final_value(&StochasticRungeKutta, &task, &dw, group) - milstein).abs() / milstein
+        };
+        // Both schemes differ by terms of order `step`
+        assert!(difference(1) < 1e-2, "{}", difference(1));
+        assert!(difference(1) < difference(finest / STEPS[0]));
+    }
+
+    #[test]
+    fn ensembles_are_reproducible() {
+        let task = geometric(MU, SIGMA);
+        let stop = StopCondition::Timed { maximum: 1.0 };
+        let mut solver = StochasticSolver::new(StochasticRungeKutta, 0.01);
+        let mut compute = |seed| Ensemble::compute(&mut solver, &task, stop, 8, seed).unwrap();
+        let (first, second, other) = (compute(11), compute(11), compute(12));
+        let last = |ensemble: &Ensemble| {
+            ensemble
+                .paths()
+                .iter()
+                .map(|it| *it[0].last().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(last(&first), last(&second));
+        assert_ne!(last(&first), last(&other));
+        // Paths of an ensemble are driven by different streams
+        assert_ne!(last(&first)[0], last(&first)[1]);
+    }
+}
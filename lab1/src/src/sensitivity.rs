@@ -0,0 +1,267 @@
+use crate::dual::Dual;
+use crate::model::Model;
+use crate::solution::{Solution, StopCondition};
+use crate::solver::Solver;
+use crate::task::{CauchyTask, Function};
+use crate::Frozen;
+use anyhow::Error;
+use std::array;
+use std::rc::Rc;
+
+/// Cauchy task augmented with its forward sensitivity equations
+/// ```math
+/// x' = f(t, x, p)
+/// s_j' = df/dx * s_j + df/dp_j
+/// x(t_0) = x_0(p)
+/// s_j(t_0) = dx_0/dp_j
+/// ```
+///
+/// where s_j = dx/dp_j for every parameter p_j, j = 1..P.
+/// State of the augmented system is `[x, s_1, s_2, ..., s_P]`, so it can be solved with any
+/// [`Solver`] for `f64`.
+pub struct SensitivityTask<const P: usize> {
+    task: CauchyTask<f64, f64>,
+    size: usize,
+    parameters: [f64; P],
+}
+
+/// Solution of a [`SensitivityTask`], split into the state and a sensitivity per parameter
+pub struct Sensitivity<const P: usize> {
+    state: Solution<f64, f64>,
+    sensitivities: [Solution<f64, f64>; P],
+    parameters: [f64; P],
+}
+
+impl<const P: usize> SensitivityTask<P> {
+    /// Derives sensitivity equations of `model` around `parameters` with forward-mode
+    /// automatic differentiation.
+    /// Parameters which the initial conditions depend on get non-zero initial sensitivities.
+    pub fn automatic<M: Model<f64, P>>(model: &M, parameters: [f64; P]) -> Self {
+        let CauchyTask {
+            size,
+            initial_conditions,
+            initial_time,
+            derivatives,
+        } = model.build(parameters);
+        let base = derivatives.into_components(size);
+        let augmented_size = size * (P + 1);
+
+        // j-th task carries unit derivative with respect to j-th parameter
+        let duals = array::from_fn::<_, P, _>(|j| {
+            model.build(array::from_fn(|k| {
+                Dual::new(parameters[k], if k == j { 1.0 } else { 0.0 })
+            }))
+        });
+
+        let mut initial = initial_conditions.into_vec();
+        for dual in &duals {
+            assert_eq!(
+                dual.size, size,
+                "Model should not change size with parameters"
+            );
+            initial.extend(dual.initial_conditions.iter().map(|x| x.derivative()));
+        }
+
+        let mut derivatives = Vec::with_capacity(augmented_size);
+        for f in base.into_vec() {
+            derivatives.push(Function::from_slice(augmented_size, move |t, xs| {
+                f.eval(t, &xs[..size])
+            }));
+        }
+        for (j, dual) in duals.into_iter().enumerate() {
+            let dual = Rc::<[Function<f64, Dual<f64>>]>::from(dual.derivatives.into_components(size));
+            for i in 0..size {
+                let dual = dual.clone();
+                derivatives.push(Function::from_slice(augmented_size, move |t, xs| {
+                    // Evaluating f_i at (x + s_j e, p + e_j e) gives df_i/dx * s_j + df_i/dp_j
+                    let inputs = (0..size)
+                        .map(|k| Dual::new(xs[k], xs[size * (j + 1) + k]))
+                        .collect::<Vec<_>>();
+                    dual[i].eval(t, &inputs).derivative()
+                }));
+            }
+        }
+
+        Self {
+            task: CauchyTask::from_parts(derivatives, initial_time, initial),
+            size,
+            parameters,
+        }
+    }
+
+    /// Builds sensitivity equations of `task` from user-provided jacobians.
+    /// `jacobian` returns `(df/dx, df/dp)` evaluated at given time and state,
+    /// `initial_sensitivities[i][j]` is `dx_0i/dp_j`.
+    pub fn with_jacobian<const S: usize, J>(
+        task: CauchyTask<f64, f64>,
+        parameters: [f64; P],
+        initial_sensitivities: [[f64; P]; S],
+        jacobian: J,
+    ) -> Self
+    where
+        J: Fn(f64, &[f64; S]) -> ([[f64; S]; S], [[f64; P]; S]) + 'static,
+    {
+        let CauchyTask {
+            size,
+            initial_conditions,
+            initial_time,
+            derivatives,
+        } = task;
+        let base = derivatives.into_components(size);
+        assert_eq!(size, S, "Jacobian should have the same size as the task");
+        let augmented_size = size * (P + 1);
+        let jacobian = Rc::new(jacobian);
+
+        let mut initial = initial_conditions.into_vec();
+        for j in 0..P {
+            initial.extend(initial_sensitivities.iter().map(|row| row[j]));
+        }
+
+        let mut derivatives = Vec::with_capacity(augmented_size);
+        for f in base.into_vec() {
+            derivatives.push(Function::from_slice(augmented_size, move |t, xs| {
+                f.eval(t, &xs[..S])
+            }));
+        }
+        for j in 0..P {
+            for i in 0..S {
+                let jacobian = jacobian.clone();
+                derivatives.push(Function::from_slice(augmented_size, move |t, xs| {
+                    let (state, params) = jacobian(t, xs.first_chunk::<S>().unwrap());
+                    let s = &xs[S * (j + 1)..S * (j + 2)];
+                    state[i].iter().zip(s).map(|(a, b)| a * b).sum::<f64>() + params[i][j]
+                }));
+            }
+        }
+
+        Self {
+            task: CauchyTask::from_parts(derivatives, initial_time, initial),
+            size,
+            parameters,
+        }
+    }
+
+    /// Augmented task, which state is `[x, s_1, s_2, ..., s_P]`
+    pub fn task(&self) -> &CauchyTask<f64, f64> {
+        &self.task
+    }
+}
+
+impl<const P: usize> Sensitivity<P> {
+    pub fn compute<S: Solver<f64, f64>>(
+        solver: Frozen<&mut S>,
+        task: &SensitivityTask<P>,
+        stop: StopCondition<f64>,
+    ) -> Result<Self, Error> {
+        let solution = Solution::compute(solver, &task.task, stop)?;
+        let size = task.size;
+
+        Ok(Self {
+            state: solution.select(0..size),
+            sensitivities: array::from_fn(|j| solution.select(size * (j + 1)..size * (j + 2))),
+            parameters: task.parameters,
+        })
+    }
+
+    pub fn state(&self) -> &Solution<f64, f64> {
+        &self.state
+    }
+
+    pub fn parameters(&self) -> &[f64; P] {
+        &self.parameters
+    }
+
+    /// Solution of `dx/dp_j`
+    pub fn parameter(&self, j: usize) -> &Solution<f64, f64> {
+        &self.sensitivities[j]
+    }
+
+    /// Solution of `p_j / x_i * dx_i/dp_j`, which is relative change of x_i per relative change
+    /// of p_j. Points where x_i vanishes are reported as zero.
+    pub fn normalized(&self, j: usize) -> Solution<f64, f64> {
+        let sensitivity = &self.sensitivities[j];
+        let outputs = (0..sensitivity.components())
+            .flat_map(|i| {
+                let p = self.parameters[j];
+                sensitivity[i]
+                    .iter()
+                    .zip(&self.state[i])
+                    .map(move |(s, x)| {
+                        if x.abs() > f64::EPSILON.sqrt() {
+                            s * p / x
+                        } else {
+                            0.0
+                        }
+                    })
+            })
+            .collect();
+
+        Solution::from_raw(Box::from(sensitivity.time()), outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Reaction;
+    use crate::solver::EulerSolver;
+
+    const STOP: StopCondition<f64> = StopCondition::Timed { maximum: 2.0 };
+
+    fn last(solution: &Solution<f64, f64>, component: usize) -> f64 {
+        *solution[component].last().unwrap()
+    }
+
+    #[test]
+    fn matches_analytic_solution() {
+        let (k1, k2) = (0.577, 0.422);
+        let task = SensitivityTask::automatic(&Reaction, [k1, k2]);
+        let sensitivity = Sensitivity::compute(EulerSolver::new(1e-5).as_mut(), &task, STOP).unwrap();
+        let t = *sensitivity.state().time().last().unwrap();
+
+        // x1 = exp(-k1 t), x2 = k1 / (k2 - k1) * (exp(-k1 t) - exp(-k2 t))
+        let (e1, e2) = ((-k1 * t).exp(), (-k2 * t).exp());
+        let dx1_dk1 = -t * e1;
+        let dx2_dk1 = k2 / (k2 - k1).powi(2) * (e1 - e2) - k1 / (k2 - k1) * t * e1;
+        let dx2_dk2 = -k1 / (k2 - k1).powi(2) * (e1 - e2) + k1 / (k2 - k1) * t * e2;
+        assert!((last(sensitivity.parameter(0), 0) - dx1_dk1).abs() < 1e-4);
+        assert!((last(sensitivity.parameter(0), 1) - dx2_dk1).abs() < 1e-4);
+        assert!((last(sensitivity.parameter(1), 0)).abs() < 1e-12);
+        assert!((last(sensitivity.parameter(1), 1) - dx2_dk2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn matches_finite_differences() {
+        let parameters = [0.577, 0.422];
+        let solve = |parameters: [f64; 2]| {
+            let task = Reaction.build(parameters);
+            Solution::compute(EulerSolver::new(1e-3).as_mut(), &task, STOP).unwrap()
+        };
+        let task = SensitivityTask::automatic(&Reaction, parameters);
+        let sensitivity = Sensitivity::compute(EulerSolver::new(1e-3).as_mut(), &task, STOP).unwrap();
+
+        let h = 1e-6;
+        for j in 0..2 {
+            let (mut lower, mut upper) = (parameters, parameters);
+            lower[j] -= h;
+            upper[j] += h;
+            let (lower, upper) = (solve(lower), solve(upper));
+            for i in 0..3 {
+                let difference = (last(&upper, i) - last(&lower, i)) / (2.0 * h);
+                let automatic = last(sensitivity.parameter(j), i);
+                assert!((difference - automatic).abs() < 1e-6, "dx{}/dk{}: {difference} != {automatic}", i + 1, j + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn normalized_is_relative() {
+        let task = SensitivityTask::automatic(&Reaction, [0.577, 0.422]);
+        let sensitivity = Sensitivity::compute(EulerSolver::new(1e-3).as_mut(), &task, STOP).unwrap();
+        let normalized = sensitivity.normalized(0);
+        let expected = last(sensitivity.parameter(0), 0) * 0.577 / last(sensitivity.state(), 0);
+        assert!((last(&normalized, 0) - expected).abs() < 1e-12);
+        // x2 and x3 vanish at the start
+        assert_eq!(normalized[1][0], 0.0);
+    }
+}
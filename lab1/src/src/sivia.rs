@@ -0,0 +1,286 @@
+//! Approximate set inversion of model parameters against measurements with bounded errors.
+//!
+//! Boxes are classified with interval solutions of an ordinary solver, which enclose the
+//! uncertainty of the parameters but not the discretization error, so the paving is an
+//! approximation rather than a guaranteed one, see [`sivia`].
+
+use crate::fitting::Observations;
+use crate::interval::Interval;
+use crate::model::Model;
+use crate::solution::{Solution, StopCondition};
+use crate::solver::Solver;
+use crate::Frozen;
+use anyhow::{bail, Error};
+
+/// Tolerance of times of measurements relative to them, see [`hull_around`]
+const RELATIVE_SLACK: f64 = 1e-9;
+
+/// Measured value of the component with index [`Self::component`] together with its error bounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraint {
+    pub component: usize,
+    pub time: f64,
+    pub value: Interval<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Classification {
+    /// Interval solution of the box satisfies all constraints
+    Consistent,
+    /// Interval solution of the box misses some constraint
+    Inconsistent,
+    /// Box is smaller than [`Options::epsilon`], but still cannot be classified
+    Undetermined,
+}
+
+pub struct Options {
+    /// Boxes narrower than that are not bisected anymore
+    pub epsilon: f64,
+    /// Upper bound of classified boxes, after which remaining ones are left undetermined
+    pub max_boxes: usize,
+}
+
+/// Set of non-overlapping boxes which cover the initial parameter domain
+pub struct Paving<const P: usize> {
+    pub boxes: Vec<([Interval<f64>; P], Classification)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            epsilon: 1e-3,
+            max_boxes: 100_000,
+        }
+    }
+}
+
+impl Constraint {
+    /// Makes constraints from every measurement, which error is bounded by `error`
+    pub fn from_observations(observations: &Observations, error: f64) -> Vec<Self> {
+        observations
+            .measurements()
+            .iter()
+            .map(|it| Self {
+                component: it.component,
+                time: it.time,
+                value: Interval::new(it.value - error, it.value + error),
+            })
+            .collect()
+    }
+}
+
+impl<const P: usize> Paving<P> {
+    pub fn classified(
+        &self,
+        classification: Classification,
+    ) -> impl Iterator<Item = &[Interval<f64>; P]> {
+        self.boxes
+            .iter()
+            .filter(move |(_, it)| *it == classification)
+            .map(|(it, _)| it)
+    }
+}
+
+/// Hull of the component at the computed points surrounding `time`, [`None`] if `time` is
+/// outside of the computed time range. It is not an enclosure of the exact solution, which may
+/// leave it between the points and by the error of the method.
+fn hull_around(
+    solution: &Solution<f64, Interval<f64>>,
+    component: usize,
+    time: f64,
+) -> Option<Interval<f64>> {
+    let ts = solution.time();
+    let values = &solution[component];
+    // Points are reached with the rounding error accumulated over steps, so a time that close
+    // to an end is taken at that end
+    let slack = time.abs() * RELATIVE_SLACK;
+    if ts.is_empty() || time < ts[0] - slack || time > ts[ts.len() - 1] + slack {
+        return None;
+    }
+    match ts.partition_point(|&t| t < time) {
+        0 => Some(values[0]),
+        idx if idx == ts.len() => Some(values[idx - 1]),
+        idx if ts[idx] == time => Some(values[idx]),
+        idx => Some(values[idx - 1].hull(values[idx])),
+    }
+}
+
+fn classify<M, S, const P: usize>(
+    model: &M,
+    constraints: &[Constraint],
+    parameters: [Interval<f64>; P],
+    solver: &mut Frozen<S>,
+) -> Result<Classification, Error>
+where
+    M: Model<f64, P>,
+    S: Solver<f64, Interval<f64>>,
+{
+    let maximum = constraints
+        .iter()
+        .map(|it| it.time)
+        .fold(f64::NEG_INFINITY, f64::max);
+    // Slack keeps the point at `maximum`, which is reached with a rounding error
+    let slack = maximum.abs() * RELATIVE_SLACK;
+    let solution = Solution::compute(
+        solver.as_mut(),
+        &model.build(parameters),
+        StopCondition::Timed {
+            maximum: maximum + slack,
+        },
+    )?;
+
+    let mut result = Classification::Consistent;
+    for constraint in constraints {
+        let Some(hull) = hull_around(&solution, constraint.component, constraint.time) else {
+            bail!(
+                "Measurement at t = {} is outside of the solution, which ends at t = {}. \
+                 Measurements should be taken at multiples of the solver step",
+                constraint.time,
+                solution.time().last().copied().unwrap_or(f64::NAN)
+            );
+        };
+        if !hull.start().is_finite() || !hull.end().is_finite() {
+            result = Classification::Undetermined;
+        } else if !hull.intersects(constraint.value) {
+            return Ok(Classification::Inconsistent);
+        } else if !hull.is_subset(constraint.value) {
+            result = Classification::Undetermined;
+        }
+    }
+    Ok(result)
+}
+
+/// Set inversion via interval analysis: bisects `domain` until the solution of every box either
+/// satisfies all `constraints`, misses some of them or the box becomes narrower than
+/// [`Options::epsilon`].
+///
+/// Each box is classified by solving the model with interval parameters, so the paving is only as
+/// reliable as the enclosures produced by `solver`. Those enclose the rounding of the parameters,
+/// but not the discretization error of the method, so the paving is not guaranteed: boxes near
+/// the boundary may be misclassified by about the local error of a step. Values between computed
+/// points are taken as the hull of the surrounding points.
+/// Fails as soon as `solver` fails for any box or a constraint is beyond the computed solution.
+pub fn sivia<M, S, const P: usize>(
+    model: &M,
+    constraints: &[Constraint],
+    domain: [Interval<f64>; P],
+    solver: &mut Frozen<S>,
+    options: &Options,
+) -> Result<Paving<P>, Error>
+where
+    M: Model<f64, P>,
+    S: Solver<f64, Interval<f64>>,
+{
+    let mut boxes = vec![];
+    let mut queue = vec![domain];
+
+    while let Some(parameters) = queue.pop() {
+        let classification = if boxes.len() + queue.len() >= options.max_boxes {
+            Classification::Undetermined
+        } else {
+            classify(model, constraints, parameters, solver)?
+        };
+
+        let widest =
+            (0..P).max_by(|&i, &j| parameters[i].width().total_cmp(&parameters[j].width()));
+        match (classification, widest) {
+            (Classification::Undetermined, Some(widest))
+                if parameters[widest].width() > options.epsilon
+                    && boxes.len() + queue.len() < options.max_boxes =>
+            {
+                let (left, right) = parameters[widest].bisect();
+                let mut other = parameters;
+                other[widest] = right;
+                queue.push(other);
+                other[widest] = left;
+                queue.push(other);
+            }
+            _ => boxes.push((parameters, classification)),
+        }
+    }
+
+    Ok(Paving { boxes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Number;
+    use crate::solver::EulerSolver;
+    use crate::task::{f, CauchyTask};
+
+    /// `x' = -k x`, `x(0) = 1`, which solution is `exp(-k t)`
+    struct Decay;
+
+    impl Model<f64, 1> for Decay {
+        fn build<N: Number>(&self, [k]: [N; 1]) -> CauchyTask<f64, N> {
+            CauchyTask::new([f(move |_, &[x]| -k * x)], 0.0, [N::from(1.0)])
+        }
+    }
+
+    const RATE: f64 = 0.7;
+
+    /// Exact values of the solution with [`RATE`] at multiples of the solver step
+    fn constraints(error: f64) -> Vec<Constraint> {
+        [0.5, 1.0, 1.5]
+            .map(|time| Constraint {
+                component: 0,
+                time,
+                value: Interval::new((-RATE * time).exp() - error, (-RATE * time).exp() + error),
+            })
+            .to_vec()
+    }
+
+    fn domain_width(paving: &Paving<1>) -> f64 {
+        paving.boxes.iter().map(|([k], _)| k.width()).sum()
+    }
+
+    #[test]
+    fn keeps_true_parameter() {
+        let paving = sivia(
+            &Decay,
+            &constraints(0.02),
+            [Interval::new(0.0, 2.0)],
+            &mut EulerSolver::new(1e-3),
+            &Options {
+                epsilon: 1e-3,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let ([k], classification) = paving
+            .boxes
+            .iter()
+            .find(|([k], _)| k.start() <= RATE && RATE <= k.end())
+            .unwrap();
+        assert_ne!(*classification, Classification::Inconsistent, "{k}");
+        assert!(paving.classified(Classification::Consistent).count() > 0);
+        // Far away rates cannot match the measurements
+        for [k] in paving.classified(Classification::Consistent) {
+            assert!((k.midpoint() - RATE).abs() < 0.1, "{k}");
+        }
+        assert!((domain_width(&paving) - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn leaves_boxes_past_the_limit_undetermined() {
+        let options = Options {
+            epsilon: 1e-9,
+            max_boxes: 8,
+        };
+        let paving = sivia(
+            &Decay,
+            &constraints(0.02),
+            [Interval::new(0.0, 2.0)],
+            &mut EulerSolver::new(1e-3),
+            &options,
+        )
+        .unwrap();
+        assert!(paving.boxes.len() <= options.max_boxes + 1);
+        // Remaining boxes are still wide, they are left because of the limit
+        assert!(paving
+            .classified(Classification::Undetermined)
+            .any(|[k]| k.width() > options.epsilon));
+        assert!((domain_width(&paving) - 2.0).abs() < 1e-12);
+    }
+}
@@ -1,48 +1,84 @@
 use crate::solver::Solver;
 use crate::task::CauchyTask;
 use crate::Frozen;
-use std::ops::Index;
+use anyhow::Error;
+use std::ops::{Index, Range};
+
+/// Maximum amount of steps done by a solver in a single [`Solver::next_batch`] call
+pub const BATCH_STEPS: usize = 1024;
 
 pub struct Solution<T, N> {
     time: Box<[T]>,
     outputs: Box<[N]>,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum StopCondition<T> {
     // Absolute maximum time to compute the solution
     Timed { maximum: T },
 }
 
-impl<T: PartialOrd, N> Solution<T, N> {
+impl<T, N> Solution<T, N> {
+    pub(crate) fn from_raw(time: Box<[T]>, outputs: Box<[N]>) -> Self {
+        assert_eq!(
+            outputs.len() % time.len().max(1),
+            0,
+            "Every output should have a value for each time point"
+        );
+        Self { time, outputs }
+    }
+
     pub fn time(&self) -> &[T] {
         &self.time
     }
 
+    /// Amount of components (`n` in [`CauchyTask`]) in this solution
+    pub fn components(&self) -> usize {
+        self.outputs.len().checked_div(self.time.len()).unwrap_or(0)
+    }
+
+    /// Solution that consists only of components from `range`
+    pub fn select(&self, range: Range<usize>) -> Self
+    where
+        T: Clone,
+        N: Clone,
+    {
+        let stripe_size = self.time.len();
+        Self {
+            time: self.time.clone(),
+            outputs: Box::from(&self.outputs[stripe_size * range.start..stripe_size * range.end]),
+        }
+    }
+}
+
+impl<T: PartialOrd + Clone, N: Clone> Solution<T, N> {
+    /// Computes the solution in batches of [`BATCH_STEPS`] steps, so that external solvers are
+    /// called once per batch instead of once per step
     pub fn compute<S: Solver<T, N>>(
         solver: Frozen<&mut S>,
         task: &CauchyTask<T, N>,
         stop: StopCondition<T>,
-    ) -> Self {
-        let data = S::solve_task(solver, task)
-            .take_while(|(t, _)| match &stop {
-                StopCondition::Timed { maximum } => t <= maximum,
-            })
-            .fold(
-                (vec![], Vec::<Vec<N>>::new()),
-                |(mut ts, mut xs), (t, x)| {
-                    ts.push(t);
-                    for (idx, item) in Box::into_iter(x).enumerate() {
-                        xs.resize_with((idx + 1).clamp(xs.len(), usize::MAX), || vec![]);
-                        xs[idx].push(item);
-                    }
-
-                    (ts, xs)
-                },
-            );
-        Self {
-            time: data.0.into_boxed_slice(),
-            outputs: Box::from_iter(data.1.into_iter().flatten()),
+    ) -> Result<Self, Error> {
+        let (mut time, mut states) = (vec![], vec![]);
+        match &stop {
+            StopCondition::Timed { maximum } if task.initial_time <= *maximum => {
+                let solver = S::prepare(solver, task)?;
+                time.push(task.initial_time.clone());
+                states.extend_from_slice(&task.initial_conditions);
+                while !solver.next_batch(task, BATCH_STEPS, maximum, &mut time, &mut states)? {}
+            }
+            StopCondition::Timed { .. } => {}
         }
+
+        // Points are stored state after state, but solution is component-major
+        let size = task.size;
+        let outputs = (0..size)
+            .flat_map(|i| states.iter().skip(i).step_by(size).cloned())
+            .collect();
+        Ok(Self {
+            time: time.into_boxed_slice(),
+            outputs,
+        })
     }
 }
 
@@ -54,3 +90,45 @@ impl<T, N> Index<usize> for Solution<T, N> {
         &self.outputs[stripe_size * index..stripe_size * (index + 1)]
     }
 }
+
+impl Solution<f64, f64> {
+    /// Linearly interpolated value of `component` at time `t`.
+    /// Points outside of the computed time range are extrapolated from the nearest segment.
+    /// Returns [`None`] if the solution has no points or no such component.
+    pub fn interpolate(&self, component: usize, t: f64) -> Option<f64> {
+        if component >= self.components() {
+            return None;
+        }
+        let values = &self[component];
+        if self.time.len() < 2 {
+            return values.first().copied();
+        }
+        let idx = self
+            .time
+            .partition_point(|&x| x <= t)
+            .clamp(1, self.time.len() - 1);
+        let (t0, t1) = (self.time[idx - 1], self.time[idx]);
+        let (x0, x1) = (values[idx - 1], values[idx]);
+        Some(x0 + (x1 - x0) * (t - t0) / (t1 - t0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_between_points() {
+        let solution = Solution::from_raw(Box::new([0.0, 1.0, 2.0]), Box::new([0.0, 2.0, 6.0]));
+        assert_eq!(solution.interpolate(0, 0.5), Some(1.0));
+        assert_eq!(solution.interpolate(0, 1.5), Some(4.0));
+        assert_eq!(solution.interpolate(0, 3.0), Some(10.0));
+        assert_eq!(solution.interpolate(1, 0.5), None);
+    }
+
+    #[test]
+    fn interpolates_nothing_without_points() {
+        let solution = Solution::<f64, f64>::from_raw(Box::new([]), Box::new([]));
+        assert_eq!(solution.interpolate(0, 0.0), None);
+    }
+}
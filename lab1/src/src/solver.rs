@@ -1,14 +1,68 @@
 use crate::task::CauchyTask;
+use anyhow::{bail, Error};
 use std::iter::{once, repeat_with};
 use crate::Frozen;
 
 pub trait Solver<T, N>: Sized {
+    /// Resets solver to the initial conditions of `task`
+    fn prepare<'a>(this: Frozen<&'a mut Self>, task: &CauchyTask<T, N>) -> Result<&'a mut Self, Error>;
+
+    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> Result<(T, &[N]), Error>;
+
+    /// Does at most `max_steps` steps, appending every point not later than `until` to `times`
+    /// and its state to `states`. Returns `true` once `until` is passed.
+    fn next_batch(
+        &mut self,
+        task: &CauchyTask<T, N>,
+        max_steps: usize,
+        until: &T,
+        times: &mut Vec<T>,
+        states: &mut Vec<N>,
+    ) -> Result<bool, Error>
+    where
+        T: PartialOrd,
+        N: Clone,
+    {
+        for _ in 0..max_steps {
+            let (t, xs) = self.next_solution(task)?;
+            if xs.len() != task.size {
+                bail!("Solver returned {} values, but the task has {} components", xs.len(), task.size);
+            }
+            if &t > until {
+                return Ok(true);
+            }
+            times.push(t);
+            states.extend_from_slice(xs);
+        }
+        Ok(false)
+    }
+
+    /// Points of the solution starting with the initial conditions.
+    /// Once an error is yielded, the solver should not be advanced anymore.
     fn solve_task(
         this: Frozen<&mut Self>,
         task: &CauchyTask<T, N>,
-    ) -> impl Iterator<Item = (T, Box<[N]>)>;
-
-    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> (T, &[N]);
+    ) -> impl Iterator<Item = Result<(T, Box<[N]>), Error>>
+    where
+        T: Clone,
+        N: Clone,
+    {
+        match Self::prepare(this, task) {
+            Ok(this) => Either::Left(
+                once(Ok((task.initial_time.clone(), task.initial_conditions.clone()))).chain(
+                    repeat_with(move || {
+                        let (t, xs) = this.next_solution(task)?;
+                        if xs.len() != task.size {
+                            let size = xs.len();
+                            bail!("Solver returned {size} values, but the task has {} components", task.size);
+                        }
+                        Ok((t, Box::from(xs)))
+                    }),
+                ),
+            ),
+            Err(e) => Either::Right(once(Err(e))),
+        }
+    }
 }
 
 pub struct EulerSolver<T, N> {
@@ -46,37 +100,26 @@ where
     T: Copy + std::ops::Mul<N> + std::ops::Add<Output = T>,
     N: Clone + std::ops::Add<<T as std::ops::Mul<N>>::Output, Output = N>,
 {
-    fn solve_task(
-        this: Frozen<&mut Self>,
-        task: &CauchyTask<T, N>,
-    ) -> impl Iterator<Item = (T, Box<[N]>)> {
-        let this = this.init(|it| {
+    fn prepare<'a>(this: Frozen<&'a mut Self>, task: &CauchyTask<T, N>) -> Result<&'a mut Self, Error> {
+        Ok(this.init(|it| {
             it.current_time = task.initial_time;
             it.last_solution = task.initial_conditions.clone();
-        });
-
-        once((
-            this.current_time,
-            this.last_solution.clone(),
-        ))
-        .chain(repeat_with(move || {
-            let (t, xs) = this.next_solution(task);
-            assert_eq!(task.size, xs.len(), "Task size should be equal to outputs size");
-            (t, Box::from(xs))
         }))
     }
 
-    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> (T, &[N]) {
+    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> Result<(T, &[N]), Error> {
+        let mut slopes = self.last_solution.clone();
+        task.derivatives.eval(self.current_time, &self.last_solution, &mut slopes);
         let xs = self
             .last_solution
             .iter()
-            .zip(&task.derivatives)
-            .map(|(y, f)| y.clone() + self.step * f.eval(self.current_time, &self.last_solution))
+            .zip(slopes)
+            .map(|(y, k)| y.clone() + self.step * k)
             .collect();
 
         self.last_solution = xs;
         self.current_time = self.current_time + self.step;
-        (self.current_time, &self.last_solution)
+        Ok((self.current_time, &self.last_solution))
     }
 }
 
@@ -85,19 +128,38 @@ where
     S1: Solver<T, N>,
     S2: Solver<T, N>
 {
-    fn solve_task(this: Frozen<&mut Self>, task: &CauchyTask<T, N>) -> impl Iterator<Item=(T, Box<[N]>)> {
-        match this.0 {
-            Either::Left(x) => Either::Left(Solver::solve_task(Frozen(x), task)),
-            Either::Right(x) => Either::Right(Solver::solve_task(Frozen(x), task)),
+    fn prepare<'a>(this: Frozen<&'a mut Self>, task: &CauchyTask<T, N>) -> Result<&'a mut Self, Error> {
+        match &mut *this.0 {
+            Either::Left(x) => drop(S1::prepare(Frozen(x), task)?),
+            Either::Right(x) => drop(S2::prepare(Frozen(x), task)?),
         }
+        Ok(this.0)
     }
 
-    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> (T, &[N]) {
+    fn next_solution(&mut self, task: &CauchyTask<T, N>) -> Result<(T, &[N]), Error> {
         match self {
             Either::Left(x) => x.next_solution(task),
             Either::Right(x) => x.next_solution(task)
         }
     }
+
+    fn next_batch(
+        &mut self,
+        task: &CauchyTask<T, N>,
+        max_steps: usize,
+        until: &T,
+        times: &mut Vec<T>,
+        states: &mut Vec<N>,
+    ) -> Result<bool, Error>
+    where
+        T: PartialOrd,
+        N: Clone,
+    {
+        match self {
+            Either::Left(x) => x.next_batch(task, max_steps, until, times, states),
+            Either::Right(x) => x.next_batch(task, max_steps, until, times, states)
+        }
+    }
 }
 
 impl<I1, I2, T> Iterator for Either<I1, I2>
@@ -114,3 +176,41 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solution::{Solution, StopCondition};
+    use crate::task::f;
+
+    /// Solver which loses the last component of every state
+    struct Truncating(EulerSolver<f64, f64>);
+
+    impl Solver<f64, f64> for Truncating {
+        fn prepare<'a>(
+            this: Frozen<&'a mut Self>,
+            task: &CauchyTask<f64, f64>,
+        ) -> Result<&'a mut Self, Error> {
+            EulerSolver::prepare(Frozen(&mut this.0 .0), task)?;
+            Ok(this.0)
+        }
+
+        fn next_solution(&mut self, task: &CauchyTask<f64, f64>) -> Result<(f64, &[f64]), Error> {
+            let (t, xs) = self.0.next_solution(task)?;
+            Ok((t, &xs[..xs.len() - 1]))
+        }
+    }
+
+    #[test]
+    fn rejects_states_of_wrong_size() {
+        let task = CauchyTask::new(
+            [f(|_, &[x, _]: &[f64; 2]| -x), f(|_, &[_, y]: &[f64; 2]| y)],
+            0.0,
+            [1.0, 1.0],
+        );
+        let mut solver = Frozen(Truncating(EulerSolver::new(0.1).0));
+        let stop = StopCondition::Timed { maximum: 1.0 };
+        assert!(Solution::compute(solver.as_mut(), &task, stop).is_err());
+        assert!(Truncating::solve_task(solver.as_mut(), &task).nth(1).unwrap().is_err());
+    }
+}
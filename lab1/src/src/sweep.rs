@@ -0,0 +1,306 @@
+use crate::config::{Combine, Experiment, Override, Quantity, Sweep};
+use crate::experiment::{self, Prepared};
+use crate::{quantity_of, CONFIG};
+use anyhow::{anyhow, bail, Context, Error};
+use project::solution::Solution;
+use std::fs::{self, File};
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::panic::resume_unwind;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Single run of a sweep with the value of every swept key
+struct Run {
+    values: Vec<f64>,
+    experiment: Experiment,
+    dir: PathBuf,
+}
+
+/// Every run of a sweep, which is checked before any of them is started
+struct Plan {
+    experiment: Experiment,
+    keys: Vec<String>,
+    root: PathBuf,
+    runs: Vec<Run>,
+}
+
+/// Combinations of values in the order of keys, the last key changes first in a product
+fn combinations(sweep: &Sweep) -> Result<Vec<Vec<f64>>, Error> {
+    if sweep.values.is_empty() {
+        bail!("At least one key should be swept");
+    }
+    let values = sweep.values.values().map(|it| it.expand()).collect::<Vec<_>>();
+    if let Some((key, _)) = sweep.values.keys().zip(&values).find(|(_, it)| it.is_empty()) {
+        bail!("Key `{key}` has no values");
+    }
+    match sweep.combine {
+        Combine::Product => Ok(values.iter().fold(vec![vec![]], |combinations, values| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |&value| {
+                        let mut result = combination.clone();
+                        result.push(value);
+                        result
+                    })
+                })
+                .collect()
+        })),
+        Combine::Zip => {
+            let count = values.first().map_or(0, Vec::len);
+            if let Some((key, it)) = sweep.values.keys().zip(&values).find(|(_, it)| it.len() != count) {
+                bail!("Zipped keys should have the same amount of values, but `{key}` has {} instead of {count}", it.len());
+            }
+            Ok((0..count).map(|i| values.iter().map(|it| it[i]).collect()).collect())
+        }
+    }
+}
+
+/// Swept keys should refer to something which exists in the experiment, otherwise they would
+/// be silently ignored
+fn check_key(prepared: &Prepared, key: &str) -> Result<(), Error> {
+    let known = match key.split_once('.') {
+        Some(("parameters", name)) => prepared.equations.parameters().iter().any(|it| it == name),
+        Some(("initial", name)) => prepared.equations.variables().iter().any(|it| it == name),
+        None => ["step", "t_max", "initial_time"].contains(&key),
+        _ => false,
+    };
+    if !known {
+        bail!("Key `{key}` should be `step`, `t_max`, `initial_time`, `parameters.<name>` or `initial.<variable>` of the experiment");
+    }
+    Ok(())
+}
+
+/// Copy of `experiment` with swept keys set to `values`
+fn with_values(experiment: &Experiment, keys: &[String], values: &[f64]) -> Result<Experiment, Error> {
+    let mut table = toml::Table::try_from(experiment)?;
+    for (key, &value) in keys.iter().zip(values) {
+        Override::new(key, toml::Value::Float(value))
+            .map_err(|e| anyhow!(e))?
+            .apply(&mut table)?;
+    }
+    Ok(table.try_into()?)
+}
+
+/// Time when `component` first reaches `threshold` from either side, linearly interpolated
+/// between points
+fn event_time(solution: &Solution<f64, f64>, component: usize, threshold: f64) -> Option<f64> {
+    let (time, values) = (solution.time(), &solution[component]);
+    if values.first() == Some(&threshold) {
+        return time.first().copied();
+    }
+    (1..values.len()).find_map(|i| {
+        let (a, b) = (values[i - 1] - threshold, values[i] - threshold);
+        (a * b <= 0.0 && a != b).then(|| time[i - 1] + (time[i] - time[i - 1]) * a / (a - b))
+    })
+}
+
+fn quantity_name(quantity: Quantity) -> &'static str {
+    match quantity {
+        Quantity::Final => "final",
+        Quantity::Peak => "peak",
+        Quantity::PeakTime => "peak_time",
+    }
+}
+
+fn plan(sweep: &Sweep) -> Result<Plan, Error> {
+    let experiment = &experiment::select(sweep.experiment.as_deref())?;
+    let prepared = Prepared::new(experiment)?;
+    // Every run writes into its own directory, which an absolute path would escape
+    let outputs = [&experiment.outputs.csv, &experiment.outputs.plot, &experiment.outputs.manifest];
+    if let Some(path) = outputs.into_iter().flatten().find(|it| it.is_absolute()) {
+        bail!("Outputs of a swept experiment should be relative to the run directory, got {}", path.display());
+    }
+    let keys = sweep.values.keys().cloned().collect::<Vec<_>>();
+    for key in &keys {
+        check_key(&prepared, key)?;
+    }
+    for name in sweep.events.keys() {
+        if !prepared.equations.variables().contains(name) {
+            bail!("Event is given for unknown variable `{name}`");
+        }
+    }
+
+    let root = CONFIG.general.output_dir.join(format!("{}-sweep", experiment.name));
+    let combinations = combinations(sweep)?;
+    let width = combinations.len().to_string().len();
+    let runs = combinations
+        .into_iter()
+        .enumerate()
+        .map(|(i, values)| {
+            let experiment = with_values(experiment, &keys, &values)
+                .with_context(|| format!("Invalid values of run {}", i + 1))?;
+            let problems = experiment.problems(CONFIG.general.t_max);
+            if !problems.is_empty() {
+                let messages = problems.into_iter().map(|it| it.message).collect::<Vec<_>>();
+                bail!("Invalid values of run {}: {}", i + 1, messages.join(", "));
+            }
+            Ok(Run {
+                experiment,
+                dir: root.join(format!("{:0width$}", i + 1)),
+                values,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Plan {
+        experiment: experiment.clone(),
+        keys,
+        root,
+        runs,
+    })
+}
+
+/// Checks the sweep without running anything
+pub fn check(sweep: &Sweep) -> Result<(), Error> {
+    plan(sweep).map(drop)
+}
+
+/// Runs the experiment for every combination of values and writes `summary.csv` with one row
+/// per run, returns an error if any run has failed
+pub fn run_sweep(sweep: &Sweep) -> Result<(), Error> {
+    let plan = plan(sweep)?;
+    println!("Sweeping `{}` over {} runs", plan.experiment.name, plan.runs.len());
+
+    let threads = match sweep.threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    };
+    let chunk_size = plan.runs.len().div_ceil(threads).max(1);
+    let results = thread::scope(|scope| {
+        let workers = plan
+            .runs
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(execute).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|it| it.join().unwrap_or_else(|e| resume_unwind(e)))
+            .collect::<Vec<_>>()
+    });
+
+    let path = plan.root.join("summary.csv");
+    write_summary(sweep, &plan, &results, &path).with_context(|| format!("Could not write {}", path.display()))?;
+    let failed = results.iter().filter(|it| it.is_err()).count();
+    if failed > 0 {
+        bail!("{failed} of {} runs have failed, see {}", plan.runs.len(), path.display());
+    }
+    println!("Summary is written to {}", path.display());
+    Ok(())
+}
+
+fn execute(run: &Run) -> Result<Solution<f64, f64>, Error> {
+    fs::create_dir_all(&run.dir).with_context(|| format!("Could not create {}", run.dir.display()))?;
+    experiment::run(&run.experiment, &run.dir, true, true)
+}
+
+fn write_summary(
+    sweep: &Sweep,
+    plan: &Plan,
+    results: &[Result<Solution<f64, f64>, Error>],
+    path: &Path,
+) -> Result<(), Error> {
+    let prepared = Prepared::new(&plan.experiment)?;
+    let variables = prepared.equations.variables();
+    let mut csv = File::create(path)?;
+    write!(csv, "run, dir")?;
+    for key in &plan.keys {
+        write!(csv, ", {key}")?;
+    }
+    for name in variables {
+        for &it in &sweep.summary {
+            write!(csv, ", {name}_{}", quantity_name(it))?;
+        }
+    }
+    for (name, threshold) in &sweep.events {
+        write!(csv, ", {name}_reaches_{threshold}")?;
+    }
+    writeln!(csv, ", error")?;
+
+    for (i, (run, result)) in plan.runs.iter().zip(results).enumerate() {
+        write!(csv, "{}, {}", i + 1, run.dir.file_name().unwrap_or_default().to_string_lossy())?;
+        for value in &run.values {
+            write!(csv, ", {value}")?;
+        }
+        match result {
+            Ok(solution) => {
+                for component in 0..variables.len() {
+                    for &it in &sweep.summary {
+                        write!(csv, ", {}", quantity_of(solution, component, it))?;
+                    }
+                }
+                for (name, &threshold) in &sweep.events {
+                    let component = variables.iter().position(|it| it == name).unwrap();
+                    match event_time(solution, component, threshold) {
+                        Some(time) => write!(csv, ", {time}")?,
+                        None => write!(csv, ",")?,
+                    }
+                }
+                writeln!(csv, ",")?;
+            }
+            Err(e) => {
+                let empty = variables.len() * sweep.summary.len() + sweep.events.len();
+                write!(csv, "{}", ",".repeat(empty))?;
+                writeln!(csv, ", \"{}\"", format!("{e:#}").replace('"', "\"\""))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use project::solution::StopCondition;
+    use project::solver::EulerSolver;
+    use project::task::{f, CauchyTask};
+
+    fn sweep(text: &str) -> Sweep {
+        toml::from_str(text).unwrap()
+    }
+
+    /// Solution of `x' = slope`, `x(0) = 0` with points every 0.1 up to 1
+    fn line(slope: f64) -> Solution<f64, f64> {
+        let task = CauchyTask::new([f(move |_, &[_]: &[f64; 1]| slope)], 0.0, [0.0]);
+        Solution::compute(EulerSolver::new(0.1).as_mut(), &task, StopCondition::Timed { maximum: 1.0 + 1e-9 }).unwrap()
+    }
+
+    #[test]
+    fn product_changes_last_key_first() {
+        let result = combinations(&sweep("values = { a = [1, 2], b = [10, 20, 30] }")).unwrap();
+        assert_eq!(result, [[1.0, 10.0], [1.0, 20.0], [1.0, 30.0], [2.0, 10.0], [2.0, 20.0], [2.0, 30.0]]);
+        let result = combinations(&sweep("values = { step = { linspace = [0.1, 0.3, 3] } }")).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!((result[1][0] - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn zip_pairs_values() {
+        let result = combinations(&sweep("combine = \"zip\"\nvalues = { a = [1, 2], b = [10, 20] }")).unwrap();
+        assert_eq!(result, [[1.0, 10.0], [2.0, 20.0]]);
+        let error = combinations(&sweep("combine = \"zip\"\nvalues = { a = [1, 2], b = [10, 20, 30] }")).unwrap_err();
+        assert_eq!(error.to_string(), "Zipped keys should have the same amount of values, but `b` has 3 instead of 2");
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        let error = combinations(&sweep("values = {}")).unwrap_err();
+        assert_eq!(error.to_string(), "At least one key should be swept");
+        let error = combinations(&sweep("values = { a = [1], b = [] }")).unwrap_err();
+        assert_eq!(error.to_string(), "Key `b` has no values");
+    }
+
+    #[test]
+    fn event_times() {
+        let rising = line(1.0);
+        assert_eq!(event_time(&rising, 0, 0.0), Some(0.0));
+        let time = event_time(&rising, 0, 0.25).unwrap();
+        assert!((time - 0.25).abs() < 1e-12, "{time}");
+        assert_eq!(event_time(&rising, 0, 2.0), None);
+        assert_eq!(event_time(&rising, 0, -0.5), None);
+
+        let time = event_time(&line(-2.0), 0, -0.5).unwrap();
+        assert!((time - 0.25).abs() < 1e-12, "{time}");
+        assert_eq!(event_time(&line(0.0), 0, 1.0), None);
+    }
+}
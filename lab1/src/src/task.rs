@@ -1,5 +1,11 @@
+use crate::layout::{describe, Layout};
+use std::any::Any;
+use std::cell::Cell;
 use std::ffi::c_void;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::rc::Rc;
 use std::slice;
+use std::thread;
 
 /// Cauchy task given in form
 /// ```math
@@ -20,42 +26,126 @@ pub struct CauchyTask<T, N> {
     pub(crate) size: usize,
     pub(crate) initial_conditions: Box<[N]>,
     pub(crate) initial_time: T,
-    pub(crate) derivatives: Box<[Function<T, N>]>,
+    pub(crate) derivatives: Derivatives<T, N>,
 }
 
+/// Right-hand side of a [`CauchyTask`]
+pub enum Derivatives<T, N> {
+    /// Separate function for every f_i
+    Components(Box<[Function<T, N>]>),
+    /// Single function computing every f_i at once, so that shared subexpressions are evaluated once
+    System(VectorFunction<T, N>),
+}
+
+/// Value returned to the solver instead of the result of a panicked function, so that it can
+/// notice failure without unwinding through foreign frames
+pub trait Sentinel {
+    fn sentinel() -> Self;
+}
+
+/// Callbacks never unwind into the solver. Instead, panic is caught and stored here until
+/// the caller on the Rust side takes it.
 #[repr(C)]
 pub struct Function<T, N> {
     state_pointer: *mut c_void,
-    fn_pointer: extern "C-unwind" fn(*const c_void, T, *const N) -> N,
-    destructor: extern "C-unwind" fn(*mut c_void),
+    fn_pointer: extern "C" fn(*const c_void, T, *const N) -> N,
+    destructor: extern "C" fn(*mut c_void),
+}
+
+/// Function `[T] x [N]^n -> [N]^n`, which writes its result into the output slice
+#[repr(C)]
+pub struct VectorFunction<T, N> {
+    state_pointer: *mut c_void,
+    fn_pointer: extern "C" fn(*const c_void, T, *const N, *mut N),
+    destructor: extern "C" fn(*mut c_void),
+}
+
+thread_local! {
+    static PANIC: Cell<Option<Box<dyn Any + Send>>> = const { Cell::new(None) };
+}
+
+impl Sentinel for f32 {
+    fn sentinel() -> Self {
+        f32::NAN
+    }
+}
+
+impl Sentinel for f64 {
+    fn sentinel() -> Self {
+        f64::NAN
+    }
+}
+
+/// Runs `body`, storing its panic, if there was no other one yet, and returning `fallback` instead
+fn guard<R>(fallback: impl FnOnce() -> R, body: impl FnOnce() -> R) -> R {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        PANIC.with(|it| {
+            let previous = it.take();
+            it.set(previous.or(Some(payload)));
+        });
+        fallback()
+    })
+}
+
+/// Payload of the first panic in any callback on this thread since the last call
+pub(crate) fn take_panic() -> Option<Box<dyn Any + Send>> {
+    PANIC.with(Cell::take)
+}
+
+/// Calls `destructor` of a callback state, resuming only its own panic. Panic of another callback,
+/// which is not taken yet, is left for its caller. Own panic is discarded if the thread is already
+/// unwinding, since resuming it would abort the process.
+fn destroy(destructor: extern "C" fn(*mut c_void), state: *mut c_void) {
+    let pending = take_panic();
+    destructor(state);
+    let own = take_panic();
+    if pending.is_some() {
+        PANIC.with(|it| it.set(pending));
+    }
+    if let Some(payload) = own {
+        if !thread::panicking() {
+            resume_unwind(payload)
+        }
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>")
 }
 
 impl<T, N> Function<T, N> {
     pub fn new<F, const S: usize>(f: F) -> Self
     where
         F: Fn(T, &[N; S]) -> N + 'static,
-        N: Copy,
+        N: Copy + Sentinel,
     {
         #[inline]
-        extern "C-unwind" fn call_closure<F, T, N, const S: usize>(
+        extern "C" fn call_closure<F, T, N, const S: usize>(
             state: *const c_void,
             time: T,
             inputs: *const N,
         ) -> N
         where
             F: Fn(T, &[N; S]) -> N + 'static,
+            N: Sentinel,
         {
-            // SAFETY: state pointer is managed by only this struct, thus never be null
-            let state = unsafe { (state as *const F).as_ref() }.unwrap();
-            assert!(!inputs.is_null(), "Inputs is null");
-            let inputs = unsafe { slice::from_raw_parts(inputs, S) };
-            let inputs = inputs
-                .first_chunk::<S>()
-                .expect("Size of an input array should be equal to degree");
-            state(time, inputs)
+            guard(N::sentinel, || {
+                // SAFETY: state pointer is managed by only this struct, thus never be null
+                let state = unsafe { (state as *const F).as_ref() }.unwrap();
+                assert!(!inputs.is_null(), "Inputs is null");
+                let inputs = unsafe { slice::from_raw_parts(inputs, S) };
+                let inputs = inputs
+                    .first_chunk::<S>()
+                    .expect("Size of an input array should be equal to degree");
+                state(time, inputs)
+            })
         }
 
-        extern "C-unwind" fn call_destructor<F, T, N, const S: usize>(state: *mut c_void)
+        extern "C" fn call_destructor<F, T, N, const S: usize>(state: *mut c_void)
         where
             F: Fn(T, &[N; S]) -> N + 'static,
         {
@@ -63,7 +153,7 @@ impl<T, N> Function<T, N> {
                 return;
             }
             // SAFETY: state pointer is managed by only this struct, thus never be null
-            let _ = unsafe { Box::from_raw(state) };
+            guard(|| (), || drop(unsafe { Box::from_raw(state as *mut F) }))
         }
 
         Self {
@@ -73,14 +163,219 @@ impl<T, N> Function<T, N> {
         }
     }
 
+    /// Same as [`Self::new`], but degree of the function is known only at runtime
+    pub fn from_slice<F>(size: usize, f: F) -> Self
+    where
+        F: Fn(T, &[N]) -> N + 'static,
+        N: Sentinel,
+    {
+        #[inline]
+        extern "C" fn call_closure<F, T, N>(
+            state: *const c_void,
+            time: T,
+            inputs: *const N,
+        ) -> N
+        where
+            F: Fn(T, &[N]) -> N + 'static,
+            N: Sentinel,
+        {
+            guard(N::sentinel, || {
+                // SAFETY: state pointer is managed by only this struct, thus never be null
+                let (size, state) = unsafe { (state as *const (usize, F)).as_ref() }.unwrap();
+                assert!(!inputs.is_null(), "Inputs is null");
+                let inputs = unsafe { slice::from_raw_parts(inputs, *size) };
+                state(time, inputs)
+            })
+        }
+
+        extern "C" fn call_destructor<F, T, N>(state: *mut c_void)
+        where
+            F: Fn(T, &[N]) -> N + 'static,
+        {
+            // SAFETY: state pointer is managed by only this struct, thus never be null
+            guard(|| (), || drop(unsafe { Box::from_raw(state as *mut (usize, F)) }))
+        }
+
+        Self {
+            state_pointer: Box::into_raw(Box::new((size, f))) as *mut _,
+            fn_pointer: call_closure::<F, T, N>,
+            destructor: call_destructor::<F, T, N>,
+        }
+    }
+
+    /// Calls the function, panic inside of it is resumed here
     pub fn eval(&self, time: T, input: &[N]) -> N {
-        (self.fn_pointer)(self.state_pointer, time, input.as_ptr())
+        let result = (self.fn_pointer)(self.state_pointer, time, input.as_ptr());
+        if let Some(payload) = take_panic() {
+            resume_unwind(payload)
+        }
+        result
+    }
+
+    /// Adds layout of the function, which is mirrored by `Function` in `solvers/include/ffi.h`
+    pub(crate) fn describe(layout: &mut Layout) {
+        describe!(layout, "Function", Self {
+            state_pointer: state_pointer,
+            fn_pointer: fn_pointer,
+            destructor: destructor,
+        });
     }
 }
 
 impl<T, N> Drop for Function<T, N> {
     fn drop(&mut self) {
-        (self.destructor)(self.state_pointer)
+        destroy(self.destructor, self.state_pointer)
+    }
+}
+
+impl<T, N> VectorFunction<T, N> {
+    pub fn new<F, const S: usize>(f: F) -> Self
+    where
+        F: Fn(T, &[N; S], &mut [N; S]) + 'static,
+        N: Sentinel,
+    {
+        Self::from_slice(S, move |t, inputs, outputs| {
+            let inputs = inputs
+                .first_chunk::<S>()
+                .expect("Size of an input array should be equal to degree");
+            let outputs = outputs
+                .first_chunk_mut::<S>()
+                .expect("Size of an output array should be equal to degree");
+            f(t, inputs, outputs)
+        })
+    }
+
+    /// Same as [`Self::new`], but degree of the function is known only at runtime
+    pub fn from_slice<F>(size: usize, f: F) -> Self
+    where
+        F: Fn(T, &[N], &mut [N]) + 'static,
+        N: Sentinel,
+    {
+        extern "C" fn call_closure<F, T, N>(
+            state: *const c_void,
+            time: T,
+            inputs: *const N,
+            outputs: *mut N,
+        ) where
+            F: Fn(T, &[N], &mut [N]) + 'static,
+            N: Sentinel,
+        {
+            // SAFETY: state pointer is managed by only this struct, thus never be null
+            let (size, state) = unsafe { (state as *const (usize, F)).as_ref() }.unwrap();
+            guard(
+                || {
+                    if !outputs.is_null() {
+                        let outputs = unsafe { slice::from_raw_parts_mut(outputs, *size) };
+                        outputs.iter_mut().for_each(|it| *it = N::sentinel());
+                    }
+                },
+                || {
+                    assert!(!inputs.is_null(), "Inputs is null");
+                    assert!(!outputs.is_null(), "Outputs is null");
+                    let inputs = unsafe { slice::from_raw_parts(inputs, *size) };
+                    let outputs = unsafe { slice::from_raw_parts_mut(outputs, *size) };
+                    state(time, inputs, outputs)
+                },
+            )
+        }
+
+        extern "C" fn call_destructor<F, T, N>(state: *mut c_void)
+        where
+            F: Fn(T, &[N], &mut [N]) + 'static,
+        {
+            // SAFETY: state pointer is managed by only this struct, thus never be null
+            guard(|| (), || drop(unsafe { Box::from_raw(state as *mut (usize, F)) }))
+        }
+
+        Self {
+            state_pointer: Box::into_raw(Box::new((size, f))) as *mut _,
+            fn_pointer: call_closure::<F, T, N>,
+            destructor: call_destructor::<F, T, N>,
+        }
+    }
+
+    /// Calls the function, panic inside of it is resumed here
+    pub fn eval(&self, time: T, inputs: &[N], outputs: &mut [N]) {
+        assert_eq!(inputs.len(), outputs.len(), "Inputs and outputs should have the same size");
+        (self.fn_pointer)(self.state_pointer, time, inputs.as_ptr(), outputs.as_mut_ptr());
+        if let Some(payload) = take_panic() {
+            resume_unwind(payload)
+        }
+    }
+
+    /// Adds layout of the function, which is mirrored by `VectorFunction` in `solvers/include/ffi.h`
+    pub(crate) fn describe(layout: &mut Layout) {
+        describe!(layout, "VectorFunction", Self {
+            state_pointer: state_pointer,
+            fn_pointer: fn_pointer,
+            destructor: destructor,
+        });
+    }
+}
+
+impl<T, N> Drop for VectorFunction<T, N> {
+    fn drop(&mut self) {
+        destroy(self.destructor, self.state_pointer)
+    }
+}
+
+impl<T, N> Derivatives<T, N> {
+    /// Writes f_i(time, inputs) into `outputs[i]` for every i
+    pub fn eval(&self, time: T, inputs: &[N], outputs: &mut [N])
+    where
+        T: Copy,
+    {
+        match self {
+            Derivatives::Components(functions) => {
+                for (output, f) in outputs.iter_mut().zip(functions) {
+                    *output = f.eval(time, inputs);
+                }
+            }
+            Derivatives::System(f) => f.eval(time, inputs, outputs),
+        }
+    }
+
+    /// Adapter to a function per component.
+    /// For [`Derivatives::System`] every component evaluates the whole system.
+    pub fn into_components(self, size: usize) -> Box<[Function<T, N>]>
+    where
+        T: 'static,
+        N: Clone + Sentinel + 'static,
+    {
+        match self {
+            Derivatives::Components(functions) => functions,
+            Derivatives::System(f) => {
+                let f = Rc::new(f);
+                (0..size)
+                    .map(|i| {
+                        let f = f.clone();
+                        Function::from_slice(size, move |t, inputs| {
+                            let mut outputs = vec![N::sentinel(); size];
+                            f.eval(t, inputs, &mut outputs);
+                            outputs.swap_remove(i)
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Adapter to a single function for the whole system
+    pub fn into_system(self, size: usize) -> VectorFunction<T, N>
+    where
+        T: Copy + 'static,
+        N: Sentinel + 'static,
+    {
+        match self {
+            Derivatives::System(f) => f,
+            Derivatives::Components(functions) => {
+                VectorFunction::from_slice(size, move |t, inputs, outputs| {
+                    for (output, f) in outputs.iter_mut().zip(&functions) {
+                        *output = f.eval(t, inputs);
+                    }
+                })
+            }
+        }
     }
 }
 
@@ -93,16 +388,140 @@ impl<T, N> CauchyTask<T, N> {
     {
         Self {
             size: S,
-            derivatives: Box::new(derivatives),
+            derivatives: Derivatives::Components(Box::new(derivatives)),
             initial_conditions: Box::new(initial_conditions),
             initial_time,
         }
     }
+
+    /// Task which right-hand side is computed by a single function for the whole system
+    pub fn system<const S: usize>(
+        derivatives: VectorFunction<T, N>,
+        initial_time: T,
+        initial_conditions: [N; S],
+    ) -> Self {
+        Self {
+            size: S,
+            derivatives: Derivatives::System(derivatives),
+            initial_conditions: Box::new(initial_conditions),
+            initial_time,
+        }
+    }
+
+    /// Same as [`Self::new`], but size of the system is known only at runtime
+    pub fn from_parts(
+        derivatives: Vec<Function<T, N>>,
+        initial_time: T,
+        initial_conditions: Vec<N>,
+    ) -> Self {
+        assert_eq!(
+            derivatives.len(),
+            initial_conditions.len(),
+            "Every derivative should have an initial condition"
+        );
+        Self {
+            size: derivatives.len(),
+            derivatives: Derivatives::Components(derivatives.into_boxed_slice()),
+            initial_conditions: initial_conditions.into_boxed_slice(),
+            initial_time,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn initial_time(&self) -> &T {
+        &self.initial_time
+    }
+
+    pub fn initial_conditions(&self) -> &[N] {
+        &self.initial_conditions
+    }
+
+    pub fn derivatives(&self) -> &Derivatives<T, N> {
+        &self.derivatives
+    }
+
+    /// Same task with a function per component
+    pub fn into_components(self) -> Self
+    where
+        T: 'static,
+        N: Clone + Sentinel + 'static,
+    {
+        Self {
+            derivatives: Derivatives::Components(self.derivatives.into_components(self.size)),
+            ..self
+        }
+    }
+
+    /// Same task with a single function for the whole system
+    pub fn into_system(self) -> Self
+    where
+        T: Copy + 'static,
+        N: Sentinel + 'static,
+    {
+        Self {
+            derivatives: Derivatives::System(self.derivatives.into_system(self.size)),
+            ..self
+        }
+    }
 }
 
 pub fn f<T, N, const S: usize>(value: impl Fn(T, &[N; S]) -> N + 'static) -> Function<T, N>
 where
-    N: Copy,
+    N: Copy + Sentinel,
 {
     Function::new(value)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Panics with its message when dropped
+    struct Bomb(&'static str);
+
+    impl Drop for Bomb {
+        fn drop(&mut self) {
+            panic!("{}", self.0)
+        }
+    }
+
+    fn message(payload: Box<dyn Any + Send>) -> String {
+        panic_message(&*payload).to_string()
+    }
+
+    #[test]
+    fn drop_resumes_own_panic() {
+        let bomb = Bomb("destructor");
+        let function = f(move |_: f64, &[x]: &[f64; 1]| {
+            let _ = &bomb;
+            x
+        });
+        let payload = catch_unwind(AssertUnwindSafe(|| drop(function))).unwrap_err();
+        assert_eq!(message(payload), "destructor");
+        assert!(take_panic().is_none());
+    }
+
+    #[test]
+    fn drop_keeps_pending_panic() {
+        guard(|| (), || panic!("pending"));
+        drop(VectorFunction::new(|_: f64, _: &[f64; 1], _: &mut [f64; 1]| ()));
+        assert_eq!(take_panic().map(message).as_deref(), Some("pending"));
+    }
+
+    #[test]
+    fn drop_while_unwinding() {
+        let payload = catch_unwind(|| {
+            let bomb = Bomb("destructor");
+            let _function = VectorFunction::new(move |_: f64, _: &[f64; 1], _: &mut [f64; 1]| {
+                let _ = &bomb;
+            });
+            panic!("outer")
+        })
+        .unwrap_err();
+        assert_eq!(message(payload), "outer");
+        assert!(take_panic().is_none());
+    }
+}
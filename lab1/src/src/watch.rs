@@ -0,0 +1,86 @@
+use crate::plugin::libraries;
+use anyhow::{Context, Error};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// Modification time and size of every plugin library in some directories, which tells
+/// whether any of them was rebuilt
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Snapshot(BTreeMap<PathBuf, (SystemTime, u64)>);
+
+/// Copies of plugin libraries in a fresh temporary directory, which is removed on drop.
+///
+/// Loading copies lets the originals be rebuilt while they are in use, and since every copy
+/// has its own path, the dynamic loader never hands out a previously loaded library.
+pub struct Staging {
+    root: PathBuf,
+    dirs: Vec<PathBuf>,
+}
+
+impl Snapshot {
+    pub fn take(dirs: &[impl AsRef<Path>]) -> Self {
+        Self(
+            dirs.iter()
+                .flat_map(|dir| libraries(dir.as_ref()))
+                .filter_map(|path| {
+                    let metadata = fs::metadata(&path).ok()?;
+                    Some((path, (metadata.modified().ok()?, metadata.len())))
+                })
+                .collect(),
+        )
+    }
+
+    /// Libraries which were added, removed or modified since `previous`
+    pub fn changes<'a>(&'a self, previous: &'a Self) -> Vec<&'a Path> {
+        let modified = self
+            .0
+            .iter()
+            .filter(|(path, stamp)| previous.0.get(*path) != Some(stamp))
+            .map(|(path, _)| path.as_path());
+        let removed = previous
+            .0
+            .keys()
+            .filter(|it| !self.0.contains_key(*it))
+            .map(PathBuf::as_path);
+        modified.chain(removed).collect()
+    }
+}
+
+impl Staging {
+    /// Copies libraries of every directory in `dirs` into its own subdirectory, so that
+    /// [`Self::dirs`] can be scanned in the same order
+    pub fn new(dirs: &[impl AsRef<Path>]) -> Result<Self, Error> {
+        static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+        let generation = GENERATION.fetch_add(1, Ordering::Relaxed);
+        let root = env::temp_dir().join(format!("project-plugins-{}-{generation}", process::id()));
+        let mut result = Self { root, dirs: vec![] };
+        for (i, dir) in dirs.iter().enumerate() {
+            let staged = result.root.join(i.to_string());
+            fs::create_dir_all(&staged)
+                .with_context(|| format!("Could not create {}", staged.display()))?;
+            for path in libraries(dir.as_ref()) {
+                let copy = staged.join(path.file_name().unwrap());
+                fs::copy(&path, &copy)
+                    .with_context(|| format!("Could not copy {}", path.display()))?;
+            }
+            result.dirs.push(staged);
+        }
+        Ok(result)
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
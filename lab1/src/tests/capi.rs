@@ -0,0 +1,60 @@
+//! Checks the C API: the committed header matches the generated one, and `capi/test.c` compiled
+//! against the library built together with the tests passes.
+#![cfg(unix)]
+
+use project::capi::HEADER;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Directory of `libproject`, which cargo builds into `target/<profile>/deps` next to the tests
+fn library_dir() -> PathBuf {
+    env::current_exe().unwrap().parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn header_is_up_to_date() {
+    let committed = fs::read_to_string(manifest_dir().join("include/project.h")).unwrap();
+    assert!(
+        committed == HEADER,
+        "include/project.h is outdated, run `cargo run -- gen-header`"
+    );
+}
+
+#[test]
+fn c_test_passes() {
+    let dir = env::temp_dir().join(format!("project-capi-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("project.h"), HEADER).unwrap();
+
+    let library_dir = library_dir();
+    let executable = dir.join("capi-test");
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .args(["-std=c11", "-Wall", "-Wextra", "-Wpedantic", "-Werror"])
+        .arg(manifest_dir().join("capi/test.c"))
+        .arg("-I")
+        .arg(&dir)
+        .arg("-L")
+        .arg(&library_dir)
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .args(["-lproject", "-lm", "-o"])
+        .arg(&executable)
+        .status()
+        .unwrap_or_else(|e| panic!("Could not run `{compiler}`: {e}"));
+    assert!(status.success(), "capi/test.c does not compile");
+
+    let output = Command::new(&executable).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        output.status.success(),
+        "capi/test.c failed:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,208 @@
+//! Exercises `ExternalSolver` with the plugin from `examples/reference_plugin.rs`, which cargo
+//! builds together with the tests.
+
+use project::ffi::{self, CanSolve, ExternalSolver};
+use project::interval::Interval;
+use project::plugin::{Parameters, Plugin};
+use project::solution::{Solution, StopCondition, BATCH_STEPS};
+use project::solver::{EulerSolver, Solver};
+use project::task::{f, CauchyTask, VectorFunction};
+use std::env;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+const STOP: StopCondition<f64> = StopCondition::Timed { maximum: 1.0 };
+
+fn plugin() -> Plugin {
+    // Test binaries are placed into `target/<profile>/deps`, examples are next to that directory
+    let path = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|it| it.parent())
+        .unwrap()
+        .join("examples")
+        .join(format!("{DLL_PREFIX}reference_plugin{DLL_SUFFIX}"));
+    unsafe { Plugin::load(&path) }.unwrap_or_else(|e| panic!("{e:#}"))
+}
+
+fn parameters(mode: Option<&str>) -> Parameters {
+    let mut result = Parameters::default();
+    if let Some(mode) = mode {
+        result.options.insert("mode".to_string(), mode.to_string());
+    }
+    result
+}
+
+/// `x' = -x`, `x(0) = 1`
+fn decay() -> CauchyTask<f64, f64> {
+    CauchyTask::new([f(|_, [x]: &[f64; 1]| -x)], 0.0, [1.0])
+}
+
+fn solve<T, N>(plugin: &Plugin, task: &CauchyTask<T, N>, maximum: T) -> Result<Solution<T, N>, String>
+where
+    for<'a> ExternalSolver<'a, T, N>: CanSolve<T, N>,
+    T: PartialOrd + Clone,
+    N: Clone,
+{
+    let mut solver = ExternalSolver::build(plugin, &parameters(None)).map_err(|e| format!("{e:#}"))?;
+    Solution::compute(solver.as_mut(), task, StopCondition::Timed { maximum })
+        .map_err(|e| format!("{e:#}"))
+}
+
+fn error_of<T, N>(mode: &str, task: &CauchyTask<T, N>, maximum: T) -> String
+where
+    for<'a> ExternalSolver<'a, T, N>: CanSolve<T, N>,
+    T: PartialOrd + Clone,
+    N: Clone,
+{
+    let plugin = plugin();
+    let mut solver = ExternalSolver::build(&plugin, &parameters(Some(mode))).unwrap();
+    match Solution::compute(solver.as_mut(), task, StopCondition::Timed { maximum }) {
+        Ok(_) => panic!("Solver in `{mode}` mode should fail"),
+        Err(e) => format!("{e:#}"),
+    }
+}
+
+#[test]
+fn metadata_is_read() {
+    let plugin = plugin();
+    let metadata = plugin.metadata();
+    assert_eq!(metadata.name, "reference");
+    assert_eq!(metadata.order, 1);
+    for binding in ffi::bindings() {
+        assert!(metadata.supports(&binding.suffix), "{} should be supported", binding.suffix);
+    }
+    assert!(metadata.accepts("mode"));
+}
+
+#[test]
+fn matches_builtin_euler() {
+    let plugin = plugin();
+    let task = decay();
+    let external = solve(&plugin, &task, 1.0).unwrap();
+    let builtin = Solution::compute(EulerSolver::new(0.1).as_mut(), &task, STOP).unwrap();
+
+    assert_eq!(external.time(), builtin.time());
+    assert_eq!(&external[0], &builtin[0]);
+    assert!((external[0].last().unwrap() - 0.9f64.powi(10)).abs() < 1e-12);
+}
+
+#[test]
+fn system_matches_components() {
+    let plugin = plugin();
+    let system = CauchyTask::system(
+        VectorFunction::new(|_, [x, y]: &[f64; 2], [dx, dy]: &mut [f64; 2]| {
+            *dx = *y;
+            *dy = -x;
+        }),
+        0.0,
+        [1.0, 0.0],
+    );
+    let components = CauchyTask::new(
+        [f(|_, [_, y]: &[f64; 2]| *y), f(|_, [x, _]: &[f64; 2]| -x)],
+        0.0,
+        [1.0, 0.0],
+    );
+
+    let system = solve(&plugin, &system, 1.0).unwrap();
+    let components = solve(&plugin, &components, 1.0).unwrap();
+    assert_eq!(system.time(), components.time());
+    for i in 0..2 {
+        assert_eq!(&system[i], &components[i]);
+    }
+}
+
+#[test]
+fn every_suffix_is_bound() {
+    let plugin = plugin();
+
+    // Time of `f32` accumulates rounding errors, so maximum is not on the grid
+    let task = CauchyTask::new([f(|_, [x]: &[f32; 1]| -x)], 0.0f32, [1.0f32]);
+    let single = solve(&plugin, &task, 1.05).unwrap();
+    assert_eq!(single.time().len(), 11);
+    assert!((single[0].last().unwrap() - 0.9f32.powi(10)).abs() < 1e-5);
+
+    let task = CauchyTask::new([f(|_, [x]: &[f64; 1]| -x)], 0.0f32, [1.0]);
+    let mixed = solve(&plugin, &task, 1.05).unwrap();
+    // Step is rounded to `f32` as well
+    assert!((mixed[0].last().unwrap() - 0.9f64.powi(10)).abs() < 1e-6);
+    let task = CauchyTask::new([f(|_, [x]: &[f32; 1]| -x)], 0.0, [1.0f32]);
+    let mixed = solve(&plugin, &task, 1.0).unwrap();
+    assert!((mixed[0].last().unwrap() - 0.9f32.powi(10)).abs() < 1e-5);
+
+    macro_rules! interval {
+        ($t:ty, $n:ty) => {{
+            let task = CauchyTask::new(
+                [f(|_, [x]: &[Interval<$n>; 1]| -*x)],
+                0.0 as $t,
+                [Interval::<$n>::new(0.9, 1.1)],
+            );
+            let solution = solve(&plugin, &task, 1.05 as $t).unwrap();
+            assert_eq!(solution.time().len(), 11, "{}", stringify!(($t, $n)));
+            for x in &solution[0] {
+                assert!(x.start() <= x.end() && x.start().is_finite() && x.end().is_finite());
+            }
+        }};
+    }
+    interval!(f64, f64);
+    interval!(f32, f64);
+    interval!(f64, f32);
+    interval!(f32, f32);
+}
+
+#[test]
+fn failure_status_is_reported() {
+    let error = error_of("fail", &decay(), 1.0);
+    assert!(error.contains("failure was requested"), "{error}");
+}
+
+#[test]
+fn null_solution_is_reported() {
+    let plugin = plugin();
+    let task = decay();
+    let mut solver = ExternalSolver::build(&plugin, &parameters(Some("null"))).unwrap();
+    let solver = ExternalSolver::prepare(solver.as_mut(), &task).unwrap();
+    let error = format!("{:#}", solver.next_solution(&task).unwrap_err());
+    assert!(error.contains("returned no solution"), "{error}");
+}
+
+#[test]
+fn batch_size_mismatch_is_reported() {
+    let error = error_of("overflow", &decay(), 1.0);
+    assert!(error.contains(&format!("at most {BATCH_STEPS} were requested")), "{error}");
+}
+
+#[test]
+fn panic_in_task_is_reported() {
+    let plugin = plugin();
+    let task = CauchyTask::new(
+        [f(|t, [x]: &[f64; 1]| {
+            assert!(t < 0.5, "derivative is undefined after 0.5");
+            -x
+        })],
+        0.0,
+        [1.0],
+    );
+    let error = solve(&plugin, &task, 1.0).err().expect("Solver should fail");
+    assert!(error.contains("panicked"), "{error}");
+    assert!(error.contains("derivative is undefined after 0.5"), "{error}");
+}
+
+#[test]
+fn nan_is_reported() {
+    let plugin = plugin();
+    let task = CauchyTask::new([f(|_, [x]: &[f64; 1]| x.sqrt() - 2.0)], 0.0, [1.0]);
+    let error = solve(&plugin, &task, 1.0).err().expect("Solver should fail");
+    assert!(error.contains("is NaN"), "{error}");
+}
+
+#[test]
+fn unknown_option_is_rejected() {
+    let plugin = plugin();
+    let mut parameters = parameters(None);
+    parameters.options.insert("order".to_string(), "2".to_string());
+    let error = ExternalSolver::<f64, f64>::build(&plugin, &parameters)
+        .err()
+        .map(|e| format!("{e:#}"))
+        .unwrap();
+    assert!(error.contains("does not accept parameter `order`"), "{error}");
+}
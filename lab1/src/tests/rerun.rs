@@ -0,0 +1,104 @@
+//! Solves an experiment with the program and checks that `rerun` of its manifest writes the same
+//! files.
+
+use std::env;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const CONFIG: &str = r#"
+[general]
+solver = "builtin"
+t_max = 1
+
+[[experiment]]
+name = "decay"
+equations = ["x' = k1"]
+# Bounds of `c ± r` are rounded, so their midpoint is not the nominal
+parameters = { k1 = "0.1 ± 0.7" }
+initial = { x = 0 }
+step = 0.1
+outputs = { csv = "data/decay.csv" }
+"#;
+
+/// Same experiment solved by the plugin from `examples/reference_plugin.rs` in a relative directory
+const PLUGIN_CONFIG: &str = r#"
+[general]
+solver = "reference"
+lib_dir = "plugins"
+t_max = 1
+
+[[experiment]]
+name = "decay"
+equations = ["x' = k1"]
+parameters = { k1 = "0.1 ± 0.7" }
+initial = { x = 0 }
+step = 0.1
+outputs = { csv = "data/decay.csv" }
+"#;
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_project"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "`project {}` has failed:\n{}{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn rerun_is_identical() {
+    let dir = env::temp_dir().join(format!("project-rerun-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("config.toml"), CONFIG).unwrap();
+
+    run(&dir, &["solve"]);
+    let output = run(&dir, &["rerun", "out/decay.manifest.toml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("data/decay.csv: identical"), "{stdout}");
+    assert!(dir.join("out/rerun/data/decay.csv").is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rerun_from_another_directory() {
+    let dir = env::temp_dir().join(format!("project-rerun-plugin-{}", std::process::id()));
+    let elsewhere = dir.join("elsewhere");
+    fs::create_dir_all(dir.join("plugins")).unwrap();
+    fs::create_dir_all(&elsewhere).unwrap();
+    fs::write(dir.join("config.toml"), PLUGIN_CONFIG).unwrap();
+    // Test binaries are placed into `target/<profile>/deps`, examples are next to that directory
+    let name = format!("{DLL_PREFIX}reference_plugin{DLL_SUFFIX}");
+    let plugin = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|it| it.parent())
+        .unwrap()
+        .join("examples")
+        .join(&name);
+    fs::copy(plugin, dir.join("plugins").join(&name)).unwrap();
+
+    run(&dir, &["solve"]);
+    let manifest = fs::read_to_string(dir.join("out/decay.manifest.toml")).unwrap();
+    let library = fs::canonicalize(dir.join("plugins").join(&name)).unwrap();
+    assert!(
+        manifest.contains(&format!("library = {:?}", library.to_str().unwrap())),
+        "{manifest}"
+    );
+
+    let output = run(&elsewhere, &["rerun", "../out/decay.manifest.toml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("data/decay.csv: identical"), "{stdout}");
+    assert!(!stderr.contains("Warning"), "{stderr}");
+    assert!(dir.join("out/rerun/data/decay.csv").is_file());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,75 @@
+//! Compiles the Runge–Kutta plugin from `solvers/src/runge_kutta.cpp` and checks its accuracy on
+//! a system, where components depend on each other.
+#![cfg(unix)]
+
+use project::ffi::ExternalSolver;
+use project::plugin::{Parameters, Plugin};
+use project::solution::{Solution, StopCondition};
+use project::task::{f, CauchyTask};
+use std::env;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn compile(dir: &Path) -> Plugin {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library = dir.join(format!("{DLL_PREFIX}runge-kutta{DLL_SUFFIX}"));
+    let compiler = env::var("CXX").unwrap_or_else(|_| "c++".to_string());
+    let status = Command::new(&compiler)
+        .args([
+            "-std=c++20",
+            "-shared",
+            "-fPIC",
+            "-Wall",
+            "-Wextra",
+            "-Wpedantic",
+            "-Werror",
+            "-I",
+        ])
+        .arg(manifest_dir.join("solvers/include"))
+        .arg(manifest_dir.join("solvers/src/runge_kutta.cpp"))
+        .arg("-o")
+        .arg(&library)
+        .status()
+        .unwrap_or_else(|e| panic!("Could not run `{compiler}`: {e}"));
+    assert!(status.success(), "runge_kutta.cpp does not compile");
+    unsafe { Plugin::load(&library) }.unwrap_or_else(|e| panic!("{e:#}"))
+}
+
+/// Largest error of `x' = y`, `y' = -x` at `t = 1` with `step` against `(cos t, -sin t)`
+fn error(plugin: &Plugin, step: f64) -> f64 {
+    let task = CauchyTask::new(
+        [f(|_, [_, y]: &[f64; 2]| *y), f(|_, [x, _]: &[f64; 2]| -x)],
+        0.0,
+        [1.0, 0.0],
+    );
+    let parameters = Parameters {
+        step,
+        ..Default::default()
+    };
+    let mut solver = ExternalSolver::build(plugin, &parameters).unwrap();
+    // Slack keeps the point at `t = 1`, which is reached with a rounding error
+    let stop = StopCondition::Timed { maximum: 1.0 + 1e-9 };
+    let solution: Solution<f64, f64> = Solution::compute(solver.as_mut(), &task, stop).unwrap();
+    let t = *solution.time().last().unwrap();
+    assert!((t - 1.0).abs() < 1e-9, "solution ends at {t}");
+    let (x, y) = (*solution[0].last().unwrap(), *solution[1].last().unwrap());
+    (x - t.cos()).abs().max((y + t.sin()).abs())
+}
+
+/// Stages used to be written into the state, which later components of the same stage were
+/// evaluated at, so coupled systems were solved with the first order only
+#[test]
+fn coupled_system_has_fourth_order() {
+    let dir = env::temp_dir().join(format!("project-runge-kutta-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let plugin = compile(&dir);
+
+    let (coarse, fine) = (error(&plugin, 0.1), error(&plugin, 0.05));
+    assert!(coarse < 1e-6, "error is {coarse}");
+    let order = (coarse / fine).log2();
+    assert!((order - 4.0).abs() < 0.3, "order is {order}");
+    drop(plugin);
+    fs::remove_dir_all(&dir).unwrap();
+}
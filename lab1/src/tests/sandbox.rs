@@ -0,0 +1,116 @@
+//! Runs the plugin from `examples/reference_plugin.rs` in the helper process, which is the
+//! `project` binary built by cargo for the tests, and checks that its crash and hang become errors.
+
+use project::ffi::ExternalSolver;
+use project::plugin::{Parameters, Plugin};
+use project::sandbox::{Breakdown, ProcessError, SandboxSolver};
+use project::solution::{Solution, StopCondition};
+use project::task::{f, CauchyTask};
+use project::Frozen;
+use std::env;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::Path;
+use std::time::Duration;
+
+const STOP: StopCondition<f64> = StopCondition::Timed { maximum: 1.0 };
+
+fn plugin() -> Plugin {
+    // Test binaries are placed into `target/<profile>/deps`, examples are next to that directory
+    let path = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|it| it.parent())
+        .unwrap()
+        .join("examples")
+        .join(format!("{DLL_PREFIX}reference_plugin{DLL_SUFFIX}"));
+    unsafe { Plugin::load(&path) }.unwrap_or_else(|e| panic!("{e:#}"))
+}
+
+fn solver(
+    plugin: &Plugin,
+    mode: Option<&str>,
+    timeout: Duration,
+) -> Frozen<SandboxSolver<f64, f64>> {
+    let mut parameters = Parameters::default();
+    if let Some(mode) = mode {
+        parameters
+            .options
+            .insert("mode".to_string(), mode.to_string());
+    }
+    let program = Path::new(env!("CARGO_BIN_EXE_project"));
+    SandboxSolver::build_with_program(program, plugin, &parameters, timeout)
+        .unwrap_or_else(|e| panic!("{e:#}"))
+}
+
+/// `x' = -x`, `x(0) = 1`
+fn decay() -> CauchyTask<f64, f64> {
+    CauchyTask::new([f(|_, [x]: &[f64; 1]| -x)], 0.0, [1.0])
+}
+
+fn process_error(mode: &str, timeout: Duration) -> ProcessError {
+    let plugin = plugin();
+    let mut solver = solver(&plugin, Some(mode), timeout);
+    let error = match Solution::compute(solver.as_mut(), &decay(), STOP) {
+        Ok(_) => panic!("Solver in `{mode}` mode should fail"),
+        Err(e) => e,
+    };
+    match error.downcast::<ProcessError>() {
+        Ok(it) => it,
+        Err(e) => panic!("Unexpected error: {e:#}"),
+    }
+}
+
+#[test]
+fn solves_like_plugin_in_process() {
+    let plugin = plugin();
+    let task = decay();
+    let mut solver = solver(&plugin, None, Duration::from_secs(10));
+    let sandboxed =
+        Solution::compute(solver.as_mut(), &task, STOP).unwrap_or_else(|e| panic!("{e:#}"));
+    let mut solver = ExternalSolver::build(&plugin, &Parameters::default()).unwrap();
+    let direct = Solution::compute(solver.as_mut(), &task, STOP).unwrap();
+    assert_eq!(sandboxed.time(), direct.time());
+    assert_eq!(&sandboxed[0], &direct[0]);
+}
+
+#[test]
+fn crash_is_reported_with_stderr() {
+    let error = process_error("crash", Duration::from_secs(10));
+    assert_eq!(error.kind, Breakdown::Crashed);
+    assert!(
+        error.stderr.contains("reference plugin crashes on purpose"),
+        "{error}"
+    );
+    assert!(error.to_string().contains("its stderr:"), "{error}");
+}
+
+#[test]
+fn hang_is_reported_after_timeout() {
+    let error = process_error("hang", Duration::from_millis(500));
+    assert_eq!(error.kind, Breakdown::TimedOut);
+    assert!(
+        error.stderr.contains("reference plugin hangs on purpose"),
+        "{error}"
+    );
+    assert!(
+        error.to_string().contains("no answer within 500ms"),
+        "{error}"
+    );
+}
+
+#[test]
+fn process_restarts_after_crash() {
+    let plugin = plugin();
+    let task = decay();
+    let mut solver = solver(&plugin, Some("crash"), Duration::from_secs(10));
+    for _ in 0..2 {
+        // Preparation starts a new process, otherwise the error would not come from a process
+        let Err(error) = Solution::compute(solver.as_mut(), &task, STOP) else {
+            panic!("Solver in `crash` mode should fail");
+        };
+        assert_eq!(
+            error.downcast_ref::<ProcessError>().map(|it| it.kind),
+            Some(Breakdown::Crashed)
+        );
+    }
+}